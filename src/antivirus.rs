@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+use std::io::Error as IoError;
+use std::time::{Duration, SystemTime};
+
+use crate::error::io_error_code;
+use crate::summary::package_of;
+
+/// Fewer denied or locked files than this are left to the sim or an addon
+/// manager holding them.
+const MIN_BLOCKED_FILES: usize = 3;
+
+/// Files modified this recently are the ones a real-time scan is still busy
+/// with, or that Controlled Folder Access has not seen yet.
+const RECENT: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// ERROR_VIRUS_INFECTED and ERROR_VIRUS_DELETED: the antivirus said so.
+fn is_virus_error(err: &IoError) -> bool {
+    cfg!(target_os = "windows") && matches!(err.raw_os_error(), Some(225) | Some(226))
+}
+
+/// When the read errors look like an antivirus at work (access denied or
+/// sharing violations, mostly in recently modified files), say so and list
+/// the affected files by package. `modified` gives the modification time of
+/// a failed file.
+pub fn report_interference(
+    read_errors: &[(String, IoError)],
+    modified: impl Fn(&str) -> Option<SystemTime>,
+) {
+    let blocked: Vec<&(String, IoError)> = read_errors
+        .iter()
+        .filter(|(_, err)| {
+            is_virus_error(err) || matches!(io_error_code(err), "E_READ_DENIED" | "E_READ_LOCKED")
+        })
+        .collect();
+    let now = SystemTime::now();
+    let recent = blocked
+        .iter()
+        .filter(|(path, _)| {
+            modified(path)
+                .and_then(|time| now.duration_since(time).ok())
+                .is_some_and(|age| age < RECENT)
+        })
+        .count();
+    let flagged = blocked.iter().any(|(_, err)| is_virus_error(err));
+    if !flagged && (blocked.len() < MIN_BLOCKED_FILES || recent * 2 <= blocked.len()) {
+        return;
+    }
+    let mut packages: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for (path, _) in &blocked {
+        packages.entry(package_of(path)).or_default().push(path);
+    }
+    eprintln!(
+        "\nHint: {} file(s) were denied or locked, {} of them modified in the last 7 days.\n\
+         An antivirus real-time scan or Windows Controlled Folder Access is the usual cause:\n\
+         exclude the packages folder from the scan (or allow this tool), then run again.\n\
+         See `msfs2020checksum explain antivirus`.",
+        blocked.len(),
+        recent
+    );
+    for (package, paths) in &packages {
+        eprintln!("  {} ({} file(s))", package, paths.len());
+        for path in paths {
+            eprintln!("    {}", path);
+        }
+    }
+}
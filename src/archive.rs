@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::{BufReader, Error as IoError, Read, Result as IoResult};
+use std::path::{Path, MAIN_SEPARATOR_STR};
+
+use zip::ZipArchive;
+
+use crate::longpath;
+
+/// Separates the path of an archive from the path of a file inside it:
+/// `Addon.zip!addon\layout.json`.
+const MEMBER_SEPARATOR: &str = "!";
+
+/// Whether `file` is opened by `--archives`.
+pub fn is_archive(file: &Path) -> bool {
+    file.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// The path of `member` of the archive at `archive_path`, with the path
+/// separators of this system as for the files on disk.
+pub fn member_path(archive_path: &str, member: &str) -> String {
+    format!(
+        "{}{}{}",
+        archive_path,
+        MEMBER_SEPARATOR,
+        member.replace('/', MAIN_SEPARATOR_STR)
+    )
+}
+
+/// Whether `path_string` is a file inside an archive rather than on disk.
+pub fn is_member(path_string: &str) -> bool {
+    path_string
+        .to_ascii_lowercase()
+        .contains(&format!(".zip{}", MEMBER_SEPARATOR))
+}
+
+/// Call `read` with the name and the content of every file of the zip
+/// archive `archive`, in archive order. The directories are left out.
+pub fn for_each_member(
+    archive: &Path,
+    mut read: impl FnMut(&str, u64, &mut dyn Read) -> IoResult<()>,
+) -> IoResult<()> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(longpath::extended(archive))?))
+        .map_err(IoError::from)?;
+    for index in 0..zip.len() {
+        let mut member = zip.by_index(index).map_err(IoError::from)?;
+        if member.is_dir() {
+            continue;
+        }
+        let name = member.name().to_string();
+        let size = member.size();
+        read(&name, size, &mut member)?;
+    }
+    Ok(())
+}
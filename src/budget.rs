@@ -0,0 +1,74 @@
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::locale;
+
+/// `30m`, `2h`, `1h30m`, `90s`, `90` (seconds) or `30d` -> a duration, for
+/// `--time-budget` and `--max-staleness`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("`{}` is not a duration such as `30m` or `1h30m`", value);
+    let mut secs = 0;
+    let mut number = String::new();
+    for c in value.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 24 * 3600,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        secs += number.parse::<u64>().map_err(|_| invalid())? * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        secs += number.parse::<u64>().map_err(|_| invalid())?;
+    }
+    if secs == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// What a run cut short by `--time-budget` hashed, out of all the files it
+/// found.
+pub struct Coverage {
+    pub files: usize,
+    pub bytes: u64,
+    pub total_files: usize,
+    pub total_bytes: u64,
+}
+
+impl Coverage {
+    fn percent(&self) -> f64 {
+        if self.total_bytes > 0 {
+            self.bytes as f64 * 100.0 / self.total_bytes as f64
+        } else {
+            100.0
+        }
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "\nTime budget reached: hashed {} of {} files, {} of {} bytes ({}%).\n\
+             With a `--cache` file, the next run reuses these hashes and goes on with the other files.",
+            locale::number(self.files as u64),
+            locale::number(self.total_files as u64),
+            locale::number(self.bytes),
+            locale::number(self.total_bytes),
+            locale::decimal(self.percent(), 1)
+        );
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "files": self.files,
+            "bytes": self.bytes,
+            "total_files": self.total_files,
+            "total_bytes": self.total_bytes,
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs::File;
+use std::io::{BufWriter, Error as IoError, Result as IoResult, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::hasher::ExtraHashes;
+use crate::intern;
+use crate::manifest::Manifest;
+use crate::redact::Redactor;
+use crate::summary;
+use crate::FileRecord;
+
+/// Header lines of a result file that hold paths or per-file data, left
+/// out of the bundle.
+const PRIVATE_HEADERS: [&str; 4] = ["directory", "chunk", "root", "error"];
+
+/// Write `(file name, content)` entries into a new zip file.
+pub fn write_bundle(bundle: &Path, entries: &[(&str, String)]) -> IoResult<()> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(bundle)?));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in entries {
+        zip.start_file(*name, options).map_err(IoError::from)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.finish().map_err(IoError::from)?.flush()
+}
+
+/// The header of a result file and its per-package totals, without a single
+/// file path. The Community package names are replaced by their aliases.
+pub fn summary_text(manifest: &Manifest, redactor: &Redactor) -> String {
+    let mut text = String::new();
+    for (key, value) in &manifest.header {
+        if !PRIVATE_HEADERS.contains(&key.as_str()) {
+            text.push_str(&format!("# {}: {}\n", key, value));
+        }
+    }
+    let mut records: Vec<FileRecord> = manifest
+        .entries
+        .iter()
+        .map(|(path, (hash, filesize))| {
+            (
+                intern::path(&redactor.redact(&path.to_string())),
+                hash.clone(),
+                filesize.unwrap_or(0),
+                0,
+                ExtraHashes::default(),
+            )
+        })
+        .collect();
+    records.sort_unstable();
+    if let Some(algorithm) = manifest.algorithm {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&summary::format_summary(&summary::summarize(
+            &records, algorithm,
+        )));
+    }
+    text
+}
@@ -0,0 +1,419 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, Metadata};
+use std::io::{BufRead, BufReader, BufWriter, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::fileid;
+use crate::hasher::{Algorithm, Digest};
+use crate::intern::{self, RecordPath};
+use crate::FileRecord;
+
+/// v2 adds when each file was hashed, v3 the file IDs; the older caches are
+/// still read.
+const CACHE_HEADER: &str = "# msfs2020checksum cache v3";
+const CACHE_HEADER_V2: &str = "# msfs2020checksum cache v2";
+const CACHE_HEADER_V1: &str = "# msfs2020checksum cache v1";
+const ROOT_PREFIX: &str = "# root: ";
+const ALGORITHM_PREFIX: &str = "# algorithm: ";
+const ROOT_ID_PREFIX: &str = "# root-id: ";
+
+/// The volume serial number and the file index of a file (see
+/// `fileid::file_id`), which a rename or a move within the volume keeps.
+type FileId = (u64, u64);
+
+/// `(size, mtime, hash, hashed, file ID)` of a cached file. `hashed` is when
+/// its content was last read, in seconds since the Unix epoch (0 if
+/// unknown).
+type CacheEntry = (u64, u64, Digest, u64, Option<FileId>);
+
+/// Hashes from a previous run, keyed by the emitted (relative) path, and
+/// found by file ID as well once the file was renamed or moved.
+pub struct HashCache {
+    entries: HashMap<RecordPath, CacheEntry>,
+    /// File ID -> path of the entries that have one.
+    ids: HashMap<FileId, RecordPath>,
+    /// With `--max-staleness`, the hashes of files read before this are not
+    /// reused.
+    stale_before: Option<u64>,
+}
+
+impl HashCache {
+    pub fn empty() -> Self {
+        HashCache {
+            entries: HashMap::new(),
+            ids: HashMap::new(),
+            stale_before: None,
+        }
+    }
+
+    /// Read all the entries of a cache file, whatever its packages directory
+    /// and algorithm.
+    pub fn read(cache_file: &Path) -> IoResult<Self> {
+        let mut cache = Self::empty();
+        let mut lines = BufReader::new(File::open(cache_file)?).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let version = match header.trim_end() {
+            CACHE_HEADER => 3,
+            CACHE_HEADER_V2 => 2,
+            CACHE_HEADER_V1 => 1,
+            _ => return Ok(cache),
+        };
+        for line in lines.skip(if version >= 3 { 3 } else { 2 }) {
+            cache.insert_line(&line?, version);
+        }
+        Ok(cache)
+    }
+
+    /// Load the cache file written by a previous run.
+    ///
+    /// A missing file yields an empty cache. A cache written for another
+    /// packages directory is ignored, because its relative paths would point
+    /// to different files, and so is a cache of another algorithm. The same
+    /// directory under another path (a new drive letter) is recognized by
+    /// its file ID.
+    pub fn load(cache_file: &Path, root: &Path, algorithm: Algorithm) -> IoResult<Self> {
+        let mut cache = Self::empty();
+        if !cache_file.exists() {
+            return Ok(cache);
+        }
+        let fhr = File::open(cache_file)?;
+        let mut lines = BufReader::new(fhr).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let version = match header.trim_end() {
+            CACHE_HEADER => 3,
+            CACHE_HEADER_V2 => 2,
+            CACHE_HEADER_V1 => 1,
+            _ => 0,
+        };
+        if version == 0 {
+            eprintln!(
+                "Warning: ignoring unrecognized cache file {:?}\n",
+                cache_file
+            );
+            return Ok(cache);
+        }
+        let root_line = lines.next().transpose()?.unwrap_or_default();
+        let algorithm_line = lines.next().transpose()?.unwrap_or_default();
+        let root_id = if version >= 3 {
+            let root_id_line = lines.next().transpose()?.unwrap_or_default();
+            root_id_line
+                .trim_end()
+                .strip_prefix(ROOT_ID_PREFIX)
+                .and_then(parse_file_id)
+        } else {
+            None
+        };
+        let cached_root = root_line.trim_end().strip_prefix(ROOT_PREFIX);
+        if cached_root != root.to_str() {
+            if root_id.is_none() || root_id != fileid::file_id(root) {
+                eprintln!(
+                    "Warning: cache file {:?} was written for another packages directory, ignoring it.\n",
+                    cache_file
+                );
+                return Ok(cache);
+            }
+            eprintln!(
+                "Note: cache file {:?} was written for {:?}, the same directory under another path.\n",
+                cache_file,
+                cached_root.unwrap_or_default()
+            );
+        }
+        if algorithm_line.trim_end().strip_prefix(ALGORITHM_PREFIX) != Some(algorithm.name()) {
+            eprintln!(
+                "Warning: cache file {:?} was written with another hash algorithm, ignoring it.\n",
+                cache_file
+            );
+            return Ok(cache);
+        }
+        for line in lines {
+            cache.insert_line(&line?, version);
+        }
+        Ok(cache)
+    }
+
+    /// `hash<TAB>size<TAB>mtime<TAB>hashed<TAB>file ID<TAB>path`, without
+    /// `hashed` in v1 and without the file ID before v3.
+    fn insert_line(&mut self, line: &str, version: u32) {
+        let mut fields = line
+            .trim_end_matches(['\r', '\n'])
+            .splitn(version as usize + 3, '\t');
+        let parsed = (|| {
+            let hash = Digest::from_hex(fields.next()?)?;
+            let size = fields.next()?.trim().parse().ok()?;
+            let mtime = fields.next()?.trim().parse().ok()?;
+            let hashed = if version == 1 {
+                0
+            } else {
+                fields.next()?.trim().parse().ok()?
+            };
+            let id = if version >= 3 {
+                parse_file_id(fields.next()?)
+            } else {
+                None
+            };
+            let path = intern::path(fields.next()?);
+            Some((path, (size, mtime, hash, hashed, id)))
+        })();
+        if let Some((path, entry)) = parsed {
+            self.insert(path, entry);
+        }
+    }
+
+    fn insert(&mut self, path: RecordPath, entry: CacheEntry) {
+        if let Some(id) = entry.4 {
+            self.ids.insert(id, path.clone());
+        }
+        self.entries.insert(path, entry);
+    }
+
+    /// Do not reuse the hashes read longer than `max_staleness` ago.
+    pub fn set_max_staleness(&mut self, max_staleness: Duration) {
+        self.stale_before =
+            Some(unix_secs(SystemTime::now()).saturating_sub(max_staleness.as_secs()));
+    }
+
+    /// Whether the file was hashed by a previous run, changed since or not.
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(&intern::path(path))
+    }
+
+    /// Return the cached hash if the file still has the recorded size and
+    /// mtime, and was not read too long ago.
+    pub fn lookup(&self, path: &str, size: u64, mtime: u64) -> Option<Digest> {
+        self.fresh(self.entries.get(&intern::path(path))?, size, mtime)
+    }
+
+    /// `lookup` for a file not cached under its path: the entry of the same
+    /// file (by file ID) under its old path, when it was renamed or moved
+    /// since. Only looks the ID of `file` up when the cache has IDs.
+    pub fn lookup_moved(&self, file: &Path, size: u64, mtime: u64) -> Option<Digest> {
+        if self.ids.is_empty() {
+            return None;
+        }
+        let old_path = self.ids.get(&fileid::file_id(file)?)?;
+        self.fresh(self.entries.get(old_path)?, size, mtime)
+    }
+
+    fn fresh(&self, entry: &CacheEntry, size: u64, mtime: u64) -> Option<Digest> {
+        let (c_size, c_mtime, hash, hashed, _) = entry;
+        (*c_size == size
+            && *c_mtime == mtime
+            && self.stale_before.is_none_or(|before| *hashed >= before))
+        .then(|| hash.clone())
+    }
+
+    /// `(hashed, path)` of every entry, the least recently read first.
+    pub fn by_staleness(&self) -> Vec<(u64, &RecordPath)> {
+        let mut entries: Vec<(u64, &RecordPath)> = self
+            .entries
+            .iter()
+            .map(|(path, entry)| (entry.3, path))
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Drop the entries whose hash differs from the `expected` one (those of
+    /// a reference), so that these files are read again whatever their
+    /// mtime. Returns the number of entries dropped.
+    pub fn retain_matching(
+        &mut self,
+        expected: &HashMap<RecordPath, (Digest, Option<u64>)>,
+    ) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|path, (size, _, hash, _, _)| {
+            expected
+                .get(path)
+                .is_none_or(|(expected_hash, expected_size)| {
+                    expected_hash == hash
+                        && expected_size.is_none_or(|expected_size| expected_size == *size)
+                })
+        });
+        before - self.entries.len()
+    }
+
+    /// Add the entries of `other`, which win over the existing ones.
+    pub fn extend(&mut self, other: HashCache) {
+        for (path, entry) in other.entries {
+            self.insert(path, entry);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Write the results of this run as the cache for the next one. The files
+/// whose hash was reused from `previous` keep the time they were read, and
+/// their file ID; `source_file` gives the file on disk of the others, whose
+/// ID is looked up.
+///
+/// The entries of `previous` that this run did not hash (left out by
+/// `--include`, `--stdin`, `--changed-since`...) are kept as they are while
+/// their file still exists, so that a partial run does not empty the cache
+/// of the next full one.
+pub fn save_cache(
+    cache_file: &Path,
+    root: &Path,
+    algorithm: Algorithm,
+    results: &[FileRecord],
+    previous: &HashCache,
+    source_file: impl Fn(&str) -> Option<PathBuf>,
+) -> IoResult<()> {
+    let now = unix_secs(SystemTime::now());
+    let fhw = File::create(cache_file)?;
+    let mut writer = BufWriter::new(fhw);
+    writeln!(writer, "{}", CACHE_HEADER)?;
+    writeln!(writer, "{}{}", ROOT_PREFIX, root.to_string_lossy())?;
+    writeln!(writer, "{}{}", ALGORITHM_PREFIX, algorithm.name())?;
+    writeln!(
+        writer,
+        "{}{}",
+        ROOT_ID_PREFIX,
+        format_file_id(fileid::file_id(root))
+    )?;
+    let mut hashed_paths = HashSet::with_capacity(results.len());
+    for (path, hash, filesize, mtime, _) in results {
+        hashed_paths.insert(path);
+        let (hashed, id) = match previous.entries.get(path) {
+            Some(entry) if previous.fresh(entry, *filesize, *mtime).as_ref() == Some(hash) => {
+                (entry.3, entry.4)
+            }
+            _ => (now, None),
+        };
+        let id = id.or_else(|| fileid::file_id(&source_file(&path.to_string())?));
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            hash,
+            filesize,
+            mtime,
+            hashed,
+            format_file_id(id),
+            path
+        )?;
+    }
+    let mut kept: Vec<(&RecordPath, &CacheEntry)> = previous
+        .entries
+        .iter()
+        .filter(|(path, _)| {
+            !hashed_paths.contains(path)
+                && source_file(&path.to_string()).is_some_and(|file| file.is_file())
+        })
+        .collect();
+    kept.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    for (path, (filesize, mtime, hash, hashed, id)) in kept {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            hash,
+            filesize,
+            mtime,
+            hashed,
+            format_file_id(*id),
+            path
+        )?;
+    }
+    writer.flush()
+}
+
+/// `volume:index`, or `-` when unknown.
+fn format_file_id(id: Option<FileId>) -> String {
+    match id {
+        Some((volume, index)) => format!("{:x}:{:x}", volume, index),
+        None => String::from("-"),
+    }
+}
+
+fn parse_file_id(text: &str) -> Option<FileId> {
+    let (volume, index) = text.trim().split_once(':')?;
+    Some((
+        u64::from_str_radix(volume, 16).ok()?,
+        u64::from_str_radix(index, 16).ok()?,
+    ))
+}
+
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Modification time in nanoseconds since the Unix epoch (0 if unavailable).
+pub fn modified_nanos(meta: &Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "f4d0497cf9394caab34d1a3ab2bece70";
+
+    fn entry(cache: &HashCache, path: &str) -> CacheEntry {
+        cache.entries[&intern::path(path)].clone()
+    }
+
+    #[test]
+    fn v1_lines() {
+        let mut cache = HashCache::empty();
+        cache.insert_line(&format!("{}\t3\t1700\ta\tb\r\n", HASH), 1);
+        assert_eq!(
+            entry(&cache, "a\tb"),
+            (3, 1700, Digest::from_hex(HASH).unwrap(), 0, None)
+        );
+    }
+
+    #[test]
+    fn v2_lines() {
+        let mut cache = HashCache::empty();
+        cache.insert_line(&format!("{}\t3\t1700\t1800\ta", HASH), 2);
+        assert_eq!(
+            entry(&cache, "a"),
+            (3, 1700, Digest::from_hex(HASH).unwrap(), 1800, None)
+        );
+        assert!(cache.ids.is_empty());
+    }
+
+    #[test]
+    fn v3_lines() {
+        let mut cache = HashCache::empty();
+        cache.insert_line(&format!("{}\t3\t1700\t1800\tab:1f\ta", HASH), 3);
+        cache.insert_line(&format!("{}\t4\t1700\t1800\t-\tb", HASH), 3);
+        assert_eq!(
+            entry(&cache, "a"),
+            (
+                3,
+                1700,
+                Digest::from_hex(HASH).unwrap(),
+                1800,
+                Some((0xab, 0x1f))
+            )
+        );
+        assert_eq!(entry(&cache, "b").4, None);
+        assert_eq!(cache.ids.len(), 1);
+        assert_eq!(cache.ids[&(0xab, 0x1f)].to_string(), "a");
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let mut cache = HashCache::empty();
+        // A v2 line read as v3, a bad size, a bad hash.
+        cache.insert_line(&format!("{}\t3\t1700\t1800\ta", HASH), 3);
+        cache.insert_line(&format!("{}\tthree\t1700\ta", HASH), 1);
+        cache.insert_line("xyz\t3\t1700\ta", 1);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn lookup_needs_the_same_size_and_mtime() {
+        let mut cache = HashCache::empty();
+        cache.insert_line(&format!("{}\t3\t1700\ta", HASH), 1);
+        assert_eq!(cache.lookup("a", 3, 1700), Digest::from_hex(HASH));
+        assert_eq!(cache.lookup("a", 4, 1700), None);
+        assert_eq!(cache.lookup("a", 3, 1701), None);
+    }
+}
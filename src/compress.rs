@@ -0,0 +1,106 @@
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, IntoInnerError, Result as IoResult, Write};
+use std::path::Path;
+
+/// `--compress` of the result file. A result file of a whole install is
+/// tens of MiB of text that compresses about tenfold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// gzip (`.gz`), opened by every archiver
+    Gzip,
+    /// Zstandard (`.zst`), faster and smaller
+    Zstd,
+}
+
+impl Compression {
+    /// `compress`, or else the compression named by the extension of
+    /// `outpath` (`.gz`, `.zst`).
+    pub fn of_output(compress: Option<Self>, outpath: &Path) -> Option<Self> {
+        compress.or_else(
+            || match outpath.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+                "gz" => Some(Compression::Gzip),
+                "zst" => Some(Compression::Zstd),
+                _ => None,
+            },
+        )
+    }
+}
+
+/// An output file, compressed or not. `finish` writes the end of the
+/// compressed stream: without it, the file is truncated.
+pub enum OutputFile {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl OutputFile {
+    /// Compress what is written to `file`, created or opened for appending.
+    /// Appended streams are read back as one, gzip and zstd alike.
+    pub fn new(file: File, compression: Option<Compression>) -> IoResult<Self> {
+        Ok(match compression {
+            None => OutputFile::Plain(file),
+            Some(Compression::Gzip) => {
+                OutputFile::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => OutputFile::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    pub fn create(outpath: &Path, compression: Option<Compression>) -> IoResult<Self> {
+        Self::new(File::create(outpath)?, compression)
+    }
+
+    pub fn finish(self) -> IoResult<()> {
+        match self {
+            OutputFile::Plain(mut file) => file.flush(),
+            OutputFile::Gzip(encoder) => encoder.finish().map(drop),
+            OutputFile::Zstd(encoder) => encoder.finish().map(drop),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(buf),
+            OutputFile::Gzip(encoder) => encoder.write(buf),
+            OutputFile::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Gzip(encoder) => encoder.flush(),
+            OutputFile::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Flush `writer`, then end the compressed stream of its file.
+pub fn finish(writer: BufWriter<OutputFile>) -> IoResult<()> {
+    writer
+        .into_inner()
+        .map_err(IntoInnerError::into_error)?
+        .finish()
+}
+
+/// Open a result file to read it, decompressed when it starts as a gzip or
+/// zstd stream does, whatever its name.
+pub fn open(file: &Path) -> IoResult<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(file)?);
+    let start = reader.fill_buf()?;
+    if start.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else if start.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(
+            reader,
+        )?)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
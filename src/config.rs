@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Prefix of the emitted paths of the sim config files, which live outside
+/// the packages directory. `#` cannot be used, it starts a header line.
+pub const CONFIG_PREFIX: &str = "@config";
+
+/// Extensions of the settings files next to `UserCfg.opt`
+/// (`UserCfg.opt`, `Content.xml`, `FlightSimulator.CFG`, ...).
+const CONFIG_EXTENSIONS: [&str; 3] = ["opt", "xml", "cfg"];
+
+/// List the sim config files for `--include-config`, as
+/// `(emitted path, real path)` sorted by emitted path.
+///
+/// These are the settings files in the directory of `UserCfg.opt` and, for
+/// the MS Store version, the locally stored copies of the cloud-synced
+/// settings (controls profiles included) in `SystemAppData\wgs`.
+pub fn config_files(usercfg: &Path) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    let config_dir = match usercfg.parent() {
+        Some(dir) => dir,
+        None => return files,
+    };
+    for entry in WalkDir::new(config_dir)
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|res| res.ok())
+    {
+        let is_config = entry.file_type().is_file()
+            && entry.path().extension().is_some_and(|ext| {
+                CONFIG_EXTENSIONS
+                    .iter()
+                    .any(|config_ext| ext.eq_ignore_ascii_case(config_ext))
+            });
+        if is_config {
+            files.push(emitted(Path::new(entry.file_name()), entry.path()));
+        }
+    }
+    // Packages\Microsoft.FlightSimulator_8wekyb3d8bbwe\LocalCache\UserCfg.opt
+    let wgs_dir = config_dir.with_file_name("SystemAppData").join("wgs");
+    for entry in WalkDir::new(&wgs_dir)
+        .into_iter()
+        .filter_map(|res| res.ok())
+        .filter(|entry| entry.file_type().is_file())
+    {
+        if let Ok(r_path) = entry.path().strip_prefix(&wgs_dir) {
+            files.push(emitted(&Path::new("wgs").join(r_path), entry.path()));
+        }
+    }
+    files.sort_unstable();
+    files
+}
+
+/// The real path of an emitted `@config` path, the reverse of `config_files`.
+pub fn config_source(usercfg: &Path, path_string: &str) -> Option<PathBuf> {
+    let r_path = Path::new(path_string).strip_prefix(CONFIG_PREFIX).ok()?;
+    let config_dir = usercfg.parent()?;
+    match r_path.strip_prefix("wgs") {
+        Ok(wgs_path) => Some(
+            config_dir
+                .with_file_name("SystemAppData")
+                .join("wgs")
+                .join(wgs_path),
+        ),
+        Err(_) => Some(config_dir.join(r_path)),
+    }
+}
+
+fn emitted(relative_path: &Path, path: &Path) -> (String, PathBuf) {
+    (
+        Path::new(CONFIG_PREFIX)
+            .join(relative_path)
+            .to_string_lossy()
+            .to_string(),
+        path.to_path_buf(),
+    )
+}
@@ -0,0 +1,223 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use dirs::{data_dir, data_local_dir};
+use walkdir::WalkDir;
+
+use crate::fileid;
+use crate::steam;
+
+pub const STORE_MSFS_DIR_NAME: &str = "Microsoft.FlightSimulator_8wekyb3d8bbwe";
+pub const STEAM_MSFS_DIR_NAME: &str = "Microsoft Flight Simulator";
+
+/// Environment variable of the `env` strategy.
+pub const USERCFG_VAR: &str = "MSFS_USERCFG";
+
+/// A way to find `UserCfg.opt`. They are tried in this order, the cheap and
+/// explicit ones first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Strategy {
+    /// The path in the `MSFS_USERCFG` environment variable
+    Env,
+    /// The path in `msfs2020checksum\usercfg-path.txt` of the local app
+    /// data, written by hand or by `search`
+    ConfigFile,
+    /// The MS Store package folder in the local app data
+    Store,
+    /// Other packages of the sim installed by the Xbox app (e.g. a beta)
+    XboxApp,
+    /// The Steam folder in the roaming app data
+    Steam,
+    /// The Steam libraries listed in `libraryfolders.vdf`, and the Proton
+    /// prefix on Linux
+    SteamVdf,
+    /// The Steam installation recorded in the registry (Windows only)
+    Registry,
+    /// Every `UserCfg.opt` under the roaming app data (slow)
+    Search,
+}
+
+impl Strategy {
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// The strategies tried, in order, with the `UserCfg.opt` found or why each
+/// failed. Only the last one may have found it.
+pub type Trail = Vec<(Strategy, Result<PathBuf, String>)>;
+
+/// Find `UserCfg.opt` with the strategies not `skipped`, stopping at the
+/// first that finds it.
+pub fn find_usercfg(skipped: &[Strategy]) -> (Option<PathBuf>, Trail) {
+    let mut trail = Trail::new();
+    for strategy in Strategy::value_variants() {
+        if skipped.contains(strategy) {
+            continue;
+        }
+        let result = match strategy {
+            Strategy::Env => from_env(),
+            Strategy::ConfigFile => from_config_file(),
+            Strategy::Store => from_store(),
+            Strategy::XboxApp => from_xbox_app(),
+            Strategy::Steam => from_steam(),
+            Strategy::SteamVdf => steam::find_steam_usercfg()
+                .ok_or_else(|| String::from("no Steam library has the sim installed")),
+            Strategy::Registry => from_registry(),
+            Strategy::Search => from_search(),
+        };
+        let found = result.as_ref().ok().cloned();
+        trail.push((*strategy, result));
+        if found.is_some() {
+            return (found, trail);
+        }
+    }
+    (None, trail)
+}
+
+/// One line per strategy tried.
+pub fn print_trail(trail: &Trail) {
+    for (strategy, result) in trail {
+        match result {
+            Ok(usercfg) => eprintln!("  {}: found {:?}", strategy.name(), usercfg),
+            Err(reason) => eprintln!("  {}: {}", strategy.name(), reason),
+        }
+    }
+}
+
+/// `path`, if it is a file.
+fn existing(path: PathBuf) -> Result<PathBuf, String> {
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(format!("{:?} does not exist", path))
+    }
+}
+
+fn from_env() -> Result<PathBuf, String> {
+    match env::var_os(USERCFG_VAR) {
+        Some(path) if !path.is_empty() => existing(PathBuf::from(path)),
+        _ => Err(format!("`{}` is not set", USERCFG_VAR)),
+    }
+}
+
+/// `msfs2020checksum\usercfg-path.txt` in the local app data.
+fn config_file() -> Option<PathBuf> {
+    Some(
+        data_local_dir()?
+            .join("msfs2020checksum")
+            .join("usercfg-path.txt"),
+    )
+}
+
+fn from_config_file() -> Result<PathBuf, String> {
+    let file = config_file().ok_or_else(|| String::from("no local app data directory"))?;
+    match fs::read_to_string(&file) {
+        Ok(text) => existing(PathBuf::from(text.trim())),
+        Err(_) => Err(format!("{:?} does not exist", file)),
+    }
+}
+
+fn from_store() -> Result<PathBuf, String> {
+    let packages = data_local_dir()
+        .ok_or_else(|| String::from("no local app data directory"))?
+        .join("Packages");
+    existing(
+        packages
+            .join(STORE_MSFS_DIR_NAME)
+            .join("LocalCache")
+            .join("UserCfg.opt"),
+    )
+}
+
+/// The packages of the local app data named after the sim, other than the
+/// one of `from_store`.
+fn from_xbox_app() -> Result<PathBuf, String> {
+    let packages = data_local_dir()
+        .ok_or_else(|| String::from("no local app data directory"))?
+        .join("Packages");
+    let entries = fs::read_dir(&packages).map_err(|_| format!("{:?} does not exist", packages))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("Microsoft.FlightSimulator") && name != STORE_MSFS_DIR_NAME {
+            let usercfg = entry.path().join("LocalCache").join("UserCfg.opt");
+            if usercfg.is_file() {
+                return Ok(usercfg);
+            }
+        }
+    }
+    Err(format!("no other package of the sim in {:?}", packages))
+}
+
+fn from_steam() -> Result<PathBuf, String> {
+    let roaming = data_dir().ok_or_else(|| String::from("no roaming app data directory"))?;
+    existing(roaming.join(STEAM_MSFS_DIR_NAME).join("UserCfg.opt"))
+}
+
+/// The `SteamPath` Steam records for the current user, which may be
+/// elsewhere than the `Program Files` looked in by `steam-vdf`.
+#[cfg(target_os = "windows")]
+fn from_registry() -> Result<PathBuf, String> {
+    use windows::core::PCWSTR;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ};
+    let wide = |text: &str| text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let (key, value) = (wide("Software\\Valve\\Steam"), wide("SteamPath"));
+    let mut buffer = vec![0u16; 1024];
+    let mut size = (buffer.len() * 2) as u32;
+    unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            PCWSTR(key.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    }
+    .map_err(|_| String::from("Steam is not recorded in the registry"))?;
+    // `size` counts the bytes of the terminating null.
+    let steam_root = String::from_utf16_lossy(&buffer[..(size as usize / 2).saturating_sub(1)]);
+    steam::usercfg_in_steam(Path::new(&steam_root))
+        .ok_or_else(|| format!("no Steam library of {:?} has the sim installed", steam_root))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn from_registry() -> Result<PathBuf, String> {
+    Err(String::from("no registry on this system"))
+}
+
+/// Remembered in the `config-file` file, so that the next runs do not search
+/// again.
+fn from_search() -> Result<PathBuf, String> {
+    let roaming = data_dir().ok_or_else(|| String::from("no roaming app data directory"))?;
+    // Each linked directory once: the profile may link into itself.
+    for entry in fileid::walk(WalkDir::new(&roaming), true) {
+        if entry.file_type().is_file() && entry.file_name() == "UserCfg.opt" {
+            let path = entry.path().to_string_lossy().to_ascii_lowercase();
+            if path.contains("microsoft") && path.contains("flight") {
+                remember(entry.path());
+                return Ok(entry.path().to_path_buf());
+            }
+        }
+    }
+    Err(format!("no `UserCfg.opt` of the sim under {:?}", roaming))
+}
+
+fn remember(usercfg: &Path) {
+    let file = match config_file() {
+        Some(file) => file,
+        None => return,
+    };
+    let written = file
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&file, usercfg.to_string_lossy().as_bytes()));
+    if let Err(err) = written {
+        eprintln!("Warning: unable to write {:?}: {}\n", file, err);
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use crate::hasher::Digest;
+use crate::intern::RecordPath;
+use crate::FileRecord;
+
+/// A set of identical files: same digest and size.
+pub struct DuplicateSet<'a> {
+    pub digest: &'a Digest,
+    pub size: u64,
+    pub paths: Vec<&'a RecordPath>,
+}
+
+impl DuplicateSet<'_> {
+    /// Bytes taken by the copies beyond the first.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Group the results by (digest, size), keeping the groups of two files or
+/// more, the most wasteful first. Empty files are all identical and left
+/// out.
+pub fn find_duplicates(results: &[FileRecord]) -> Vec<DuplicateSet<'_>> {
+    let mut groups: HashMap<(&Digest, u64), Vec<&RecordPath>> = HashMap::new();
+    for (path, hash, filesize, _, _) in results {
+        if *filesize > 0 {
+            groups.entry((hash, *filesize)).or_default().push(path);
+        }
+    }
+    let mut sets: Vec<DuplicateSet> = groups
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((digest, size), mut paths)| {
+            paths.sort_unstable();
+            DuplicateSet {
+                digest,
+                size,
+                paths,
+            }
+        })
+        .collect();
+    sets.sort_unstable_by(|a, b| {
+        b.wasted()
+            .cmp(&a.wasted())
+            .then_with(|| a.paths[0].cmp(b.paths[0]))
+    });
+    sets
+}
+
+/// Print every set (`size`, `copies`, `digest`, then its paths) and the
+/// total of the wasted bytes to the stderr, after the hash values.
+pub fn print_duplicates(sets: &[DuplicateSet], show: impl Fn(&str) -> String) {
+    eprintln!();
+    for set in sets {
+        eprintln!(
+            "{} bytes x {} copies ({} bytes wasted)\t{}",
+            set.size,
+            set.paths.len(),
+            set.wasted(),
+            set.digest
+        );
+        for path in &set.paths {
+            eprintln!("    {}", show(&path.to_string()));
+        }
+    }
+    let wasted: u64 = sets.iter().map(DuplicateSet::wasted).sum();
+    let copies: usize = sets.iter().map(|set| set.paths.len() - 1).sum();
+    eprintln!(
+        "\n{} set(s) of identical files, {} redundant copies, {} bytes ({:.1} MiB) wasted.\n",
+        sets.len(),
+        copies,
+        wasted,
+        wasted as f64 / 1024.0 / 1024.0
+    );
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Every way a run can fail, each with its own process exit code and a
+/// stable string code for the JSON output (see `code`).
+///
+/// | Code | Meaning                                                 |
+/// |------|---------------------------------------------------------|
+/// | 0    | Success                                                 |
+/// | 2    | Invalid command line arguments                          |
+/// | 3    | `UserCfg.opt` or its `InstalledPackagesPath` not found  |
+/// | 4    | The packages directory does not exist                   |
+/// | 5    | The output (or cache) file cannot be written            |
+/// | 6    | Some files could not be read                            |
+/// | 7    | Verification found mismatching or missing files        |
+/// | 8    | A result file given as input cannot be read or used     |
+/// | 9    | Not enough memory for the hashing buffers               |
+/// | 10   | Interrupted by Ctrl+C (a checkpoint was saved)          |
+/// | 11   | No file found, or far fewer than in the previous run    |
+/// | 12   | The rolling cache looks damaged                         |
+#[derive(Debug)]
+pub enum AppError {
+    InvalidPattern(String, String),
+    UnknownTopic(String),
+    InvalidRoot(String),
+    InvalidSnapshotName(String),
+    UnknownSnapshot(String),
+    NotKept(String, String),
+    InvalidKey(String, String),
+    ScheduleFailed(String, String),
+    ConfigNotFound,
+    PackagesPathNotConfigured(PathBuf),
+    PackagesDirMissing(PathBuf, IoError),
+    OutputUnwritable(PathBuf, IoError),
+    ReadErrorsOccurred(usize),
+    VerificationFailed(usize),
+    ManifestUnreadable(PathBuf, IoError),
+    UnknownAlgorithm(PathBuf),
+    AlgorithmMismatch(PathBuf, &'static str, &'static str),
+    InsufficientMemory(u64),
+    BufferTooLarge(u64, usize, u64),
+    Interrupted(PathBuf, usize),
+    TooFewFiles(usize, Option<usize>),
+    RollingCacheInvalid(PathBuf, usize),
+}
+
+impl AppError {
+    pub const INTERRUPTED_EXIT_CODE: i32 = 10;
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::InvalidPattern(..)
+            | AppError::UnknownTopic(_)
+            | AppError::InvalidRoot(_)
+            | AppError::InvalidSnapshotName(_)
+            | AppError::UnknownSnapshot(_)
+            | AppError::NotKept(..)
+            | AppError::InvalidKey(..) => 2,
+            AppError::ConfigNotFound | AppError::PackagesPathNotConfigured(_) => 3,
+            AppError::PackagesDirMissing(..) => 4,
+            AppError::OutputUnwritable(..) | AppError::ScheduleFailed(..) => 5,
+            AppError::ReadErrorsOccurred(_) => 6,
+            AppError::VerificationFailed(_) => 7,
+            AppError::ManifestUnreadable(..)
+            | AppError::UnknownAlgorithm(_)
+            | AppError::AlgorithmMismatch(..) => 8,
+            AppError::InsufficientMemory(_) | AppError::BufferTooLarge(..) => 9,
+            AppError::Interrupted(..) => Self::INTERRUPTED_EXIT_CODE,
+            AppError::TooFewFiles(..) => 11,
+            AppError::RollingCacheInvalid(..) => 12,
+        }
+    }
+
+    /// Stable code for GUI wrappers, never changed once released.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InvalidPattern(..) => "E_INVALID_PATTERN",
+            AppError::UnknownTopic(_) => "E_UNKNOWN_TOPIC",
+            AppError::InvalidRoot(_) => "E_INVALID_ROOT",
+            AppError::InvalidSnapshotName(_) => "E_INVALID_SNAPSHOT_NAME",
+            AppError::UnknownSnapshot(_) => "E_UNKNOWN_SNAPSHOT",
+            AppError::NotKept(..) => "E_NOT_KEPT",
+            AppError::InvalidKey(..) => "E_INVALID_KEY",
+            AppError::ConfigNotFound => "E_USERCFG_NOT_FOUND",
+            AppError::PackagesPathNotConfigured(_) => "E_PACKAGES_PATH_NOT_CONFIGURED",
+            AppError::PackagesDirMissing(path, _) if !drive_exists(path) => {
+                "E_PACKAGES_DRIVE_MISSING"
+            }
+            AppError::PackagesDirMissing(..) => "E_PACKAGES_DIR_MISSING",
+            AppError::OutputUnwritable(..) => "E_OUTPUT_UNWRITABLE",
+            AppError::ScheduleFailed(..) => "E_SCHEDULE_FAILED",
+            AppError::ReadErrorsOccurred(_) => "E_READ_ERRORS",
+            AppError::VerificationFailed(_) => "E_VERIFICATION_FAILED",
+            AppError::ManifestUnreadable(..) => "E_RESULT_FILE_UNREADABLE",
+            AppError::UnknownAlgorithm(_) => "E_UNKNOWN_ALGORITHM",
+            AppError::AlgorithmMismatch(..) => "E_ALGORITHM_MISMATCH",
+            AppError::InsufficientMemory(_) => "E_INSUFFICIENT_MEMORY",
+            AppError::BufferTooLarge(..) => "E_BUFFER_TOO_LARGE",
+            AppError::Interrupted(..) => "E_INTERRUPTED",
+            AppError::TooFewFiles(..) => "E_TOO_FEW_FILES",
+            AppError::RollingCacheInvalid(..) => "E_ROLLING_CACHE_INVALID",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::InvalidPattern(pattern, err) => {
+                write!(f, "Invalid glob pattern {:?}: {}", pattern, err)
+            }
+            AppError::UnknownTopic(topic) => write!(
+                f,
+                "Unknown help topic {:?}, run `explain` without a topic to list them.",
+                topic
+            ),
+            AppError::InvalidRoot(spec) => write!(
+                f,
+                "Invalid root {:?}, expected `NAME=PATH` with a name made of letters, digits, `-` and `_` (`packages` and `config` are reserved).",
+                spec
+            ),
+            AppError::InvalidSnapshotName(name) => write!(
+                f,
+                "Invalid snapshot name {:?}, use letters, digits, `-`, `_` and `.` only.",
+                name
+            ),
+            AppError::UnknownSnapshot(name) => write!(
+                f,
+                "No snapshot named {:?}, run `snapshot list` to list them.",
+                name
+            ),
+            AppError::NotKept(name, path) => write!(
+                f,
+                "Snapshot {:?} did not keep {:?}: not a text file, larger than `--keep-text-max-size`, or not found.",
+                name, path
+            ),
+            AppError::InvalidKey(key, err) => {
+                write!(f, "Invalid ed25519 key {:?}: {}", key, err)
+            }
+            AppError::ConfigNotFound => write!(
+                f,
+                "Unable to find the `UserCfg.opt` file, you may not have correctly installed MSFS2020."
+            ),
+            AppError::PackagesPathNotConfigured(usercfg) => write!(
+                f,
+                "Unable to find the `InstalledPackagesPath` configuration in the `UserCfg.opt` file: {:?}",
+                usercfg
+            ),
+            AppError::PackagesDirMissing(path, err) => {
+                write!(f, "Unable to access the packages path {:?}: {}", path, err)
+            }
+            AppError::OutputUnwritable(path, err) => {
+                write!(f, "Unable to write the output file {:?}: {}", path, err)
+            }
+            AppError::ScheduleFailed(name, err) => {
+                write!(f, "Unable to change the scheduled task {:?}: {}", name, err)
+            }
+            AppError::ReadErrorsOccurred(count) => {
+                write!(f, "{} file(s) could not be read.", count)
+            }
+            AppError::VerificationFailed(count) => {
+                write!(f, "Verification failed: {} problem(s) found.", count)
+            }
+            AppError::ManifestUnreadable(path, err) => {
+                write!(f, "Unable to read the result file {:?}: {}", path, err)
+            }
+            AppError::UnknownAlgorithm(path) => write!(
+                f,
+                "Unable to detect the hash algorithm of the result file {:?}.",
+                path
+            ),
+            AppError::AlgorithmMismatch(path, found, expected) => write!(
+                f,
+                "The result file {:?} uses the {} algorithm, but {} is required.",
+                path, found, expected
+            ),
+            AppError::InsufficientMemory(available) => write!(
+                f,
+                "No enough memory: current {:.3} MiB",
+                *available as f64 / 1024.0 / 1024.0
+            ),
+            AppError::BufferTooLarge(buffer, threads, limit) => write!(
+                f,
+                "{} thread(s) with {} MiB buffers do not fit in {:.3} MiB of memory, lower `--buffer-size` or `--threads`.",
+                threads,
+                buffer / 1024 / 1024,
+                *limit as f64 / 1024.0 / 1024.0
+            ),
+            AppError::Interrupted(checkpoint, count) => write!(
+                f,
+                "Interrupted after {} file(s), checkpoint saved to {:?}. Run again with `--resume {}` to continue.",
+                count,
+                checkpoint,
+                checkpoint.to_string_lossy()
+            ),
+            AppError::TooFewFiles(0, _) => write!(
+                f,
+                "No file was found: check the packages path (`-P`) and the `--include` patterns."
+            ),
+            AppError::TooFewFiles(files, Some(previous)) => write!(
+                f,
+                "Only {} file(s) were found, against {} in the previous run: check the packages path (`-P`), or add `--allow-few-files` if files were removed on purpose.",
+                files, previous
+            ),
+            AppError::TooFewFiles(files, None) => {
+                write!(f, "Only {} file(s) were found.", files)
+            }
+            AppError::RollingCacheInvalid(file, problems) => write!(
+                f,
+                "The rolling cache {:?} looks damaged ({} problem(s)): delete it from the sim settings (Data > Rolling cache > Delete rolling cache file).",
+                file, problems
+            ),
+        }
+    }
+}
+
+/// Stable code of an error reading one file, for the JSON output.
+pub fn io_error_code(err: &IoError) -> &'static str {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION: the sim or an
+    // antivirus holds the file.
+    if matches!(err.raw_os_error(), Some(32) | Some(33)) && cfg!(target_os = "windows") {
+        return "E_READ_LOCKED";
+    }
+    match err.kind() {
+        ErrorKind::NotFound => "E_READ_NOT_FOUND",
+        ErrorKind::PermissionDenied => "E_READ_DENIED",
+        _ => "E_READ_FAILED",
+    }
+}
+
+/// Whether the drive (or root) of `path` exists. Relative paths have none.
+fn drive_exists(path: &Path) -> bool {
+    let root = path.ancestors().last().unwrap_or(path);
+    root.as_os_str().is_empty() || root.exists()
+}
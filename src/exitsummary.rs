@@ -0,0 +1,62 @@
+use serde_json::{json, Value};
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Result as IoResult, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::cache::unix_secs;
+use crate::error::AppError;
+
+/// The summary of a command: what ran on which PC, how it ended and why,
+/// and the totals of its hashing (`null` when it did not get that far).
+/// Written to the `--summary-json` file and sent by `--notify webhook=`.
+pub fn summary(
+    command: &str,
+    packages_dir: Option<&Path>,
+    result: &Result<(), AppError>,
+    stats: Option<&Value>,
+) -> Value {
+    let (status, exit_code, code, message) = match result {
+        Ok(()) => ("ok", 0, "OK", String::from("Completed successfully.")),
+        Err(err) => (
+            if err.exit_code() == AppError::INTERRUPTED_EXIT_CODE {
+                "interrupted"
+            } else {
+                "failed"
+            },
+            err.exit_code(),
+            err.code(),
+            err.to_string(),
+        ),
+    };
+    // The number behind the exit code, for the failures that have one.
+    let count = match result {
+        Err(AppError::VerificationFailed(problems)) => Some(*problems),
+        Err(AppError::ReadErrorsOccurred(errors)) => Some(*errors),
+        Err(AppError::RollingCacheInvalid(_, problems)) => Some(*problems),
+        _ => None,
+    };
+    json!({
+        "msfs2020checksum": env!("CARGO_PKG_VERSION"),
+        "host": env::var("COMPUTERNAME").or_else(|_| env::var("HOSTNAME")).ok(),
+        "command": command,
+        "packages_dir": packages_dir.map(|dir| dir.to_string_lossy()),
+        "finished": unix_secs(SystemTime::now()),
+        "status": status,
+        "exit_code": exit_code,
+        "code": code,
+        "message": message,
+        "count": count,
+        "totals": stats,
+    })
+}
+
+/// Write the `--summary-json` file. Written whatever the outcome, so that a
+/// script running the tool on many PCs collects one such file from each.
+pub fn write(file: &Path, summary: &Value) -> IoResult<()> {
+    let mut writer = BufWriter::new(File::create(file)?);
+    serde_json::to_writer_pretty(&mut writer, summary)?;
+    writeln!(writer)?;
+    writer.flush()
+}
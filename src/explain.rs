@@ -0,0 +1,272 @@
+use crate::error::AppError;
+
+/// `(names, text)` of every help topic. The first name is the one listed;
+/// the others are the status or error codes that lead to the same topic.
+const TOPICS: &[(&[&str], &str)] = &[
+    (
+        &["mismatch", "hash-mismatch", "MISMATCH"],
+        "MISMATCH: the file differs from the reference.
+
+The file exists on both sides but its content is different. Without a
+baseline the tool cannot tell an official update from a damaged file.
+
+Next steps:
+  * Make sure the reference was made for the same sim build and store.
+  * Run `verify` again with `--baseline` (the result file of your previous
+    sim version) to classify the file as UPDATED, OUTDATED or CORRUPTED.
+  * If it is damaged, repair the sim from the Windows settings (MS Store) or
+    use \"Verify integrity of game files\" (Steam).",
+    ),
+    (
+        &["corrupted", "CORRUPTED"],
+        "CORRUPTED: the file matches neither the reference nor the baseline.
+
+Neither the previous nor the current sim version has this content, so the
+file was changed locally: a disk error, an interrupted update or a mod that
+overwrote an official file.
+
+Next steps:
+  * Remove any mod that replaces official files.
+  * Repair the sim, or delete the package folder and let the sim download it
+    again.
+  * If files keep getting corrupted, check the disk (`chkdsk /scan`).",
+    ),
+    (
+        &["outdated", "OUTDATED"],
+        "OUTDATED: the file still has the content of the previous sim version.
+
+The update did not reach this file, usually because the download was
+interrupted or the package was skipped.
+
+Next steps:
+  * Start the sim and let it finish the update.
+  * If it does not, delete the package folder and let the sim download it
+    again.",
+    ),
+    (
+        &["updated", "UPDATED"],
+        "UPDATED: the file was changed by the official update.
+
+It differs from the baseline and matches the reference. Nothing to do.",
+    ),
+    (
+        &["missing", "MISSING"],
+        "MISSING: a file of the reference (or of a layout.json) is not on disk.
+
+Next steps:
+  * Check that `--include` / `--exclude` did not skip it.
+  * Start the sim to download the package again, or repair the sim.",
+    ),
+    (
+        &["extra", "EXTRA"],
+        "EXTRA: the file is on disk but not in the reference.
+
+It is often harmless (logs, caches, files of a newer build). It is a
+problem when a mod left files inside an official package.",
+    ),
+    (
+        &["moved", "MOVED"],
+        "MOVED: a file is at another path, with the same content.
+
+Printed as `MOVED<TAB>old -> new` instead of a MISSING and an EXTRA line by
+`verify`, or a REMOVED and an ADDED line by `diff`, when exactly one file on
+each side has that hash and size.
+
+Across a sim update (`diff`), the update restructured the package. In
+`verify`, the sim will not find the file where the reference has it: a
+leftover of an earlier layout or a file copied into the wrong folder. Start
+the sim to let it fix the package, or repair the sim.",
+    ),
+    (
+        &["obsolete", "OBSOLETE"],
+        "OBSOLETE: the file existed in the previous sim version but not in the
+current one.
+
+The update should have removed it. Delete it, or delete the package folder
+and let the sim download it again.",
+    ),
+    (
+        &["size", "SIZE"],
+        "SIZE: the size of the file differs from its package layout.json.
+
+The file is truncated or damaged. Delete the package folder and let the
+sim download it again.",
+    ),
+    (
+        &["orphan", "ORPHAN"],
+        "ORPHAN: the file is not listed in its package layout.json.
+
+Leftovers of an update or files added by a mod. The sim does not use them
+and they can usually be deleted.",
+    ),
+    (
+        &["range", "RANGE"],
+        "RANGE: the bytes first-last of a mismatching file differ.
+
+Printed by `verify` after a MISMATCH (or CORRUPTED, OUTDATED) line when the
+reference was made with `--chunk-size`. A few small ranges point to disk
+errors; everything from some offset to the end points to an interrupted
+download or copy.",
+    ),
+    (
+        &["snapshot", "CHANGED", "ADDED", "REMOVED"],
+        "CHANGED, ADDED, REMOVED: a file differs between two result files.
+
+Printed by `diff OLD NEW` and `snapshot compare OLD NEW`. CHANGED files have another content or
+size in NEW, ADDED files are only in NEW and REMOVED files only in OLD.
+Across a sim update these are the files the update touched; with no update
+in between, a CHANGED official file points to local damage (run `verify`).",
+    ),
+    (
+        &["local-state", "EMPTY", "ZEROED", "MALFORMED", "UNREADABLE"],
+        "A LocalState file (logbook, wishlist, content cache) is damaged.
+
+EMPTY and ZEROED files were cut off while the sim saved them, usually by a
+crash or a full disk. MALFORMED files are incomplete JSON or XML.
+
+Next steps:
+  * Close the sim, then move the damaged file away (keep a copy).
+  * Start the sim: it rebuilds the content cache and downloads the logbook
+    and wishlist again from the cloud.
+  * Free some space on the system drive.",
+    ),
+    (
+        &[
+            "read-error",
+            "E_READ_ERRORS",
+            "E_READ_NOT_FOUND",
+            "E_READ_DENIED",
+            "E_READ_LOCKED",
+            "E_READ_FAILED",
+        ],
+        "Read errors: some files could not be read.
+
+Next steps:
+  * Close the sim and any addon manager, they lock some files.
+  * Exclude the packages folder from the antivirus real-time scan while
+    hashing, or wait for the scan to finish.
+  * Run the tool from a terminal started as Administrator if access is
+    denied.",
+    ),
+    (
+        &["antivirus", "controlled-folder-access"],
+        "An antivirus is likely blocking the reads.
+
+Many files were denied or locked, mostly files modified in the last days:
+a real-time scan opens every new file of an update, and Controlled Folder
+Access denies programs it does not know.
+
+Next steps:
+  * Wait for the scan of the update to finish, then run again.
+  * Exclude the packages folder from the real-time scan, or allow
+    msfs2020checksum in Windows Security > Ransomware protection >
+    Allow an app through Controlled folder access.
+  * Put the exclusion back once the check is done if you prefer.",
+    ),
+    (
+        &[
+            "usercfg",
+            "E_USERCFG_NOT_FOUND",
+            "E_PACKAGES_PATH_NOT_CONFIGURED",
+        ],
+        "UserCfg.opt not found or without InstalledPackagesPath.
+
+The tool reads the packages location from the `UserCfg.opt` file of the sim.
+
+Next steps:
+  * Start the sim once, it writes the file at the first launch.
+  * Give the file with `--config`, or the packages folder with `--packages`.",
+    ),
+    (
+        &[
+            "packages-dir",
+            "E_PACKAGES_DIR_MISSING",
+            "E_PACKAGES_DRIVE_MISSING",
+        ],
+        "The packages folder cannot be accessed.
+
+Next steps:
+  * Connect the drive the packages are installed on.
+  * If the packages were moved, give the new folder with `--packages`.",
+    ),
+    (
+        &["algorithm", "E_UNKNOWN_ALGORITHM", "E_ALGORITHM_MISMATCH"],
+        "The hash algorithm of a result file is unknown or different.
+
+Next steps:
+  * Use result files made with the same `--algorithm`.
+  * Add a `# algorithm: <name>` line at the top of a result file made by
+    another tool.",
+    ),
+    (
+        &["memory", "E_INSUFFICIENT_MEMORY", "E_BUFFER_TOO_LARGE"],
+        "Not enough memory for the read buffers.
+
+Every thread reads through its own buffer, so the memory used is the
+buffer size times the number of threads.
+
+Next steps:
+  * Close other programs, or use fewer threads with `--threads`.
+  * Give a smaller `--buffer-size`, or leave it out to let the tool choose.",
+    ),
+    (
+        &["interrupted", "E_INTERRUPTED"],
+        "The run was interrupted by Ctrl+C.
+
+The files hashed so far were saved to a checkpoint. Run the same command
+with `--resume <checkpoint>` to continue without reading them again.",
+    ),
+    (
+        &["too-few-files", "E_TOO_FEW_FILES"],
+        "The run found no file, or less than half the files of the previous run.
+
+The result file is written anyway, but it is most likely not what you want
+to keep as a reference: a mistyped `-P`, a drive that is not mounted yet or
+an `--include` pattern that matches nothing all give such a result. The
+previous run is the output file being overwritten, or the `--cache` file.
+
+Next steps:
+  * Check the packages path printed at the start of the run.
+  * If the files were removed on purpose (packages uninstalled), run again
+    with `--allow-few-files`.",
+    ),
+    (
+        &["rolling-cache", "E_ROLLING_CACHE_INVALID"],
+        "`--check-rolling-cache` found the rolling cache (`ROLLINGCACHE.CCC`)
+missing, truncated, larger than configured, or with a blank index.
+
+A damaged rolling cache is a common cause of crashes to desktop, while the
+packages all verify fine. The file is only a cache of streamed scenery: it
+is safe to delete, the sim fills it again.
+
+Next steps:
+  * In the sim, Options > General Options > Data > Rolling cache: delete
+    the rolling cache file, or turn the rolling cache off.
+  * Or delete `ROLLINGCACHE.CCC` while the sim is closed.",
+    ),
+];
+
+/// Print the help topic for `topic`, or the list of topics.
+pub fn explain(topic: Option<&str>) -> Result<(), AppError> {
+    let topic = match topic {
+        Some(topic) => topic,
+        None => {
+            println!("Topics (a result status or an error code works too):");
+            for (names, _) in TOPICS {
+                println!("  {}", names[0]);
+            }
+            return Ok(());
+        }
+    };
+    match TOPICS.iter().find(|(names, _)| {
+        names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(topic.trim()))
+    }) {
+        Some((_, text)) => {
+            println!("{}", text);
+            Ok(())
+        }
+        None => Err(AppError::UnknownTopic(topic.to_string())),
+    }
+}
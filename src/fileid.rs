@@ -0,0 +1,57 @@
+use std::collections::HashSet;
+use std::path::Path;
+use walkdir::{DirEntry, WalkDir};
+
+/// The volume and the number of the file or directory at `path`, the same
+/// for all the paths (links, junctions) that lead to it.
+#[cfg(target_os = "windows")]
+pub fn file_id(path: &Path) -> Option<(u64, u64)> {
+    use crate::longpath;
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION, FILE_FLAG_BACKUP_SEMANTICS,
+    };
+    // A directory can only be opened with `FILE_FLAG_BACKUP_SEMANTICS`; no
+    // access right is needed to read its information.
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(longpath::extended(path))
+        .ok()?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle() as isize), &mut info) }.ok()?;
+    let index = (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow);
+    Some((u64::from(info.dwVolumeSerialNumber), index))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn file_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = path.metadata().ok()?;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Walk `walk`, the way the packages are walked for their hashes.
+/// Without `follow_links` the links are left out. With it, a directory
+/// reached again through another link is skipped, which also ends the link
+/// cycles.
+pub fn walk(walk: WalkDir, follow_links: bool) -> impl Iterator<Item = DirEntry> {
+    let mut visited = HashSet::new();
+    walk.follow_links(follow_links)
+        // The directories themselves before the links to them, so that
+        // their files keep the same path as without `--follow-links`.
+        .sort_by(|a, b| {
+            (a.path_is_symlink(), a.file_name()).cmp(&(b.path_is_symlink(), b.file_name()))
+        })
+        .into_iter()
+        .filter_entry(move |entry| {
+            !follow_links
+                || !entry.file_type().is_dir()
+                || file_id(entry.path()).is_none_or(|id| visited.insert(id))
+        })
+        .filter_map(|res| res.ok())
+        .filter(move |entry| follow_links || !entry.path_is_symlink())
+}
@@ -0,0 +1,104 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+
+/// Files of the packages directory that change between two runs without
+/// the install changing: the content history and the marketplace licenses
+/// the sim rewrites, and the leftovers of interrupted downloads.
+const VOLATILE_PATHS: [&str; 6] = [
+    "**/ContentHistory.json",
+    "**/ContentHistory/**",
+    "**/*.lic",
+    "**/ROLLINGCACHE.CCC",
+    "**/*.tmp",
+    "**/*.part",
+];
+
+/// `--include` / `--exclude` globs, matched against the paths relative to
+/// the packages directory, and the volatile files of `--skip-volatile`.
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    volatile: Option<GlobSet>,
+    /// The sample of `--spot-check`: no other file is kept.
+    only: Option<HashSet<PathBuf>>,
+    /// The arguments of `new`.
+    globs: (Vec<String>, Vec<String>, bool),
+}
+
+impl PathFilter {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        skip_volatile: bool,
+    ) -> Result<Self, AppError> {
+        let globs = (include.to_vec(), exclude.to_vec(), skip_volatile);
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_globset(include)?)
+        };
+        let volatile = if skip_volatile {
+            let patterns: Vec<String> = VOLATILE_PATHS.iter().map(|p| p.to_string()).collect();
+            Some(build_globset(&patterns)?)
+        } else {
+            None
+        };
+        Ok(PathFilter {
+            include,
+            exclude: build_globset(exclude)?,
+            volatile,
+            only: None,
+            globs,
+        })
+    }
+
+    /// Keep only these files (relative paths), whatever the globs.
+    pub fn restrict_to<'a>(&mut self, paths: impl Iterator<Item = &'a str>) {
+        self.only = Some(paths.map(PathBuf::from).collect());
+    }
+
+    /// The globs and `--skip-volatile` the filter was made of, or `None`
+    /// once restricted to a sample.
+    pub fn globs(&self) -> Option<(Vec<String>, Vec<String>, bool)> {
+        match self.only {
+            Some(_) => None,
+            None => Some(self.globs.clone()),
+        }
+    }
+
+    /// Whether the file is one of the volatile files, with `--skip-volatile`.
+    pub fn is_volatile(&self, relative_path: &Path) -> bool {
+        self.volatile
+            .as_ref()
+            .is_some_and(|volatile| volatile.is_match(relative_path))
+    }
+
+    /// A file is kept when it matches any include glob (or there is none),
+    /// no exclude glob and is not skipped as volatile.
+    pub fn is_match(&self, relative_path: &Path) -> bool {
+        self.only
+            .as_ref()
+            .is_none_or(|only| only.contains(relative_path))
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative_path))
+            && !self.exclude.is_match(relative_path)
+            && !self.is_volatile(relative_path)
+    }
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, AppError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|err| AppError::InvalidPattern(pattern.clone(), err.to_string()))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|err| AppError::InvalidPattern(patterns.join(" "), err.to_string()))
+}
@@ -0,0 +1,343 @@
+use crate::intern::RecordPath;
+use clap::ValueEnum;
+use sha2::Digest as _;
+use std::fmt;
+use twox_hash::{XxHash3_128, XxHash3_64};
+
+/// Hash algorithms that can be computed and may appear in result files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    #[clap(name = "xxh3-128")]
+    Xxh3_128,
+    #[clap(name = "xxh3-64")]
+    Xxh3_64,
+    Sha256,
+    Blake3,
+    Crc32,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Xxh3_128 => "xxh3-128",
+            Algorithm::Xxh3_64 => "xxh3-64",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Crc32 => "crc32",
+        }
+    }
+
+    /// Parse the name used in `# algorithm:` headers.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "xxh3-128" | "xxh128" => Some(Algorithm::Xxh3_128),
+            "xxh3-64" | "xxh3" => Some(Algorithm::Xxh3_64),
+            "sha256" | "sha-256" => Some(Algorithm::Sha256),
+            "blake3" => Some(Algorithm::Blake3),
+            "crc32" => Some(Algorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    /// Parse the tag of a BSD-style `TAG (path) = digest` line.
+    pub fn from_bsd_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "XXH128" => Some(Algorithm::Xxh3_128),
+            "XXH3" | "XXH3_64" => Some(Algorithm::Xxh3_64),
+            "SHA256" => Some(Algorithm::Sha256),
+            "BLAKE3" => Some(Algorithm::Blake3),
+            "CRC32" => Some(Algorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    /// The algorithm of a checksum list named after it, as `sha256sum` and
+    /// `b3sum` lists usually are (`Official.sha256`, `Official.b3`).
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "xxh128" => Some(Algorithm::Xxh3_128),
+            "xxh3" | "xxh64" => Some(Algorithm::Xxh3_64),
+            "sha256" => Some(Algorithm::Sha256),
+            "b3" | "blake3" => Some(Algorithm::Blake3),
+            "crc32" => Some(Algorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    /// Whether it hashes at memory speed (xxh3, crc32): faster than giving
+    /// it a thread of its own pays off.
+    pub fn is_fast(self) -> bool {
+        matches!(
+            self,
+            Algorithm::Xxh3_128 | Algorithm::Xxh3_64 | Algorithm::Crc32
+        )
+    }
+
+    /// Guess the algorithm from the length of a hex digest.
+    /// A 64 digits digest is assumed to be SHA-256 rather than BLAKE3.
+    pub fn from_digest_len(len: usize) -> Option<Self> {
+        match len {
+            32 => Some(Algorithm::Xxh3_128),
+            16 => Some(Algorithm::Xxh3_64),
+            64 => Some(Algorithm::Sha256),
+            8 => Some(Algorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    /// A streaming hasher, fed chunk by chunk.
+    pub fn hasher(self) -> Box<dyn Hasher + Send> {
+        match self {
+            Algorithm::Xxh3_128 => Box::new(XxHash3_128::new()),
+            Algorithm::Xxh3_64 => Box::new(XxHash3_64::new()),
+            Algorithm::Sha256 => Box::new(sha2::Sha256::new()),
+            Algorithm::Blake3 => Box::new(blake3::Hasher::new()),
+            Algorithm::Crc32 => Box::new(crc32fast::Hasher::new()),
+        }
+    }
+
+    /// Hash a whole file content already in memory.
+    pub fn hash_oneshot(self, data: &[u8]) -> Digest {
+        match self {
+            Algorithm::Xxh3_128 => Digest::from(XxHash3_128::oneshot(data)),
+            Algorithm::Xxh3_64 => Digest(XxHash3_64::oneshot(data).to_be_bytes().to_vec()),
+            _ => {
+                let mut hasher = self.hasher();
+                hasher.update(data);
+                hasher.digest()
+            }
+        }
+    }
+}
+
+/// A digest of any algorithm, displayed as lowercase hex.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Digest(Vec<u8>);
+
+impl Digest {
+    pub fn from_hex(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() || !text.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes = (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Digest(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Number of hex digits when displayed.
+    pub fn hex_len(&self) -> usize {
+        self.0.len() * 2
+    }
+}
+
+impl From<u128> for Digest {
+    fn from(value: u128) -> Self {
+        Digest(value.to_be_bytes().to_vec())
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// The hashes of a file besides the main one.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExtraHashes {
+    /// Whole-file hashes of the other `--algorithm`s, in their order.
+    pub algorithms: Vec<Digest>,
+    /// Hashes of each `--chunk-size` chunk, with the main algorithm. Empty
+    /// for the files that fit in one chunk.
+    pub chunks: Vec<Digest>,
+    /// With `--dedupe-links`, the path of the same physical file whose
+    /// hashes these are copied from: this path is a hard link or is reached
+    /// through a junction.
+    pub alias_of: Option<RecordPath>,
+}
+
+/// Hashes a stream chunk by chunk, for `--chunk-size`.
+pub struct ChunkHasher {
+    algorithm: Algorithm,
+    chunk_size: u64,
+    current: Box<dyn Hasher + Send>,
+    /// Bytes fed to `current`.
+    filled: u64,
+    digests: Vec<Digest>,
+}
+
+impl ChunkHasher {
+    pub fn new(algorithm: Algorithm, chunk_size: u64) -> Self {
+        assert!(chunk_size > 0, "chunks of 0 bytes");
+        ChunkHasher {
+            algorithm,
+            chunk_size,
+            current: algorithm.hasher(),
+            filled: 0,
+            digests: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let room = (self.chunk_size - self.filled).min(data.len() as u64) as usize;
+            self.current.update(&data[..room]);
+            self.filled += room as u64;
+            data = &data[room..];
+            if self.filled == self.chunk_size {
+                let full = std::mem::replace(&mut self.current, self.algorithm.hasher());
+                self.digests.push(full.digest());
+                self.filled = 0;
+            }
+        }
+    }
+
+    /// The digests of every chunk, the last one possibly shorter.
+    pub fn finish(mut self) -> Vec<Digest> {
+        if self.filled > 0 {
+            self.digests.push(self.current.digest());
+        }
+        self.digests
+    }
+}
+
+/// Streaming interface shared by all algorithms, so the read loops do not
+/// care which one is used.
+pub trait Hasher {
+    fn update(&mut self, data: &[u8]);
+    fn digest(&self) -> Digest;
+}
+
+impl Hasher for XxHash3_128 {
+    fn update(&mut self, data: &[u8]) {
+        self.write(data);
+    }
+
+    fn digest(&self) -> Digest {
+        Digest::from(self.finish_128())
+    }
+}
+
+impl Hasher for XxHash3_64 {
+    fn update(&mut self, data: &[u8]) {
+        std::hash::Hasher::write(self, data);
+    }
+
+    fn digest(&self) -> Digest {
+        Digest(std::hash::Hasher::finish(self).to_be_bytes().to_vec())
+    }
+}
+
+impl Hasher for sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data);
+    }
+
+    fn digest(&self) -> Digest {
+        Digest(self.clone().finalize().to_vec())
+    }
+}
+
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn digest(&self) -> Digest {
+        Digest(self.finalize().as_bytes().to_vec())
+    }
+}
+
+impl Hasher for crc32fast::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        crc32fast::Hasher::update(self, data);
+    }
+
+    fn digest(&self) -> Digest {
+        Digest(self.clone().finalize().to_be_bytes().to_vec())
+    }
+}
+
+/// The XXH3 implementation picked at runtime for this CPU, in the same order
+/// of preference as `twox-hash` uses.
+pub fn xxh3_implementation() -> &'static str {
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return "NEON";
+        }
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return "AVX2";
+        } else if is_x86_feature_detected!("sse2") {
+            return "SSE2";
+        }
+    }
+    "scalar"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The chunk digests of `data` fed in slices of `slice` bytes.
+    fn chunk_digests(data: &[u8], chunk_size: u64, slice: usize) -> Vec<Digest> {
+        let mut chunks = ChunkHasher::new(Algorithm::Xxh3_128, chunk_size);
+        for part in data.chunks(slice) {
+            chunks.update(part);
+        }
+        chunks.finish()
+    }
+
+    fn oneshot(data: &[u8]) -> Digest {
+        Algorithm::Xxh3_128.hash_oneshot(data)
+    }
+
+    #[test]
+    fn exact_multiple_of_the_chunk_size() {
+        let data: Vec<u8> = (0..40).collect();
+        let expected: Vec<Digest> = data.chunks(10).map(oneshot).collect();
+        assert_eq!(chunk_digests(&data, 10, 40), expected);
+    }
+
+    #[test]
+    fn short_last_chunk() {
+        let data: Vec<u8> = (0..25).collect();
+        let digests = chunk_digests(&data, 10, 25);
+        assert_eq!(digests.len(), 3);
+        assert_eq!(digests[2], oneshot(&data[20..]));
+    }
+
+    #[test]
+    fn slices_across_chunk_boundaries() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let expected: Vec<Digest> = data.chunks(64).map(oneshot).collect();
+        // One slice spanning several chunks, and slices that end mid-chunk.
+        for slice in [1, 7, 64, 100, 1000] {
+            assert_eq!(chunk_digests(&data, 64, slice), expected, "slice {}", slice);
+        }
+    }
+
+    #[test]
+    fn empty_stream() {
+        assert!(chunk_digests(&[], 10, 1).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn chunks_of_0_bytes() {
+        ChunkHasher::new(Algorithm::Xxh3_128, 0);
+    }
+}
@@ -0,0 +1,155 @@
+use rusqlite::{params, Connection};
+use std::io::Error as IoError;
+use std::path::{Path, MAIN_SEPARATOR_STR};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+use crate::hasher::Algorithm;
+use crate::settings::format_timestamp;
+use crate::snapshot::display_created;
+use crate::FileRecord;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started INTEGER NOT NULL,
+    packages_dir TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    files INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    path TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    mtime INTEGER NOT NULL,
+    PRIMARY KEY (run_id, path)
+);
+CREATE INDEX IF NOT EXISTS files_path ON files (path);
+";
+
+/// `(run id, started, algorithm, hash, size)` of a file in one run.
+type FileRun = (i64, i64, String, String, i64);
+
+fn open(db: &Path) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(db)?;
+    connection.execute_batch(SCHEMA)?;
+    Ok(connection)
+}
+
+/// Add a run and the result of each of its files to the `--db` database.
+/// Returns the id of the run.
+pub fn record_run(
+    db: &Path,
+    started: SystemTime,
+    packages_dir: &Path,
+    algorithm: Algorithm,
+    results: &[FileRecord],
+) -> Result<i64, AppError> {
+    let insert =
+        || -> rusqlite::Result<i64> {
+            let mut connection = open(db)?;
+            let transaction = connection.transaction()?;
+            transaction.execute(
+            "INSERT INTO runs (started, packages_dir, algorithm, files) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                started.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64,
+                packages_dir.to_string_lossy(),
+                algorithm.name(),
+                results.len() as i64,
+            ],
+        )?;
+            let run_id = transaction.last_insert_rowid();
+            {
+                let mut statement = transaction.prepare(
+                "INSERT INTO files (run_id, path, hash, size, mtime) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+                for (path, hash, filesize, mtime, _) in results {
+                    statement.execute(params![
+                        run_id,
+                        path.to_string(),
+                        hash.to_string(),
+                        *filesize as i64,
+                        *mtime as i64
+                    ])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(run_id)
+        };
+    insert().map_err(|err| AppError::OutputUnwritable(db.to_path_buf(), IoError::other(err)))
+}
+
+/// Print the runs of the `--db` database in which the hash or size of `path`
+/// (relative to the packages directory) differs from the run before, and
+/// when it last changed.
+pub fn print_history(db: &Path, path: &str, case_sensitive: bool) -> Result<(), AppError> {
+    let path = path.replace(['/', '\\'], MAIN_SEPARATOR_STR);
+    let query = || -> rusqlite::Result<Vec<FileRun>> {
+        let connection = open(db)?;
+        let mut statement = connection.prepare(if case_sensitive {
+            "SELECT runs.id, runs.started, runs.algorithm, files.hash, files.size
+             FROM files JOIN runs ON runs.id = files.run_id
+             WHERE files.path = ?1 ORDER BY runs.id"
+        } else {
+            "SELECT runs.id, runs.started, runs.algorithm, files.hash, files.size
+             FROM files JOIN runs ON runs.id = files.run_id
+             WHERE files.path = ?1 COLLATE NOCASE ORDER BY runs.id"
+        })?;
+        let rows = statement.query_map(params![path], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?;
+        rows.collect()
+    };
+    let rows = query()
+        .map_err(|err| AppError::ManifestUnreadable(db.to_path_buf(), IoError::other(err)))?;
+    if rows.is_empty() {
+        eprintln!("{} is in no run of {:?}.", path, db);
+        return Ok(());
+    }
+    let mut previous: Option<(&str, &str, i64)> = None;
+    let mut changes = Vec::new();
+    for (run_id, started, algorithm, hash, filesize) in &rows {
+        let current = (algorithm.as_str(), hash.as_str(), *filesize);
+        // Runs of another algorithm cannot be compared with each other.
+        let changed = match previous {
+            Some((old_algorithm, old_hash, old_size)) if old_algorithm == algorithm => {
+                old_hash != hash || old_size != *filesize
+            }
+            _ => true,
+        };
+        if changed {
+            let started = display_created(&format_timestamp(
+                UNIX_EPOCH + Duration::from_secs(*started as u64),
+            ));
+            println!(
+                "{}\t{}\t{:10}\trun {}\t{}",
+                hash, algorithm, filesize, run_id, started
+            );
+            changes.push((*run_id, started));
+        }
+        previous = Some(current);
+    }
+    match changes.as_slice() {
+        [(run_id, started)] => eprintln!(
+            "\nIn {} run(s), unchanged since run {} ({}).",
+            rows.len(),
+            run_id,
+            started
+        ),
+        [.., (run_id, started)] => eprintln!(
+            "\nIn {} run(s), last changed in run {} ({}).",
+            rows.len(),
+            run_id,
+            started
+        ),
+        [] => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Run a user-specified `--on-complete` / `--on-mismatch` command.
+///
+/// The command line is split on whitespace (double quotes group an argument
+/// containing spaces), then `{report}`, `{summary}` and `{exit_code}` are
+/// replaced in every argument. A failing hook only prints a warning, it
+/// never changes the exit code of the run.
+pub fn run_hook(command_line: &str, report: Option<&Path>, summary: &str, exit_code: i32) {
+    let report = report.map_or(String::new(), |path| path.to_string_lossy().to_string());
+    let exit_code = exit_code.to_string();
+    let mut args = split_command_line(command_line).into_iter().map(|arg| {
+        arg.replace("{report}", &report)
+            .replace("{summary}", summary)
+            .replace("{exit_code}", &exit_code)
+    });
+    let program = match args.next() {
+        Some(program) => program,
+        None => return,
+    };
+    match Command::new(&program).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: hook {:?} exited with {}", program, status),
+        Err(err) => eprintln!("Warning: unable to run hook {:?}: {}", program, err),
+    }
+}
+
+fn split_command_line(command_line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+    for c in command_line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Error as IoError;
+
+use crate::summary::PackageSummary;
+use crate::verify::Findings;
+use crate::{FileRecord, RunHeader};
+
+/// The style and the script are inline: the page is one file, to attach to
+/// a forum post or open from a mail.
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+td.hash { font-family: monospace; }
+table.sortable th { cursor: pointer; background: #eee; }
+table.sortable th::after { content: ' \\2195'; color: #999; }
+tr.bad { background: #fdd; }
+tr.warn { background: #ffe4c4; }
+tr.info { background: #e8f0ff; }
+";
+
+/// Clicking a header of a sortable table sorts its rows by that column, then
+/// the other way round.
+const SORT_SCRIPT: &str = "
+document.querySelectorAll('table.sortable th').forEach(function (th) {
+  th.addEventListener('click', function () {
+    var body = th.closest('table').tBodies[0];
+    var column = th.cellIndex;
+    var ascending = th.dataset.order !== 'asc';
+    th.dataset.order = ascending ? 'asc' : 'desc';
+    var numeric = th.classList.contains('num');
+    var rows = Array.from(body.rows);
+    rows.sort(function (a, b) {
+      var x = a.cells[column].textContent, y = b.cells[column].textContent;
+      var order = numeric ? x - y : (x < y ? -1 : x > y ? 1 : 0);
+      return ascending ? order : -order;
+    });
+    rows.forEach(function (row) { body.appendChild(row); });
+  });
+});
+";
+
+/// The page of a hashing run (`--format html`): the run, the summary of
+/// each package and every file.
+pub fn hash_page(
+    header: &RunHeader,
+    results: &[FileRecord],
+    read_errors: &[(String, &IoError)],
+    summary: &BTreeMap<String, PackageSummary>,
+) -> String {
+    let mut about = vec![(
+        "algorithm",
+        header
+            .algorithms
+            .iter()
+            .map(|algorithm| algorithm.name())
+            .collect::<Vec<_>>()
+            .join(", "),
+    )];
+    if let Some(provenance) = header.provenance {
+        about.push(("store", provenance.store.to_string()));
+        if let Some(ref sim_build) = provenance.sim_build {
+            about.push(("sim build", sim_build.clone()));
+        }
+    }
+    if let Some(chunk_size) = header.chunk_size {
+        about.push(("chunk size", format!("{} MiB", chunk_size)));
+    }
+    if let Some(sample) = header.sample {
+        about.push((
+            "sample",
+            format!("{} MiB at both ends of the larger files", sample),
+        ));
+    }
+    if let Some(tree) = header.tree {
+        about.push(("tree", format!("digest of the {} MiB leaves", tree)));
+    }
+    for (name, dir) in header.roots {
+        about.push(("root", format!("@{} = {}", name, dir)));
+    }
+    about.push(("files", results.len().to_string()));
+    about.push((
+        "bytes",
+        results.iter().map(|r| r.2).sum::<u64>().to_string(),
+    ));
+
+    let mut body = about_table(&about);
+    body.push_str("<h2>Packages</h2>\n");
+    body.push_str(&table_head(&[
+        ("package", false),
+        ("files", true),
+        ("bytes", true),
+        ("digest", false),
+    ]));
+    for (package, totals) in summary {
+        let _ = writeln!(
+            body,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"hash\">{}</td></tr>",
+            escape(package),
+            totals.files,
+            totals.bytes,
+            totals.digest
+        );
+    }
+    body.push_str("</tbody></table>\n");
+    if !read_errors.is_empty() {
+        let _ = writeln!(body, "<h2>Unreadable files ({})</h2>", read_errors.len());
+        body.push_str(&table_head(&[("path", false), ("error", false)]));
+        for (path, err) in read_errors {
+            let _ = writeln!(
+                body,
+                "<tr class=\"bad\"><td>{}</td><td>{}</td></tr>",
+                escape(path),
+                escape(&err.to_string())
+            );
+        }
+        body.push_str("</tbody></table>\n");
+    }
+    let _ = writeln!(body, "<h2>Files ({})</h2>", results.len());
+    body.push_str(&table_head(&[
+        ("path", false),
+        ("size", true),
+        ("hash", false),
+    ]));
+    for (path, hash, filesize, _, _) in results {
+        let _ = writeln!(
+            body,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"hash\">{}</td></tr>",
+            escape(&path.to_string()),
+            filesize,
+            hash
+        );
+    }
+    body.push_str("</tbody></table>\n");
+    page("msfs2020checksum report", &body)
+}
+
+/// The page of `verify --report` or `diff --report`: how many files of each
+/// status, then the files, the problems highlighted.
+pub fn findings_page(title: &str, about: &[(&str, String)], findings: &Findings) -> String {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (_, status) in findings {
+        *counts.entry(status).or_default() += 1;
+    }
+    let mut body = about_table(about);
+    if findings.is_empty() {
+        body.push_str("<p>No difference found.</p>\n");
+        return page(title, &body);
+    }
+    body.push_str("<h2>Statuses</h2>\n<table>\n");
+    for (status, count) in &counts {
+        let _ = writeln!(
+            body,
+            "<tr class=\"{}\"><td>{}</td><td class=\"num\">{}</td></tr>",
+            status_class(status),
+            status,
+            count
+        );
+    }
+    body.push_str("</table>\n");
+    let _ = writeln!(body, "<h2>Files ({})</h2>", findings.len());
+    body.push_str(&table_head(&[("status", false), ("path", false)]));
+    for (path, status) in findings {
+        let _ = writeln!(
+            body,
+            "<tr class=\"{}\"><td>{}</td><td>{}</td></tr>",
+            status_class(status),
+            status,
+            escape(path)
+        );
+    }
+    body.push_str("</tbody></table>\n");
+    page(title, &body)
+}
+
+/// The problems of `verify` are red or orange, the other differences blue.
+fn status_class(status: &str) -> &'static str {
+    match status {
+        "MISMATCH" | "CORRUPTED" | "MISSING" => "bad",
+        "OUTDATED" | "MOVED" => "warn",
+        _ => "info",
+    }
+}
+
+fn about_table(about: &[(&str, String)]) -> String {
+    let mut table = format!(
+        "<table>\n<tr><th>msfs2020checksum</th><td>{}</td></tr>\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    for (key, value) in about {
+        let _ = writeln!(
+            table,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(key),
+            escape(value)
+        );
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+/// `(name, numeric)` of each column; the numeric ones sort as numbers.
+fn table_head(columns: &[(&str, bool)]) -> String {
+    let mut head = String::from("<table class=\"sortable\">\n<thead><tr>");
+    for (name, numeric) in columns {
+        if *numeric {
+            let _ = write!(head, "<th class=\"num\">{}</th>", name);
+        } else {
+            let _ = write!(head, "<th>{}</th>", name);
+        }
+    }
+    head.push_str("</tr></thead>\n<tbody>\n");
+    head
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n{body}<script>{SORT_SCRIPT}</script>\n</body>\n</html>\n",
+        title = escape(title),
+        STYLE = STYLE,
+        body = body,
+        SORT_SCRIPT = SORT_SCRIPT
+    )
+}
+
+/// Package and file names may contain `&`, and `<` in theory.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The links (junctions and symbolic links) to directories under
+/// `packages_dir`, resolved inside the image mounted at `mount_point`, as
+/// `(path relative to packages_dir, directory in the image)`. Links under
+/// the resolved directories are resolved as well.
+///
+/// A link in a backup image or an external clone still holds the path it
+/// had on the machine it was made on (e.g. `C:\XboxGames\...`), which here
+/// leads to the live install or nowhere: such a target is looked up under
+/// `mount_point` instead, without its drive or root. A link that cannot be
+/// resolved in the image is left out with a warning.
+pub fn image_links(packages_dir: &Path, mount_point: &Path) -> Vec<(String, PathBuf)> {
+    let mut links = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(PathBuf::new(), packages_dir.to_path_buf())];
+    while let Some((prefix, dir)) = pending.pop() {
+        for entry in WalkDir::new(&dir)
+            .follow_links(false)
+            .into_iter()
+            .filter_map(|res| res.ok())
+            .filter(|entry| entry.path_is_symlink())
+        {
+            let link = entry.path();
+            let r_path = prefix.join(link.strip_prefix(&dir).unwrap_or(link));
+            let target = match image_target(link, mount_point) {
+                Some(target) => target,
+                None => {
+                    eprintln!("Warning: unable to read the link {:?}, skipped.", link);
+                    continue;
+                }
+            };
+            if target.is_dir() {
+                // A link back to a directory already walked would loop.
+                if visited.insert(fs::canonicalize(&target).unwrap_or_else(|_| target.clone())) {
+                    links.push((r_path.to_string_lossy().to_string(), target.clone()));
+                    pending.push((r_path, target));
+                }
+            } else if target.exists() {
+                eprintln!(
+                    "Warning: the link {:?} is not to a directory, skipped.",
+                    link
+                );
+            } else {
+                eprintln!(
+                    "Warning: the link {:?} points to {:?}, which is not in the image, skipped.",
+                    link, target
+                );
+            }
+        }
+    }
+    links.sort_unstable();
+    links
+}
+
+/// The target of `link`, moved under `mount_point` when it is outside.
+fn image_target(link: &Path, mount_point: &Path) -> Option<PathBuf> {
+    let target = fs::read_link(link).ok()?;
+    let target = if target.is_relative() {
+        link.parent()?.join(target)
+    } else {
+        target
+    };
+    if target.starts_with(mount_point) {
+        return Some(target);
+    }
+    // Drop the drive (`C:`, `\\?\C:`) and the root.
+    let inner: PathBuf = target
+        .components()
+        .filter(|component| !matches!(component, Component::Prefix(_) | Component::RootDir))
+        .collect();
+    Some(mount_point.join(inner))
+}
@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The hashing threads and the result file readers may intern a directory
+/// at the same time; each shard has its own lock.
+const SHARDS: usize = 16;
+
+static DIRS: OnceLock<Vec<Mutex<HashSet<Arc<str>>>>> = OnceLock::new();
+
+thread_local! {
+    /// The files of a directory come one after the other: the directory of
+    /// the previous one is taken without a lock.
+    static LAST_DIR: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
+}
+
+/// The path of a file in the results, the reference, the baseline and the
+/// other result files of the run: its directory (with the package name and
+/// the trailing separator), shared by all the files of that directory in
+/// all of them, and its own name.
+///
+/// A verify run holds the 300k+ paths of an install up to three times, in
+/// about a tenth as many directories.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct RecordPath {
+    dir: Arc<str>,
+    name: Box<str>,
+}
+
+impl RecordPath {
+    /// The whole path.
+    pub fn as_string(&self) -> String {
+        [&*self.dir, &*self.name].concat()
+    }
+
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        match prefix.len().checked_sub(self.dir.len()) {
+            None => self.dir.starts_with(prefix),
+            Some(rest) => {
+                prefix.starts_with(&*self.dir)
+                    && self.name.as_bytes().get(..rest)
+                        == Some(&prefix.as_bytes()[self.dir.len()..])
+            }
+        }
+    }
+
+    /// The package, or the file name of a path without a directory.
+    pub fn first_component(&self) -> &str {
+        match self.dir.split_once(['\\', '/']) {
+            Some((first, _)) => first,
+            None => &self.name,
+        }
+    }
+
+    fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.dir.bytes().chain(self.name.bytes())
+    }
+}
+
+/// The order of the whole paths, not of the directories first.
+impl Ord for RecordPath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if Arc::ptr_eq(&self.dir, &other.dir) {
+            return self.name.cmp(&other.name);
+        }
+        self.bytes().cmp(other.bytes())
+    }
+}
+
+impl PartialOrd for RecordPath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for RecordPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.as_string())
+    }
+}
+
+impl fmt::Debug for RecordPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_string(), f)
+    }
+}
+
+/// Split `path` after its last separator, its directory shared with the
+/// other paths of the run.
+pub fn path(path: &str) -> RecordPath {
+    let split = path.rfind(['\\', '/']).map_or(0, |at| at + 1);
+    let (dir, name) = path.split_at(split);
+    RecordPath {
+        dir: dir_of(dir),
+        name: Box::from(name),
+    }
+}
+
+fn dir_of(dir: &str) -> Arc<str> {
+    let last = LAST_DIR.with_borrow(|last| last.as_ref().filter(|last| ***last == *dir).cloned());
+    if let Some(last) = last {
+        return last;
+    }
+    let shards = DIRS.get_or_init(|| (0..SHARDS).map(|_| Mutex::default()).collect());
+    let mut hasher = DefaultHasher::new();
+    dir.hash(&mut hasher);
+    let mut shard = shards[hasher.finish() as usize % SHARDS].lock().unwrap();
+    let interned = match shard.get(dir) {
+        Some(interned) => interned.clone(),
+        None => {
+            let interned: Arc<str> = Arc::from(dir);
+            shard.insert(interned.clone());
+            interned
+        }
+    };
+    LAST_DIR.set(Some(interned.clone()));
+    interned
+}
+
+/// Forget the directories of a run once it is over: the next ones (`--then`,
+/// `--watch`) start from an empty table. The paths already made keep their
+/// directory, only no longer shared with the new ones.
+pub fn release() {
+    if let Some(shards) = DIRS.get() {
+        for shard in shards {
+            *shard.lock().unwrap() = HashSet::new();
+        }
+    }
+    LAST_DIR.set(None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_after_the_last_separator() {
+        let file = path("fs-base\\scenery\\a.bgl");
+        assert_eq!(&*file.dir, "fs-base\\scenery\\");
+        assert_eq!(&*file.name, "a.bgl");
+        assert_eq!(file.to_string(), "fs-base\\scenery\\a.bgl");
+        assert_eq!(path("layout.json").to_string(), "layout.json");
+        assert!(Arc::ptr_eq(&file.dir, &path("fs-base\\scenery\\b.bgl").dir));
+    }
+
+    #[test]
+    fn orders_as_the_whole_paths() {
+        // `\` sorts after ` `: "a b\c" < "a\z", although "a" < "a b".
+        let mut paths = [path("a\\z"), path("a b\\c"), path("a\\b\\c"), path("a\\b")];
+        paths.sort();
+        let paths: Vec<String> = paths.iter().map(RecordPath::to_string).collect();
+        assert_eq!(paths, ["a b\\c", "a\\b", "a\\b\\c", "a\\z"]);
+    }
+
+    #[test]
+    fn starts_with_across_the_split() {
+        let file = path("@Community\\addon\\a.bgl");
+        assert!(file.starts_with("@"));
+        assert!(file.starts_with("@Community\\"));
+        assert!(file.starts_with("@Community\\addon\\a"));
+        assert!(!file.starts_with("@Community\\addon\\b"));
+        assert!(!file.starts_with("@Community\\addon\\a.bgl.bak"));
+    }
+}
@@ -0,0 +1,27 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::AppError;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch Ctrl+C so that the hashing threads stop and the results so far are
+/// saved. A second Ctrl+C exits immediately.
+pub fn install_handler() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            eprintln!("\nAborted.");
+            process::exit(AppError::INTERRUPTED_EXIT_CODE);
+        }
+        eprintln!(
+            "\nInterrupted, saving the results so far. Press Ctrl+C again to abort immediately."
+        );
+    });
+    if let Err(err) = result {
+        eprintln!("Warning: unable to handle Ctrl+C: {}\n", err);
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,129 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::fileid;
+use crate::filter::PathFilter;
+
+/// Files of a package that are not listed in its own `layout.json`.
+const UNLISTED_FILES: [&str; 2] = ["layout.json", "manifest.json"];
+
+/// Compare every package that has a `layout.json` with the files on disk.
+///
+/// Reports `SIZE` for files whose size differs from the layout, `MISSING`
+/// for listed files that do not exist and `ORPHAN` for files that are not
+/// listed. The dates in the layout are not compared, because the installer
+/// does not preserve them.
+///
+/// Prints one `STATUS<TAB>path` line per finding to the stdout and returns
+/// the number of findings. The links are walked as for the hashes
+/// (`--follow-links`).
+pub fn check_layouts(packages_dir: &Path, filter: &PathFilter, follow_links: bool) -> usize {
+    let mut findings = BTreeMap::new();
+    let mut packages = 0;
+    // Official\OneStore\<package>\layout.json is the deepest layout.
+    for entry in fileid::walk(WalkDir::new(packages_dir).max_depth(3), follow_links) {
+        if entry.file_name() != "layout.json" {
+            continue;
+        }
+        let package_dir = match entry.path().parent() {
+            Some(dir) => dir,
+            None => continue,
+        };
+        let layout = match read_layout(entry.path()) {
+            Some(layout) => layout,
+            None => {
+                eprintln!("Warning: unable to parse {:?}, skipping it.", entry.path());
+                continue;
+            }
+        };
+        packages += 1;
+        check_package(
+            packages_dir,
+            package_dir,
+            &layout,
+            filter,
+            follow_links,
+            &mut findings,
+        );
+    }
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (path, status) in &findings {
+        println!("{}\t{}", status, path);
+        *counts.entry(status).or_default() += 1;
+    }
+    eprintln!(
+        "\nChecked {} package(s) against their layout.json.",
+        packages
+    );
+    for (status, count) in &counts {
+        eprintln!("{:>10}: {}", status, count);
+    }
+    findings.len()
+}
+
+fn check_package(
+    packages_dir: &Path,
+    package_dir: &Path,
+    layout: &HashMap<String, u64>,
+    filter: &PathFilter,
+    follow_links: bool,
+    findings: &mut BTreeMap<String, &'static str>,
+) {
+    let relative_to_root = |path: &Path| match path.strip_prefix(packages_dir) {
+        Ok(r_path) => r_path.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    };
+    let mut listed: HashMap<String, (&str, u64)> = layout
+        .iter()
+        .map(|(path, size)| (path.to_lowercase(), (path.as_str(), *size)))
+        .collect();
+    for entry in fileid::walk(WalkDir::new(package_dir).min_depth(1), follow_links)
+        .filter(|entry| entry.file_type().is_file())
+    {
+        let in_package = match entry.path().strip_prefix(package_dir) {
+            Ok(r_path) => r_path,
+            Err(_) => continue,
+        };
+        let key = in_package.to_string_lossy().replace('\\', "/");
+        let root_path = relative_to_root(entry.path());
+        // Windows paths are case-insensitive, and so are the layouts.
+        let status = match listed.remove(&key.to_lowercase()) {
+            Some((_, size)) => match entry.metadata() {
+                Ok(meta) if meta.len() != size => "SIZE",
+                _ => continue,
+            },
+            None if UNLISTED_FILES.contains(&key.as_str()) => continue,
+            None => "ORPHAN",
+        };
+        if filter.is_match(&root_path) {
+            findings.insert(root_path.to_string_lossy().to_string(), status);
+        }
+    }
+    for (path, _) in listed.into_values() {
+        let root_path = relative_to_root(
+            &path
+                .split('/')
+                .fold(package_dir.to_path_buf(), |dir, name| dir.join(name)),
+        );
+        if filter.is_match(&root_path) {
+            findings.insert(root_path.to_string_lossy().to_string(), "MISSING");
+        }
+    }
+}
+
+/// Read `content[].path` and `content[].size` from a `layout.json`.
+fn read_layout(layout_json: &Path) -> Option<HashMap<String, u64>> {
+    let text = fs::read_to_string(layout_json).ok()?;
+    let json: Value = serde_json::from_str(text.trim_start_matches('\u{feff}')).ok()?;
+    let mut layout = HashMap::new();
+    for item in json.get("content")?.as_array()? {
+        let path = item.get("path")?.as_str()?;
+        let size = item.get("size")?.as_u64()?;
+        layout.insert(path.replace('\\', "/"), size);
+    }
+    Some(layout)
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Component, Path};
+use walkdir::WalkDir;
+
+use crate::fileid;
+
+/// Device names that Windows refuses to open as regular files,
+/// with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Check every file and directory name under `packages_dir` and print the
+/// problems found, grouped by package. Returns the number of problems.
+/// The links are walked as for the hashes (`--follow-links`).
+pub fn lint_packages(packages_dir: &Path, follow_links: bool) -> usize {
+    let mut by_package: BTreeMap<String, Vec<(String, &str)>> = BTreeMap::new();
+    for entry in fileid::walk(WalkDir::new(packages_dir).min_depth(1), follow_links) {
+        let relative_path = match entry.path().strip_prefix(packages_dir) {
+            Ok(r_path) => r_path,
+            Err(_) => entry.path(),
+        };
+        let package = match relative_path.components().next() {
+            Some(Component::Normal(name)) => name.to_string_lossy().to_string(),
+            _ => String::new(),
+        };
+        for problem in check_name(entry.file_name()) {
+            by_package
+                .entry(package.clone())
+                .or_default()
+                .push((relative_path.to_string_lossy().to_string(), problem));
+        }
+    }
+
+    let mut total = 0;
+    for (package, problems) in &by_package {
+        println!("{}", package);
+        for (path, problem) in problems {
+            println!("    {}\t{}", problem, path);
+        }
+        total += problems.len();
+    }
+    eprintln!(
+        "\n{} problem(s) found in {} package(s).",
+        total,
+        by_package.len()
+    );
+    total
+}
+
+/// Return the reasons why a single file or directory name is problematic.
+fn check_name(name: &OsStr) -> Vec<&'static str> {
+    let mut problems = Vec::new();
+    let name = match name.to_str() {
+        Some(name) => name,
+        None => {
+            problems.push("not valid Unicode");
+            return problems;
+        }
+    };
+    if name.ends_with(' ') {
+        problems.push("trailing space");
+    }
+    if name.ends_with('.') {
+        problems.push("trailing dot");
+    }
+    if name.starts_with(' ') {
+        problems.push("leading space");
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        problems.push("reserved device name");
+    }
+    if name.chars().any(|c| c as u32 > 0xFFFF) {
+        problems.push("character outside the BMP");
+    }
+    if name.chars().any(|c| c.is_control()) {
+        problems.push("control character");
+    }
+    if name.contains(['<', '>', ':', '"', '|', '?', '*', '\\']) {
+        problems.push("character not allowed on Windows");
+    }
+    problems
+}
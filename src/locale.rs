@@ -0,0 +1,124 @@
+use std::sync::OnceLock;
+
+/// The separators of the numbers written for people: progress, summaries,
+/// statistics and counts on the stderr. The result files, the JSON and the
+/// other formats read by programs never use them.
+#[derive(Clone, Copy, Debug)]
+struct NumberFormat {
+    /// Between the groups of three digits; none in the `C` locale.
+    group: Option<char>,
+    decimal: char,
+}
+
+/// Numbers as Rust writes them, for the `C` locale and the unknown ones.
+const PLAIN: NumberFormat = NumberFormat {
+    group: None,
+    decimal: '.',
+};
+
+static FORMAT: OnceLock<NumberFormat> = OnceLock::new();
+
+/// Use the separators of `locale` (`--locale`, e.g. `de-DE` or `fr_FR`)
+/// rather than those of the user's locale. Only the first call counts.
+pub fn init(locale: Option<&str>) {
+    FORMAT.get_or_init(|| match locale {
+        Some(locale) => from_name(locale),
+        None => system_locale().map_or(PLAIN, |locale| from_name(&locale)),
+    });
+}
+
+fn current() -> NumberFormat {
+    *FORMAT.get_or_init(|| system_locale().map_or(PLAIN, |locale| from_name(&locale)))
+}
+
+/// `n` with its digits grouped by three, e.g. `1,234,567` or `1.234.567`.
+pub fn number(n: u64) -> String {
+    group_digits(&n.to_string(), current().group)
+}
+
+/// `x` with `precision` decimals, e.g. `1,234.5` or `1.234,5`.
+pub fn decimal(x: f64, precision: usize) -> String {
+    let format = current();
+    let text = format!("{:.*}", precision, x);
+    let (integer, fraction) = match text.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (text.as_str(), None),
+    };
+    let (sign, digits) = match integer.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", integer),
+    };
+    let mut formatted = format!("{}{}", sign, group_digits(digits, format.group));
+    if let Some(fraction) = fraction {
+        formatted.push(format.decimal);
+        formatted.push_str(fraction);
+    }
+    formatted
+}
+
+fn group_digits(digits: &str, group: Option<char>) -> String {
+    let group = match group {
+        Some(group) => group,
+        None => return digits.to_string(),
+    };
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(group);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// The separators of a locale name such as `de-DE`, `de_CH.UTF-8` or `C`.
+fn from_name(name: &str) -> NumberFormat {
+    let name = name
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('_', "-")
+        .to_ascii_lowercase();
+    let (language, region) = name.split_once('-').unwrap_or((&name, ""));
+    let (group, decimal) = match (language, region) {
+        ("de" | "it", "ch" | "li") => ('\u{2019}', '.'),
+        ("es", "mx" | "us") => (',', '.'),
+        ("pt", "pt") => ('\u{a0}', ','),
+        ("en" | "ja" | "zh" | "ko" | "he" | "th" | "hi" | "ms", _) => (',', '.'),
+        (
+            "de" | "nl" | "it" | "es" | "pt" | "da" | "tr" | "id" | "el" | "ro" | "hr" | "sl"
+            | "sr",
+            _,
+        ) => ('.', ','),
+        (
+            "fr" | "ru" | "pl" | "cs" | "sk" | "fi" | "sv" | "nb" | "nn" | "no" | "uk" | "hu"
+            | "bg" | "lt" | "lv" | "et",
+            _,
+        ) => ('\u{a0}', ','),
+        _ => return PLAIN,
+    };
+    NumberFormat {
+        group: Some(group),
+        decimal,
+    }
+}
+
+/// The locale of the user's regional settings.
+#[cfg(target_os = "windows")]
+fn system_locale() -> Option<String> {
+    use windows::Win32::Globalization::GetUserDefaultLocaleName;
+    let mut buffer = [0u16; 85];
+    // The length counts the terminating null; 0 on failure.
+    let len = unsafe { GetUserDefaultLocaleName(&mut buffer) } as usize;
+    (len > 1).then(|| String::from_utf16_lossy(&buffer[..len - 1]))
+}
+
+/// The locale of the numbers, from the environment as the C library reads
+/// it.
+#[cfg(not(target_os = "windows"))]
+fn system_locale() -> Option<String> {
+    ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|locale| !locale.is_empty())
+}
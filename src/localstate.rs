@@ -0,0 +1,112 @@
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::settings::format_timestamp;
+
+/// Parts of the names of the files known to get corrupted, usually when the
+/// sim crashes or the disk fills up while it saves them.
+const FRAGILE_NAMES: [&str; 3] = ["logbook", "wishlist", "content"];
+
+/// Check that the fragile LocalState files (logbook, wishlist, content cache)
+/// are still readable, and list them with their size and modification time.
+///
+/// Prints one `STATUS<TAB>size<TAB>modified<TAB>path` line per file to the
+/// stdout and returns the number of damaged files.
+pub fn check_local_state(usercfg: &Path) -> usize {
+    let config_dir = match usercfg.parent() {
+        Some(dir) => dir,
+        None => return 0,
+    };
+    // MS Store: LocalState next to LocalCache. Steam: next to UserCfg.opt.
+    let dirs = [
+        config_dir.with_file_name("LocalState"),
+        config_dir.to_path_buf(),
+    ];
+    let mut files: Vec<PathBuf> = dirs
+        .iter()
+        .filter(|dir| dir.is_dir())
+        .flat_map(|dir| {
+            WalkDir::new(dir)
+                .max_depth(2)
+                .into_iter()
+                .filter_map(|res| res.ok())
+                .filter(|entry| entry.file_type().is_file() && is_fragile(entry.file_name()))
+                .map(|entry| entry.into_path())
+        })
+        .collect();
+    files.sort_unstable();
+    files.dedup();
+
+    let mut damaged = 0;
+    for file in &files {
+        let (size, modified) = match file.metadata() {
+            Ok(meta) => (
+                meta.len(),
+                meta.modified()
+                    .map_or(String::from("unknown"), format_timestamp),
+            ),
+            Err(_) => (0, String::from("unknown")),
+        };
+        let status = check_file(file);
+        if status != "OK" {
+            damaged += 1;
+        }
+        println!(
+            "{}\t{:10}\t{}\t{}",
+            status,
+            size,
+            modified,
+            file.to_string_lossy()
+        );
+    }
+    eprintln!(
+        "\nChecked {} LocalState file(s), {} damaged.",
+        files.len(),
+        damaged
+    );
+    damaged
+}
+
+fn is_fragile(name: &std::ffi::OsStr) -> bool {
+    let name = name.to_string_lossy().to_ascii_lowercase();
+    FRAGILE_NAMES.iter().any(|fragile| name.contains(fragile))
+}
+
+/// A basic check only: the file can be read, is not empty or zero-filled,
+/// and JSON / XML files look complete.
+fn check_file(file: &Path) -> &'static str {
+    let data = match fs::read(file) {
+        Ok(data) => data,
+        Err(_) => return "UNREADABLE",
+    };
+    if data.is_empty() {
+        return "EMPTY";
+    }
+    // A crash during the save typically leaves a file full of zeros.
+    if data.iter().all(|&byte| byte == 0) {
+        return "ZEROED";
+    }
+    let extension = file
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    let text = || {
+        String::from_utf8_lossy(&data)
+            .trim_start_matches('\u{feff}')
+            .trim()
+            .to_string()
+    };
+    match extension.as_deref() {
+        Some("json") if serde_json::from_str::<Value>(&text()).is_err() => "MALFORMED",
+        Some("xml") => {
+            let text = text();
+            if text.starts_with('<') && text.ends_with('>') {
+                "OK"
+            } else {
+                "MALFORMED"
+            }
+        }
+        _ => "OK",
+    }
+}
@@ -0,0 +1,63 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+/// `path` in the extended-length form of Windows (`\\?\D:\...`,
+/// `\\?\UNC\server\share\...`), for the calls that open a file or read its
+/// metadata.
+///
+/// The standard library only switches to this form for the paths longer
+/// than `MAX_PATH`, and Windows drops the trailing dots and spaces of the
+/// names of the other paths, so such a file of an addon (see `lint`) cannot
+/// be opened at all. A path already in this form is left as is.
+#[cfg(target_os = "windows")]
+pub fn extended(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, PathBuf, Prefix};
+    let absolute = if path.is_absolute() {
+        Cow::Borrowed(path)
+    } else {
+        match std::env::current_dir() {
+            Ok(dir) => Cow::Owned(dir.join(path)),
+            Err(_) => return Cow::Borrowed(path),
+        }
+    };
+    // `D:file`, relative to the current directory of drive D.
+    if !absolute.is_absolute() {
+        return Cow::Borrowed(path);
+    }
+    let mut components = absolute.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut unc = OsString::from(r"\\?\UNC\");
+                unc.push(server);
+                unc.push(r"\");
+                unc.push(share);
+                PathBuf::from(unc)
+            }
+            // Already verbatim, or a device.
+            _ => return Cow::Borrowed(path),
+        },
+        _ => return Cow::Borrowed(path),
+    };
+    // The verbatim form is taken literally: `.` and `..` are resolved here.
+    for component in components {
+        match component {
+            Component::RootDir => extended.push(r"\"),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                extended.pop();
+            }
+            Component::Normal(name) => extended.push(name),
+            Component::Prefix(_) => {}
+        }
+    }
+    Cow::Owned(extended)
+}
+
+/// Elsewhere a path has no length limit to work around.
+#[cfg(not(target_os = "windows"))]
+pub fn extended(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
@@ -1,172 +1,3858 @@
-use clap::Parser;
-use dirs::{data_dir, data_local_dir};
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Read, Result as IoResult, Write};
+mod antivirus;
+mod archive;
+mod budget;
+mod bundle;
+mod cache;
+mod compress;
+mod config;
+mod discovery;
+mod duplicates;
+mod error;
+mod exitsummary;
+mod explain;
+mod fileid;
+mod filter;
+mod hasher;
+mod history;
+mod hooks;
+mod html;
+mod image;
+mod intern;
+mod interrupt;
+mod layout;
+mod lint;
+mod locale;
+mod localstate;
+mod longpath;
+mod manifest;
+mod markdown;
+mod network;
+mod normalize;
+mod notify;
+mod output;
+mod progress;
+mod provenance;
+mod quick;
+mod redact;
+mod remote;
+mod rollingcache;
+mod roots;
+mod schedule;
+mod selfcheck;
+mod session;
+mod settings;
+mod signature;
+mod snapshot;
+mod spotcheck;
+mod stats;
+mod steam;
+mod summary;
+mod template;
+mod textdiff;
+mod usercfg;
+mod verify;
+mod watch;
+
+use budget::Coverage;
+use cache::{modified_nanos, save_cache, unix_secs, HashCache};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use compress::{Compression, OutputFile};
+use crossbeam_channel::bounded;
+use discovery::{Strategy, STEAM_MSFS_DIR_NAME, STORE_MSFS_DIR_NAME};
+use error::AppError;
+use filter::PathFilter;
+use hasher::{Algorithm, ChunkHasher, Digest, ExtraHashes, Hasher};
+use intern::RecordPath;
+use memmap2::Mmap;
+use network::IoProfile;
+use output::{
+    GroupBy, LineEnding, Live, LiveOutput, OutputFormat, RelativeTo, SortKey, TextStyle,
+    OUTPUT_BUFFER_SIZE,
+};
+use progress::Progress;
+use provenance::Provenance;
+use quick::QuickScan;
+use redact::Redactor;
+use rollingcache::RollingCache;
+use serde_json::json;
+use session::Session;
+use snapshot::SnapshotStore;
+use stats::RunStats;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{
+    stderr, stdin, stdout, BufWriter, Error as IoError, ErrorKind, IsTerminal, Read,
+    Result as IoResult, Seek, SeekFrom, Write,
+};
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Mutex;
 use std::thread;
-use walkdir::WalkDir;
-use xxhash_rust::xxh3;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use template::Template;
+use usercfg::UserCfg;
+use walkdir::{DirEntry, WalkDir};
 
 /// xxhash checksum for MSFS 2020 data files
 #[derive(Parser, Debug)]
-#[clap(version, about)]
+#[clap(version, about, after_help = AFTER_HELP, disable_version_flag = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Print version
+    /// (With `--verbose`, also print the platform and the selected SIMD implementation)
+    #[clap(short = 'V', long)]
+    version: bool,
+
+    /// Print the man page (roff) of the tool, e.g.
+    /// `--generate-manpage > msfs2020checksum.1`
+    #[clap(long)]
+    generate_manpage: bool,
+
+    /// Print more details
+    #[clap(long, global = true)]
+    verbose: bool,
+
     /// Force use this path as the `UserCfg.opt` file
-    #[clap(short = 'c', long = "config")]
+    #[clap(short = 'c', long = "config", global = true)]
     cfgfile: Option<PathBuf>,
 
     /// Force use this path as the `InstalledPackagesPath` directory
-    /// (Setting this argument will ignore the `config` argument)
-    #[clap(short = 'P', long = "packages")]
-    packages: Option<PathBuf>,
+    /// (Setting this argument will ignore the `config` argument). May be
+    /// repeated: the other directories are hashed as roots named after their
+    /// folder, e.g. `@Community`. Taken from `MSFS_PACKAGES_DIR` when not
+    /// given (and `MSFS_USERCFG` is tried first for `UserCfg.opt`)
+    #[clap(
+        short = 'P',
+        long = "packages",
+        env = PACKAGES_DIR_VAR,
+        global = true
+    )]
+    packages: Vec<PathBuf>,
 
-    /// The number of multi-threaded parallelism
-    /// (0 means the number of CPU threads)
-    #[clap(short = 'T', long, default_value_t = 0)]
-    threads: usize,
+    /// Hash `--packages` as given, even when it is the `OneStore` / `Steam`
+    /// folder or the `InstalledPackagesPath` instead of `Official`
+    #[clap(long, requires = "packages", global = true)]
+    exact_packages: bool,
+
+    /// Do not look for `UserCfg.opt` this way (May be repeated, or
+    /// comma-separated; `find-install --verbose` lists the ways tried)
+    #[clap(
+        long,
+        value_enum,
+        value_name = "STRATEGY",
+        value_delimiter = ',',
+        global = true
+    )]
+    skip_discovery: Vec<Strategy>,
+
+    /// Record the run and the result of every file in this SQLite database
+    /// (created if needed), for the `history` command
+    #[clap(long, global = true)]
+    db: Option<PathBuf>,
+
+    /// Match the paths with those of the reference (or snapshot) exactly.
+    /// On Windows, paths that differ only by case match by default
+    #[clap(long, global = true)]
+    case_sensitive: bool,
+
+    /// Write the numbers of the progress, summaries and statistics with the
+    /// separators of this locale (e.g. `de-DE`, `en-US`, `C` for none)
+    /// instead of those of the regional settings; the result files and the
+    /// JSON never use them
+    #[clap(long, value_name = "LOCALE", global = true)]
+    locale: Option<String>,
+
+    /// Write how the command ended (exit code, error code and message) and
+    /// the totals of its hashing as JSON to this file, on failure too, for
+    /// the scripts that run the tool on many PCs
+    #[clap(long, value_name = "FILE", global = true)]
+    summary_json: Option<PathBuf>,
+
+    /// Cache file of a previous run; files whose size and modification time
+    /// are unchanged reuse the cached hash instead of being read again
+    #[clap(long, global = true)]
+    cache: Option<PathBuf>,
+
+    /// Line ending of the result files and of the files written by `fmt`
+    #[clap(long, value_enum, default_value_t = LineEnding::Crlf, global = true)]
+    line_ending: LineEnding,
 
+    /// Start the result files and the files written by `fmt` with a UTF-8
+    /// byte order mark, so that Excel shows non-ASCII paths right
+    #[clap(long, global = true)]
+    bom: bool,
+
+    /// Run this command when the run is over, whatever its result
+    /// (`{report}`, `{summary}` and `{exit_code}` are replaced in its arguments)
+    #[clap(long, value_name = "COMMAND", global = true)]
+    on_complete: Option<String>,
+
+    /// Run this command when verification finds problems
+    /// (`{report}`, `{summary}` and `{exit_code}` are replaced in its arguments)
+    #[clap(long, value_name = "COMMAND", global = true)]
+    on_mismatch: Option<String>,
+
+    /// Report the end of the run, its result, counts and duration:
+    /// `webhook=<URL>` POSTs the `--summary-json` document (with a `text`
+    /// line for chat webhooks), `toast` shows a desktop notification
+    /// (May be repeated)
+    #[clap(long, value_name = "TARGET", value_parser = notify::parse_target, global = true)]
+    notify: Vec<notify::Target>,
+
+    #[clap(flatten)]
+    read: ReadArgs,
+
+    #[clap(flatten)]
+    hash: HashArgs,
+}
+
+/// Arguments of hashing into a result file: the `hash` command, which is
+/// also what runs without a command.
+#[derive(clap::Args, Debug)]
+struct HashArgs {
     /// Path for the output file
     /// (If the file already exists it will be overwritten)
     #[clap(short = 'o', long)]
     output: Option<PathBuf>,
+
+    /// Format of the hash results
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Print the file count, total size and combined digest of every package
+    /// (Also included in the JSON output)
+    #[clap(long)]
+    summary: bool,
+
+    /// List the sets of identical files (same hash and size) and the bytes
+    /// their extra copies take
+    #[clap(long)]
+    find_duplicates: bool,
+
+    /// Write the results of every `--root` to its own output file
+    /// (`<output>.<NAME>.<ext>`), with its own summary
+    #[clap(long, requires = "output")]
+    split_roots: bool,
+
+    /// Hide the user name, the home directory and the drive letters in the
+    /// emitted paths, to post the results in public
+    #[clap(long)]
+    redact_paths: bool,
+
+    /// Also replace the Community package names with stable aliases
+    #[clap(long, requires = "redact_paths", conflicts_with = "authoritative")]
+    redact_community: bool,
+
+    /// Mark the output file as an authoritative reference and record its
+    /// provenance (sim build, store type, package versions) in the header
+    #[clap(long, requires = "output")]
+    authoritative: bool,
+
+    /// Instead of hashing, compare every package with its `layout.json`
+    /// and report files of the wrong size, missing files and orphan files
+    #[clap(long, conflicts_with = "output")]
+    check_layout: bool,
+
+    /// Also hash the live install (found as without `--packages`) and list
+    /// the files of `--packages` that differ from it
+    #[clap(long, requires = "packages")]
+    compare_live: bool,
+
+    /// Only list the size and modification time of every file, without
+    /// reading them: a quick scan to `diff` with an earlier one, or to give
+    /// to `--changed-since`
+    #[clap(long, conflicts_with_all = ["format", "summary", "authoritative", "find_duplicates", "check_layout", "compare_live", "time_budget"])]
+    quick: bool,
+
+    /// Only hash the files whose size or modification time differs from
+    /// this quick scan, or that it does not list
+    #[clap(long, value_name = "QUICK_SCAN", conflicts_with = "quick")]
+    changed_since: Option<PathBuf>,
+
+    /// Hash exactly the files listed on the stdin, one per line, instead of
+    /// walking the packages directory (e.g. from `fd`, or the paths of a
+    /// `verify` run). Relative paths are under the packages directory
+    #[clap(long, conflicts_with_all = ["quick", "changed_since", "compare_live", "check_rolling_cache"])]
+    stdin: bool,
+
+    /// The paths on the stdin are separated by NUL characters
+    /// (`fd -0`, `rg --files --null`)
+    #[clap(long, requires = "stdin")]
+    null: bool,
+
+    /// Check the rolling cache configured in `UserCfg.opt`
+    /// (`ROLLINGCACHE.CCC`: its size against the configured one, its index)
+    /// and hash it instead of the packages (add `--chunk-size` to hash it in
+    /// chunks)
+    #[clap(long, conflicts_with_all = ["packages", "quick", "changed_since", "compare_live", "include_config", "roots"])]
+    check_rolling_cache: bool,
+
+    /// Stop starting new files after this long (e.g. `30m`, `1h30m`): the
+    /// cached files come first, then the files never hashed, then the most
+    /// recently modified ones; the coverage is reported at the end
+    #[clap(long, value_name = "DURATION", value_parser = budget::parse_duration)]
+    time_budget: Option<Duration>,
+
+    /// Base of the paths in the results
+    #[clap(long, value_enum, default_value_t = RelativeTo::Packages)]
+    relative_to: RelativeTo,
+
+    /// Write `/` as the path separator, as on Linux and in a Steam Proton
+    /// prefix, so that results of both systems can be compared
+    #[clap(long)]
+    forward_slashes: bool,
+
+    /// Write the paths in lowercase, so that results of installs whose
+    /// folder names differ only by case can be compared (Windows paths are
+    /// case-insensitive)
+    #[clap(long)]
+    lowercase_paths: bool,
+
+    /// Order of the entries in the output file (`size` lists the largest
+    /// files first)
+    #[clap(long, value_enum, default_value_t = SortKey::Path)]
+    sort: SortKey,
+
+    /// Keep the entries of each group together, each group sorted by `--sort`
+    #[clap(long, value_enum, value_name = "GROUP")]
+    group_by: Option<GroupBy>,
+
+    /// After the entries of each group, write a
+    /// `# subtotal: files<TAB>bytes<TAB>group` line
+    #[clap(long, requires = "group_by")]
+    subtotals: bool,
+
+    /// Write each text entry as this line instead, with the placeholders
+    /// {hash}, {size}, {path}, {mtime} (Unix seconds) and {package}, and no
+    /// `#` lines: e.g. "{hash}  {path}" as `sha256sum` and `xxhsum` do
+    #[clap(long, value_parser = template::parse_template, conflicts_with = "subtotals")]
+    template: Option<Template>,
+
+    /// Compress the output file; `-o` names ending in `.gz` or `.zst` are
+    /// compressed without it. `verify` and `diff` read such files as they are
+    #[clap(long, value_enum, requires = "output")]
+    compress: Option<Compression>,
+
+    /// Sign the output file with this ed25519 private key (a PKCS#8 PEM
+    /// file, or one holding the 64 hex digits of the key): a last
+    /// `# signature:` line, checked by `verify --require-signature`
+    #[clap(
+        long,
+        value_name = "KEY_FILE",
+        requires = "output",
+        conflicts_with = "template"
+    )]
+    sign: Option<PathBuf>,
+
+    /// Keep running once hashed and hash again the files of the packages
+    /// directory that change, are added or removed (e.g. while the sim
+    /// updates), rewriting the output file after each batch, until Ctrl+C
+    #[clap(
+        long,
+        requires = "output",
+        conflicts_with_all = ["quick", "changed_since", "stdin", "compare_live", "split_roots", "time_budget", "check_rolling_cache"]
+    )]
+    watch: bool,
+
+    /// Do not fail when less than half the files of the previous run (the
+    /// output file being overwritten, or the cache file) are found
+    #[clap(long)]
+    allow_few_files: bool,
+}
+
+/// Arguments of reading the files, taken by the commands that hash them:
+/// `hash` (and the bare invocation), `verify`, `compare` and `snapshot save`.
+#[derive(clap::Args, Debug)]
+struct ReadArgs {
+    /// The packages directory is on a backup image or an external clone
+    /// mounted at this directory: its junctions and symbolic links are
+    /// resolved inside the image instead of on this machine
+    #[clap(long, value_name = "MOUNT_POINT", requires = "packages")]
+    image: Option<PathBuf>,
+
+    /// Walk into the junctions and symbolic links met in the packages
+    /// directories, each linked directory once (Off by default: addon
+    /// linkers may link a folder twice, or into itself)
+    #[clap(long, value_name = "BOOL", default_value_t = false, action = clap::ArgAction::Set, conflicts_with = "image")]
+    follow_links: bool,
+
+    /// Also hash this directory, recorded as a named root (e.g.
+    /// `--root Community=D:\MSFS\Community`); its files are listed under `@NAME`
+    /// (May be repeated)
+    #[clap(long = "root", value_name = "NAME=PATH")]
+    roots: Vec<String>,
+
+    /// The number of multi-threaded parallelism
+    /// (0 means the number of CPU threads)
+    #[clap(short = 'T', long, default_value_t = 0)]
+    threads: usize,
+
+    /// Read settings: `network` (few threads, no `--mmap`, more `--retries`)
+    /// suits SMB and NFS shares and is selected by `auto` on such a share
+    #[clap(long, value_enum, default_value_t = IoProfile::Auto)]
+    io_profile: IoProfile,
+
+    /// For a machine that already misbehaves: one thread, a small read
+    /// buffer, no memory mapping, no links followed, more retries, and no
+    /// cache, database, archives, `--tree` or statistics upload. Slow, but
+    /// asks the least of the system
+    #[clap(long)]
+    safe_mode: bool,
+
+    /// Read buffer of each thread, in MiB
+    /// (By default 256 MiB, halved until it fits in the available memory)
+    #[clap(long, value_name = "MiB", value_parser = clap::value_parser!(u64).range(1..=MAX_MIB))]
+    buffer_size: Option<u64>,
+
+    /// Upper limit of the memory used by all the read buffers, in MiB
+    #[clap(long, value_name = "MiB", value_parser = clap::value_parser!(u64).range(1..=MAX_MIB))]
+    max_memory: Option<u64>,
+
+    /// Also hash the sim settings files: `UserCfg.opt`, `Content.xml` and the
+    /// locally stored controls profiles (listed under `@config`)
+    #[clap(long)]
+    include_config: bool,
+
+    /// Read a file that fails to read (e.g. locked by the running sim) again
+    /// up to this many times, waiting a little longer before each attempt
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Memory-map the files larger than the read buffer instead of reading
+    /// them through it, which allows a much smaller buffer
+    #[clap(long)]
+    mmap: bool,
+
+    /// Also open the `.zip` files found and hash every file inside them,
+    /// listed as `Addon.zip!path\inside`
+    #[clap(long)]
+    archives: bool,
+
+    /// Hash the files reached through several paths (hard links, junctions
+    /// of an addon linker) once; the other paths get the same hashes and are
+    /// listed as aliases of the first one
+    #[clap(long)]
+    dedupe_links: bool,
+
+    /// The sim may be downloading or updating files during the scan: hash
+    /// again, at the end, the files modified while the scan was running
+    #[clap(long)]
+    tolerate_activity: bool,
+
+    /// Also hash the files larger than this, in MiB, chunk by chunk, so that
+    /// `verify` can tell which byte ranges of a huge file differ
+    /// (`verify` uses the chunk size of the reference)
+    #[clap(long, value_name = "MiB", value_parser = clap::value_parser!(u64).range(1..=MAX_MIB))]
+    chunk_size: Option<u64>,
+
+    /// Triage: hash only the first and last MiB given of the files larger
+    /// than twice that, and their size; the result file says so and such
+    /// digests only match those of another sampled run
+    /// (`verify` samples as the reference did)
+    #[clap(long, value_name = "MiB", value_parser = clap::value_parser!(u64).range(1..=MAX_MIB), conflicts_with_all = ["chunk_size", "cache", "resume", "db"])]
+    sample: Option<u64>,
+
+    /// When `--packages` is a single file, hash it in leaves of this many
+    /// MiB on every thread, then hash the digests of the leaves: a tree
+    /// digest, marked so in the result file, that only matches another run
+    /// with the same leaf size (`verify` uses the leaf size of the reference)
+    #[clap(long, value_name = "MiB", value_parser = clap::value_parser!(u64).range(1..=MAX_MIB), conflicts_with_all = ["chunk_size", "sample", "cache", "resume", "db"])]
+    tree: Option<u64>,
+
+    /// Opt-in: POST anonymized statistics of the run (file count, total
+    /// size, read error rate, sim build; never paths or package names) as
+    /// JSON to this URL
+    #[clap(long, value_name = "URL")]
+    submit_stats: Option<String>,
+
+    /// Print the file count, bytes, bytes read, files reused from the cache,
+    /// elapsed time, read throughput, bytes read per thread and read error
+    /// count of the hashing to the stderr
+    #[clap(long)]
+    stats: bool,
+
+    /// Write these statistics as JSON to this file
+    #[clap(long, value_name = "FILE")]
+    stats_file: Option<PathBuf>,
+
+    /// Only hash files whose relative path matches this glob
+    /// (May be repeated, e.g. `--include "fs-base*/**"`)
+    #[clap(long)]
+    include: Vec<String>,
+
+    /// Skip files whose relative path matches this glob
+    /// (May be repeated, e.g. `--exclude "**/*.wasm"`)
+    #[clap(long)]
+    exclude: Vec<String>,
+
+    /// Skip the files the sim rewrites without the install changing (content
+    /// history, marketplace licenses, partial downloads); `verify` does not
+    /// expect them either
+    #[clap(long)]
+    skip_volatile: bool,
+
+    /// Do not use the cache file at all
+    #[clap(long, requires = "cache", conflicts_with = "refresh")]
+    no_cache: bool,
+
+    /// Ignore the cached hashes and re-hash all files, then rewrite the cache
+    #[clap(long, requires = "cache")]
+    refresh: bool,
+
+    /// Re-hash the cached files read longer ago than this (e.g. `30d`), even
+    /// if unchanged, so that repeated `--time-budget` runs cover everything
+    #[clap(long, value_name = "DURATION", value_parser = budget::parse_duration, requires = "cache")]
+    max_staleness: Option<Duration>,
+
+    /// Checkpoint saved by an interrupted run; the files it lists are not
+    /// read again if they are unchanged
+    #[clap(long, value_name = "CHECKPOINT")]
+    resume: Option<PathBuf>,
+
+    /// Hash algorithm; several comma-separated ones are computed in a single
+    /// read of each file, one column each
+    /// (xxh3-128 by default; `verify` uses the algorithm of the reference)
+    #[clap(short = 'a', long, value_enum, value_delimiter = ',')]
+    algorithm: Vec<Algorithm>,
+
+    /// Sim build to record instead of the detected one
+    /// (The version of `fs-base` is used by default)
+    #[clap(long)]
+    sim_build: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Hash the files into a result file (the default without a command)
+    Hash {
+        #[clap(flatten)]
+        hash: HashArgs,
+
+        #[clap(flatten)]
+        read: Box<ReadArgs>,
+    },
+
+    /// Report file names that are known to break the sim or addon tools
+    /// (trailing spaces, reserved device names, characters outside the BMP)
+    Lint {
+        /// Walk into the junctions and symbolic links, each linked directory
+        /// once, as `hash --follow-links` does
+        #[clap(long, value_name = "BOOL", default_value_t = false, action = clap::ArgAction::Set)]
+        follow_links: bool,
+    },
+
+    /// Hash the files on disk and compare them with a reference result file
+    Verify {
+        /// Result file of the expected (e.g. freshly updated) sim version
+        #[clap(long, required_unless_present = "remote")]
+        reference: Option<PathBuf>,
+
+        /// Download the reference from this URL (a published result file),
+        /// kept in the local app data and downloaded again only when the
+        /// server says it changed (ETag)
+        #[clap(long, value_name = "URL", conflicts_with = "reference")]
+        remote: Option<String>,
+
+        /// Result file of the previous sim version, to tell files changed by
+        /// the official update from locally corrupted ones
+        #[clap(long)]
+        baseline: Option<PathBuf>,
+
+        /// The reference comes from the other store type (MS Store / Steam):
+        /// ignore the `OneStore` / `Steam` folder and the packages that only
+        /// one side has
+        #[clap(long)]
+        cross_store: bool,
+
+        /// Only hash a random sample of this share of the reference files
+        /// (e.g. `5%`), for a quick check between full ones
+        #[clap(long, value_name = "PERCENT", value_parser = spotcheck::parse_percent, conflicts_with = "cross_store")]
+        spot_check: Option<f64>,
+
+        /// Seed of the `--spot-check` sample, to check the same files again
+        #[clap(long, requires = "spot_check")]
+        seed: Option<u64>,
+
+        /// Show what changed in the differing `.cfg`, `.xml` and `.json`
+        /// files since this snapshot, saved with `snapshot save --keep-text`
+        #[clap(long, value_name = "SNAPSHOT")]
+        show_diff: Option<String>,
+
+        /// Directory of the snapshot store of `--show-diff`
+        /// [default: `msfs2020checksum\snapshots` in the local app data]
+        #[clap(long, requires = "show_diff")]
+        store: Option<PathBuf>,
+
+        /// Hash only the files whose size or modification time changed since
+        /// the last `--fast` verification, and those that did not match then;
+        /// the others are taken as unchanged. Uses the `--cache` file, by
+        /// default `REFERENCE.cache` next to the reference
+        #[clap(long, conflicts_with_all = ["spot_check", "no_cache", "refresh"])]
+        fast: bool,
+
+        /// Also write the findings as a self-contained HTML page, the
+        /// problems highlighted, or as Markdown to a `.md` file
+        #[clap(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+
+        /// Refuse a reference that is not signed (`--sign`) with this
+        /// ed25519 public key: 64 hex digits, or a file holding them or the
+        /// PEM public key
+        #[clap(long, value_name = "PUBKEY")]
+        require_signature: Option<String>,
+
+        #[clap(flatten)]
+        read: Box<ReadArgs>,
+    },
+
+    /// Hash two directories in one run (e.g. `Official` and a backup copy
+    /// of it) and list the files that differ or exist on one side only,
+    /// without result files
+    Compare {
+        /// The first directory (used instead of `--packages`)
+        dir_a: PathBuf,
+
+        /// The second directory
+        dir_b: PathBuf,
+
+        #[clap(flatten)]
+        read: Box<ReadArgs>,
+    },
+
+    /// List the files changed, added and removed from one result file to
+    /// another, without hashing
+    Diff {
+        /// The older result file
+        old: PathBuf,
+
+        /// The newer result file
+        new: PathBuf,
+
+        /// Also write the differences as a self-contained HTML page, or as
+        /// Markdown to a `.md` file
+        #[clap(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+    },
+
+    /// Rewrite a result file the way this version writes them (entries
+    /// sorted by path, one path separator, CRLF line endings, the header
+    /// lines in a fixed order), to compare result files with text tools
+    Fmt {
+        /// The result file
+        file: PathBuf,
+
+        /// Path for the rewritten file (the stdout by default; may be the
+        /// result file itself)
+        #[clap(short = 'o', long)]
+        output: Option<PathBuf>,
+
+        /// Write `/` as the path separator instead of `\`
+        #[clap(long)]
+        forward_slashes: bool,
+    },
+
+    /// Copy the sim settings, controls profiles and logbook files into a new
+    /// timestamped directory, with a checksum file
+    BackupSettings {
+        /// Directory to create the backup in
+        dest: PathBuf,
+    },
+
+    /// Check a backup made by `backup-settings` against its checksum file
+    VerifySettings {
+        /// The `msfs-settings-*` directory of the backup
+        backup: PathBuf,
+    },
+
+    /// Check that the logbook, wishlist and content cache files of the sim
+    /// are still readable, and list their sizes and modification times
+    CheckLocalState,
+
+    /// Explain a result status or an error code and what to do about it
+    /// (Without a topic, list the topics)
+    Explain {
+        /// A topic, a result status (e.g. `CORRUPTED`) or an error code
+        topic: Option<String>,
+    },
+
+    /// Hash this executable and compare it with the SHA-256 published with
+    /// the release (the `.sha256` file next to it, or `--expected`)
+    SelfCheck {
+        /// The expected SHA-256, or the `.sha256` file of the release
+        #[clap(long)]
+        expected: Option<String>,
+    },
+
+    /// Print where the sim is installed, as found without `--packages`:
+    /// `UserCfg.opt`, `InstalledPackagesPath`, the store and the sizes of
+    /// `Official` and `Community`, without hashing
+    FindInstall {
+        /// Count the files of the junctions and symbolic links, each linked
+        /// directory once, as `hash --follow-links` does
+        #[clap(long, value_name = "BOOL", default_value_t = false, action = clap::ArgAction::Set)]
+        follow_links: bool,
+    },
+
+    /// List the files of the `--cache` file read the longest time ago, and
+    /// how recently the others were read
+    Stalest {
+        /// Number of files to list
+        #[clap(short = 'n', long, default_value_t = 20)]
+        count: usize,
+    },
+
+    /// List the runs recorded with `--db` in which a file changed
+    History {
+        /// Path of the file, relative to the packages directory
+        path: String,
+    },
+
+    /// Write a zip file to attach to a bug report: version, path diagnostics
+    /// and, optionally, the totals of a result file and a saved log, with the
+    /// user name and Community package names hidden
+    SupportBundle {
+        /// Zip file to create
+        bundle: PathBuf,
+
+        /// A result file; only its header and per-package totals are included
+        #[clap(long)]
+        result: Option<PathBuf>,
+
+        /// A saved output of the tool (e.g. redirected with `2> run.log`)
+        #[clap(long)]
+        log: Option<PathBuf>,
+    },
+
+    /// Save named result files in a snapshot store, list them and compare
+    /// them, e.g. before and after a sim update
+    Snapshot {
+        /// Directory of the snapshot store
+        /// [default: `msfs2020checksum\snapshots` in the local app data]
+        #[clap(long)]
+        store: Option<PathBuf>,
+
+        #[clap(subcommand)]
+        action: SnapshotAction,
+    },
+
+    /// Print the completion script of a shell, e.g.
+    /// `completions powershell >> $PROFILE` or
+    /// `completions bash > /etc/bash_completion.d/msfs2020checksum`
+    Completions {
+        /// The shell
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Register a Windows scheduled task that runs the tool regularly, or
+    /// remove it
+    Schedule {
+        #[clap(subcommand)]
+        action: ScheduleAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ScheduleAction {
+    /// Register the task (replacing a task of the same name), e.g.
+    /// `schedule install --weekly --output C:\checks\%date%.txt`, or with
+    /// the arguments of the run after `--`:
+    /// `schedule install --daily -- verify --reference D:\Official.txt`
+    Install {
+        /// Run every day rather than every week
+        #[clap(long, conflicts_with_all = ["weekly", "day"])]
+        daily: bool,
+
+        /// Run every week (the default)
+        #[clap(long)]
+        weekly: bool,
+
+        /// Day of the weekly run
+        #[clap(long, value_enum, default_value_t = schedule::Weekday::Sunday)]
+        day: schedule::Weekday,
+
+        /// Time of the run, `HH:MM`; a run missed while the computer was off
+        /// starts as soon as possible
+        #[clap(long, value_parser = schedule::parse_time, default_value = "03:00")]
+        time: (u8, u8),
+
+        /// Name of the task in the Task Scheduler
+        #[clap(long, default_value = schedule::DEFAULT_TASK_NAME)]
+        name: String,
+
+        /// Path for the result file of every run; `%date%` is replaced by the
+        /// date of the run (`YYYYMMDD`)
+        #[clap(short = 'o', long, required_unless_present = "args")]
+        output: Option<PathBuf>,
+
+        /// Arguments of the run [default: `hash`]
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Remove the task
+    Remove {
+        /// Name of the task in the Task Scheduler
+        #[clap(long, default_value = schedule::DEFAULT_TASK_NAME)]
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotAction {
+    /// Hash the packages into a new snapshot
+    Save {
+        /// Name of the snapshot, e.g. `pre-SU15` (replaces a snapshot of the
+        /// same name)
+        #[clap(long)]
+        name: String,
+
+        /// Also keep the content of the small text files (`.cfg`, `.xml`,
+        /// `.json`, `.ini`, `.flt`), compressed, for `verify --show-diff`
+        /// and `snapshot show`
+        #[clap(long)]
+        keep_text: bool,
+
+        /// Size in KiB above which a text file is not kept
+        #[clap(long, value_name = "KIB", default_value_t = 64, requires = "keep_text")]
+        keep_text_max_size: u64,
+
+        #[clap(flatten)]
+        read: Box<ReadArgs>,
+    },
+
+    /// Print the content of a text file kept by `snapshot save --keep-text`,
+    /// e.g. to recover a setting that an update changed
+    Show {
+        /// Name of the snapshot
+        name: String,
+
+        /// Path of the file, as in the result files
+        /// (e.g. `Official\OneStore\asobo-aircraft-c152\SimObjects\Airplanes\Asobo_C152\aircraft.cfg`)
+        path: PathBuf,
+    },
+
+    /// List the snapshots, oldest first
+    List,
+
+    /// List the files changed, added and removed from one snapshot to another
+    Compare {
+        /// Name of the older snapshot
+        old: String,
+
+        /// Name of the newer snapshot
+        new: String,
+    },
+}
+
+/// `(path, error)` of a file that could not be read.
+type ReadError = (String, IoError);
+
+/// Paths waiting for a hashing thread.
+const WORK_QUEUE_SIZE: usize = 4096;
+
+/// Files found by the walk and held back to hand out the largest of them
+/// first.
+const REORDER_WINDOW: usize = 4096;
+
+/// `(path, hash, size, mtime, extra hashes)` of one hashed file.
+type FileRecord = (RecordPath, Digest, u64, u64, ExtraHashes);
+
+/// A `FileRecord` without its path.
+type FileHashes = (Digest, u64, u64, ExtraHashes, bool);
+
+/// Results, read errors, bytes read by each thread and `(files, bytes)`
+/// reused without reading (from the cache, or from the command before) of a
+/// hashing run, and what it covered when `--time-budget` cut it short.
+type HashRun = (
+    Vec<FileRecord>,
+    Vec<ReadError>,
+    Vec<u64>,
+    (usize, u64),
+    Option<Coverage>,
+);
+
+/// Settings shared by every hashing run.
+struct HashOptions {
+    thread_num: usize,
+    algorithm: Algorithm,
+    /// The other `--algorithm`s, computed in the same read pass.
+    extra_algorithms: Vec<Algorithm>,
+    /// `--chunk-size`, in bytes.
+    chunk_size: Option<u64>,
+    /// `--sample`, in bytes.
+    sample: Option<u64>,
+    /// `--tree`, the size of the leaves in bytes.
+    tree: Option<u64>,
+    /// Write each file as soon as it is hashed.
+    live: Option<Live>,
+    filter: PathFilter,
+    /// Applied to the paths printed while hashing.
+    redactor: Option<Redactor>,
+    /// `--buffer-size` and `--max-memory`, in MiB.
+    buffer_size: Option<u64>,
+    max_memory: Option<u64>,
+    mmap: bool,
+    /// `UserCfg.opt`, when `--include-config` is given.
+    usercfg: Option<PathBuf>,
+    /// `--root` directories, hashed after the packages directory.
+    roots: Vec<(String, PathBuf)>,
+    /// Base of the emitted paths.
+    relative_to: RelativeTo,
+    /// With `--image`, the links of the packages directory resolved in the
+    /// image; they are not followed by the walk.
+    image_links: Option<Vec<(String, PathBuf)>>,
+    /// `--follow-links`: walk into the links instead of leaving them out.
+    follow_links: bool,
+    /// `--retries` of a file that fails to read.
+    retries: u32,
+    /// End of the `--time-budget`: no file is started after it.
+    deadline: Option<Instant>,
+    /// Normalization of the emitted paths.
+    forward_slashes: bool,
+    lowercase_paths: bool,
+    /// `--archives`: the files inside the zip files are hashed as well.
+    archives: bool,
+    /// `--dedupe-links`: each physical file is hashed once.
+    dedupe_links: bool,
+    /// `--changed-since`: the files listed unchanged are not hashed.
+    changed_since: Option<QuickScan>,
+    /// `--stdin`: the files to hash, instead of the walk.
+    file_list: Option<Vec<String>>,
+}
+
+impl HashOptions {
+    /// The main algorithm first, then the extra ones.
+    fn algorithms(&self) -> Vec<Algorithm> {
+        std::iter::once(self.algorithm)
+            .chain(self.extra_algorithms.iter().copied())
+            .collect()
+    }
+
+    /// The file on disk of a hashed path: under a `--root` for `@NAME\...`,
+    /// a config file for `@config\...`, the packages directory otherwise.
+    fn source_file(&self, packages_dir: &Path, path_string: &str) -> Option<PathBuf> {
+        if path_string.starts_with(config::CONFIG_PREFIX) {
+            return config::config_source(self.usercfg.as_deref()?, path_string);
+        }
+        if self.archives && archive::is_member(path_string) {
+            return None;
+        }
+        for (name, dir) in &self.roots {
+            if let Ok(r_path) = Path::new(path_string).strip_prefix(roots::root_prefix(name)) {
+                return Some(dir.join(r_path));
+            }
+        }
+        // The deepest link first: a link may be under another one.
+        for (link, dir) in self.image_links.iter().flatten().rev() {
+            if let Ok(r_path) = Path::new(path_string).strip_prefix(link) {
+                return Some(dir.join(r_path));
+            }
+        }
+        if packages_dir.is_file() {
+            Some(packages_dir.to_path_buf())
+        } else {
+            Some(packages_dir.join(path_string))
+        }
+    }
+
+    /// A hashed path as written to the results: rebased by `--relative-to`,
+    /// redacted and normalized. A single file given as `--packages` is in no
+    /// package: `--relative-to package` leaves its name as is.
+    fn emitted_path(&self, packages_dir: &Path, path_string: &str) -> String {
+        let rebase = !packages_dir.is_file();
+        let path = match self.relative_to {
+            RelativeTo::Absolute => self
+                .source_file(packages_dir, path_string)
+                .map(|file| file.to_string_lossy().to_string()),
+            _ => None,
+        }
+        .unwrap_or_else(|| path_string.to_string());
+        // Redacted before the package name is removed: the Community aliases
+        // are given to the package names.
+        let path = match self.redactor {
+            Some(ref redactor) => redactor.redact(&path),
+            None => path,
+        };
+        let path = match self.relative_to {
+            // The config files are not in a package.
+            RelativeTo::Package if rebase && !path_string.starts_with(config::CONFIG_PREFIX) => {
+                package_relative(&path)
+            }
+            _ => path,
+        };
+        self.normalized(&path)
+    }
+
+    /// `--lowercase-paths` and `--forward-slashes`.
+    fn normalized(&self, path: &str) -> String {
+        let path = if self.lowercase_paths {
+            path.to_lowercase()
+        } else {
+            path.to_string()
+        };
+        if self.forward_slashes {
+            path.replace('\\', "/")
+        } else {
+            path
+        }
+    }
+}
+
+/// The path of a single file given as `--packages`, relative to its folder
+/// as the files of a directory are to the directory: its name.
+fn single_file_path(file: &Path) -> String {
+    file.file_name()
+        .unwrap_or(file.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// `package\path` -> `path`, `@NAME\package\path` -> `@NAME\path`: the files
+/// of a `--root` keep the prefix of their root.
+fn package_relative(path_string: &str) -> String {
+    let mut components = Path::new(path_string).components();
+    let root = if path_string.starts_with('@') {
+        components.next()
+    } else {
+        None
+    };
+    components.next();
+    let r_path = components.as_path();
+    match root {
+        Some(root) => Path::new(root.as_os_str()).join(r_path),
+        None => r_path.to_path_buf(),
+    }
+    .to_string_lossy()
+    .to_string()
 }
 
+/// The unit of the size arguments.
+const MIB: u64 = 1024 * 1024;
+
+/// The largest size argument, in MiB, whose bytes still fit in a `u64`.
+const MAX_MIB: u64 = u64::MAX / MIB;
+
+/// Read buffer of `--safe-mode`, in MiB: small enough for a machine short of
+/// memory, large enough for a hard disk to read sequentially.
+const SAFE_MODE_BUFFER_SIZE: u64 = 4;
+
+/// Least `--retries` of `--safe-mode`.
+const SAFE_MODE_RETRIES: u32 = 3;
+
+const AFTER_HELP: &str =
+    "Chain commands with `--then`, e.g. `-o a.txt --then verify --reference a.txt`:
+they run in order until one fails, on the install of the first one unless given
+`--packages` or `--config`. Only the results are shared: a command hashing the
+files the command before it hashed, the same way, takes them instead of reading
+the files again; any other command finds the install and hashes on its own.
+
+Exit codes:
+  0  Success
+  2  Invalid command line arguments
+  3  `UserCfg.opt` or its `InstalledPackagesPath` not found
+  4  The packages directory does not exist
+  5  The output (or cache) file cannot be written
+  6  Some files could not be read
+  7  Verification found mismatching or missing files
+  8  A result file given as input cannot be read or used
+  9  Not enough memory for the hashing buffers
+  10 Interrupted by Ctrl+C (a checkpoint was saved)
+  11 No file found, or far fewer than in the previous run
+  12 The rolling cache looks damaged (`--check-rolling-cache`)";
+
+/// Environment variable read for `--packages` when it is not given.
+const PACKAGES_DIR_VAR: &str = "MSFS_PACKAGES_DIR";
+
 fn main() {
-    let args = Args::parse();
+    // An empty variable is as unset (`set MSFS_PACKAGES_DIR=` in a batch
+    // file), not an empty path.
+    if std::env::var_os(PACKAGES_DIR_VAR).is_some_and(|dir| dir.is_empty()) {
+        std::env::remove_var(PACKAGES_DIR_VAR);
+    }
+    let steps = match session::split_steps(std::env::args_os()) {
+        Some(steps) => steps,
+        None => Args::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                format!("`{}` must be followed by a command", session::THEN),
+            )
+            .exit(),
+    };
+    // Every command line is checked before the first command runs.
+    let (mut steps, commands): (Vec<Args>, Vec<String>) = steps
+        .into_iter()
+        .map(|step| {
+            let matches = Args::command().get_matches_from(step);
+            let command = matches.subcommand_name().unwrap_or("hash").to_string();
+            if let Some(arg) = read_arg_before_command(&matches) {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        format!(
+                            "`--{}` is not taken before a command, give it after `{}` if that command hashes",
+                            arg, command
+                        ),
+                    )
+                    .exit();
+            }
+            let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+            (args, command)
+        })
+        .unzip();
+    // A command given neither `--packages` nor `--config` works on the
+    // install of the command before it.
+    for index in 1..steps.len() {
+        let (before, after) = steps.split_at_mut(index);
+        let (previous, args) = (&before[index - 1], &mut after[0]);
+        if args.packages.is_empty() && args.cfgfile.is_none() {
+            args.packages = previous.packages.clone();
+            args.exact_packages = previous.exact_packages;
+            args.cfgfile = previous.cfgfile.clone();
+        }
+    }
+    locale::init(steps.iter().find_map(|args| args.locale.as_deref()));
+    interrupt::install_handler();
+    let mut session = Session::default();
+    for (args, command) in steps.into_iter().zip(commands) {
+        if let Err(exit_code) = run_step(args, &command, &mut session) {
+            process::exit(exit_code);
+        }
+    }
+}
 
-    let packages_dir = match args.packages {
-        Some(packages_path) => packages_path,
-        None => {
-            let usercfg = match args.cfgfile {
-                Some(cfg_path) => cfg_path,
-                None => match find_msfs_usercfg() {
-                    Some(cfg_path) => cfg_path,
-                    None => {
-                        panic!("Unable to find the `UserCfg.opt` file, you may not have correctly installed MSFS2020.");
+/// The first argument of reading given before a command: only the bare
+/// invocation takes them there, a command takes its own (`verify --threads 2`).
+fn read_arg_before_command(matches: &ArgMatches) -> Option<String> {
+    matches.subcommand_name()?;
+    <ReadArgs as clap::Args>::augment_args(clap::Command::new(""))
+        .get_arguments()
+        .find(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .and_then(|arg| arg.get_long().map(str::to_string))
+}
+
+/// Run one of the chained commands, with its hooks; `Err` is the exit code
+/// of a failed command.
+fn run_step(mut args: Args, command: &str, session: &mut Session) -> Result<(), i32> {
+    // `hash` is the bare invocation spelled out; the other commands that
+    // hash read the files as given after them.
+    match args.command {
+        Some(Command::Hash { .. }) => {
+            if let Some(Command::Hash { hash, read }) = args.command.take() {
+                args.hash = hash;
+                args.read = *read;
+            }
+        }
+        Some(Command::Verify { ref mut read, .. })
+        | Some(Command::Compare { ref mut read, .. })
+        | Some(Command::Snapshot {
+            action: SnapshotAction::Save { ref mut read, .. },
+            ..
+        }) => std::mem::swap(&mut args.read, &mut **read),
+        Some(Command::Lint { follow_links }) | Some(Command::FindInstall { follow_links }) => {
+            args.read.follow_links = follow_links;
+        }
+        _ => {}
+    }
+    // `%date%` of the result files of `schedule install`.
+    args.hash.output = args.hash.output.as_deref().map(schedule::expand_date);
+    if args.version {
+        print_version(args.verbose);
+        return Ok(());
+    }
+    let on_complete = args.on_complete.clone();
+    let on_mismatch = args.on_mismatch.clone();
+    let report = args.hash.output.clone();
+    let format = args.hash.format;
+    let summary_json = args.summary_json.clone();
+    let notify_targets = std::mem::take(&mut args.notify);
+    session.totals = None;
+    let started = Instant::now();
+    let result = run(args, session);
+    // The next step interns its own directories.
+    intern::release();
+    let exit_summary = (summary_json.is_some() || !notify_targets.is_empty()).then(|| {
+        let (packages_dir, stats) = match session.totals {
+            Some((ref packages_dir, ref stats)) => (Some(packages_dir.as_path()), Some(stats)),
+            None => (None, None),
+        };
+        exitsummary::summary(command, packages_dir, &result, stats)
+    });
+    if let (Some(ref file), Some(ref exit_summary)) = (summary_json, &exit_summary) {
+        if let Err(err) = exitsummary::write(file, exit_summary) {
+            eprintln!("Warning: unable to write {:?}: {}\n", file, err);
+        }
+    }
+    let (summary, exit_code) = match result {
+        Ok(()) => (String::from("Completed successfully."), 0),
+        Err(ref err) => (err.to_string(), err.exit_code()),
+    };
+    if let Some(mut exit_summary) = exit_summary.filter(|_| !notify_targets.is_empty()) {
+        let elapsed = started.elapsed();
+        exit_summary["duration_secs"] = json!(elapsed.as_secs_f64());
+        let title = format!(
+            "msfs2020checksum {}: {}",
+            command,
+            exit_summary["status"].as_str().unwrap_or_default()
+        );
+        let text = format!(
+            "{} ({})",
+            summary,
+            progress::format_duration(elapsed.as_secs_f64())
+        );
+        notify::notify(&notify_targets, &title, &text, &exit_summary);
+    }
+    if let (Some(command_line), Err(AppError::VerificationFailed(_))) = (&on_mismatch, &result) {
+        hooks::run_hook(command_line, report.as_deref(), &summary, exit_code);
+    }
+    if let Some(command_line) = on_complete {
+        hooks::run_hook(&command_line, report.as_deref(), &summary, exit_code);
+    }
+    if let Err(err) = result {
+        match format {
+            OutputFormat::Text
+            | OutputFormat::Html
+            | OutputFormat::Markdown
+            | OutputFormat::Coreutils => {
+                eprintln!("Error: {}", err)
+            }
+            OutputFormat::Json | OutputFormat::Jsonl => eprintln!(
+                "{}",
+                json!({
+                    "error": {
+                        "code": err.code(),
+                        "exit_code": err.exit_code(),
+                        "message": err.to_string(),
                     }
-                },
-            };
-            match get_msfs_packages_dir(&usercfg) {
-                Some(mut packages_path) => {
-                    packages_path.push("Official");
-                    packages_path
-                }
-                None => {
-                    panic!("Unable to find the `InstalledPackagesPath` configuration in the `UserCfg.opt` file.");
+                })
+            ),
+        }
+        return Err(err.exit_code());
+    }
+    Ok(())
+}
+
+fn print_version(verbose: bool) {
+    print!("{}", version_info(verbose));
+}
+
+fn version_info(verbose: bool) -> String {
+    let mut info = format!("msfs2020checksum {}\n", env!("CARGO_PKG_VERSION"));
+    if verbose {
+        info.push_str(&format!(
+            "platform: {}-{}\n",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ));
+        info.push_str(&format!(
+            "xxh3 implementation: {} (selected at runtime)\n",
+            hasher::xxh3_implementation()
+        ));
+    }
+    info
+}
+
+/// Gather what a bug report needs into the `support-bundle` zip file: the
+/// version, how the paths were resolved, the totals of a result file and a
+/// saved log, with the user name, home directory and Community package names
+/// hidden.
+fn support_bundle(
+    args: &Args,
+    bundle: &Path,
+    result: Option<&Path>,
+    log: Option<&Path>,
+) -> Result<(), AppError> {
+    let packages_dir = resolve_packages_dir(args);
+    let redactor = Redactor::new(packages_dir.as_deref().unwrap_or(Path::new("")), true);
+    let mut entries = vec![
+        ("version.txt", version_info(true)),
+        (
+            "paths.txt",
+            redactor.redact_text(&path_diagnostics(args, &packages_dir)),
+        ),
+    ];
+    if let Some(result) = result {
+        let manifest = load_manifest(result)?;
+        entries.push(("summary.txt", bundle::summary_text(&manifest, &redactor)));
+    }
+    if let Some(log) = log {
+        let text =
+            fs::read(log).map_err(|err| AppError::ManifestUnreadable(log.to_path_buf(), err))?;
+        entries.push((
+            "run.log",
+            redactor.redact_text(&String::from_utf8_lossy(&text)),
+        ));
+    }
+    bundle::write_bundle(bundle, &entries)
+        .map_err(|err| AppError::OutputUnwritable(bundle.to_path_buf(), err))?;
+    eprintln!("Support bundle written to {:?}, with:", bundle);
+    for (name, _) in &entries {
+        eprintln!("    {}", name);
+    }
+    eprintln!("Check its content before attaching it to an issue.");
+    Ok(())
+}
+
+/// Print the install found from `UserCfg.opt` and the sizes of its package
+/// directories (the `find-install` command).
+fn find_install(args: &Args) -> Result<(), AppError> {
+    let usercfg = match args.cfgfile.clone().or_else(|| find_msfs_usercfg(args)) {
+        Some(usercfg) => usercfg,
+        None => return Err(AppError::ConfigNotFound),
+    };
+    println!("UserCfg.opt: {}", usercfg.to_string_lossy());
+    let installed = match get_msfs_packages_dir(&usercfg) {
+        Some(installed) => installed,
+        None => return Err(AppError::PackagesPathNotConfigured(usercfg)),
+    };
+    println!("InstalledPackagesPath: {}", installed.to_string_lossy());
+    // The packages tell the store best; a new install may have none yet.
+    let store = match provenance::detect_store(&installed.join("Official")) {
+        "unknown" if usercfg.to_string_lossy().contains(STORE_MSFS_DIR_NAME) => "MS Store",
+        "unknown" if usercfg.to_string_lossy().contains(STEAM_MSFS_DIR_NAME) => "Steam",
+        store => store,
+    };
+    println!("store: {}", store);
+    for name in ["Official", "Community"] {
+        let dir = installed.join(name);
+        if let Err(err) = dir.metadata() {
+            println!("{}: not accessible ({})", name, err);
+            continue;
+        }
+        let (files, bytes) = fileid::walk(WalkDir::new(&dir), args.read.follow_links)
+            .filter(|entry| entry.file_type().is_file())
+            .fold((0, 0), |(files, bytes), entry| {
+                (
+                    files + 1,
+                    bytes + entry.metadata().map_or(0, |meta| meta.len()),
+                )
+            });
+        println!(
+            "{}: {} files, {} bytes ({:.1} GiB)",
+            name,
+            files,
+            bytes,
+            bytes as f64 / 1024.0 / 1024.0 / 1024.0
+        );
+    }
+    Ok(())
+}
+
+/// How the `UserCfg.opt` and the packages directory were found, or why not.
+fn path_diagnostics(args: &Args, packages_dir: &Result<PathBuf, AppError>) -> String {
+    let mut lines = vec![
+        format!("--packages: {:?}", args.packages),
+        format!("--cfgfile: {:?}", args.cfgfile),
+    ];
+    match args.cfgfile.clone().or_else(|| find_msfs_usercfg(args)) {
+        Some(usercfg) => {
+            lines.push(format!("UserCfg.opt: {:?}", usercfg));
+            let installed = UserCfg::read(&usercfg).map(|settings| {
+                settings
+                    .installed_packages_path()
+                    .map(|path| path.to_string())
+            });
+            lines.push(match installed {
+                Ok(Some(path)) => format!("InstalledPackagesPath: {:?}", path),
+                Ok(None) => String::from("InstalledPackagesPath: not set"),
+                Err(err) => format!("InstalledPackagesPath: unreadable ({})", err),
+            });
+        }
+        None => lines.push(String::from("UserCfg.opt: not found")),
+    }
+    match packages_dir {
+        Ok(packages_dir) => {
+            lines.push(format!("packages directory: {:?}", packages_dir));
+            match packages_dir.metadata() {
+                Ok(_) => {
+                    let provenance =
+                        Provenance::collect(packages_dir, None, args.read.follow_links);
+                    lines.push(format!("store: {}", provenance.store));
+                    lines.push(format!(
+                        "sim build: {}",
+                        provenance.sim_build.as_deref().unwrap_or("unknown")
+                    ));
+                    lines.push(format!(
+                        "packages with a manifest.json: {}",
+                        provenance.packages.len()
+                    ));
                 }
+                Err(err) => lines.push(format!("packages directory: not accessible ({})", err)),
+            }
+            if let Some(parent) = packages_dir.parent() {
+                lines.push(format!(
+                    "Community next to it: {}",
+                    parent.join("Community").is_dir()
+                ));
             }
         }
-    };
-    eprintln!(
-        "Using MSFS 2020 InstalledPackagesPath: {:?}\n",
-        packages_dir.to_string_lossy()
-    );
+        Err(err) => lines.push(format!("packages directory: {}", err)),
+    }
+    lines.push(format!(
+        "current directory: {:?}",
+        std::env::current_dir().unwrap_or_default()
+    ));
+    lines.join("\n") + "\n"
+}
+
+fn run(mut args: Args, session: &mut Session) -> Result<(), AppError> {
+    // Windows paths are case-insensitive: the case of a path may change
+    // between two installs without the file changing.
+    let case_sensitive = args.case_sensitive || !cfg!(target_os = "windows");
+    // Works without the sim installed.
+    if let Some(Command::Explain { ref topic }) = args.command {
+        return explain::explain(topic.as_deref());
+    }
+    if let Some(Command::SelfCheck { ref expected }) = args.command {
+        return selfcheck::self_check(expected.as_deref());
+    }
+    if let Some(Command::FindInstall { .. }) = args.command {
+        return find_install(&args);
+    }
+    if args.generate_manpage {
+        return clap_mangen::Man::new(Args::command())
+            .render(&mut stdout())
+            .map_err(|err| AppError::OutputUnwritable(PathBuf::from("-"), err));
+    }
+    if let Some(Command::Completions { shell }) = args.command {
+        let mut command = Args::command();
+        let name = command.get_name().to_string();
+        // Generated in memory: `generate` panics when it cannot write.
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut command, name, &mut script);
+        return stdout()
+            .write_all(&script)
+            .map_err(|err| AppError::OutputUnwritable(PathBuf::from("-"), err));
+    }
+    match args.command {
+        Some(Command::Schedule {
+            action:
+                ScheduleAction::Install {
+                    daily,
+                    day,
+                    time,
+                    ref name,
+                    ref output,
+                    ref args,
+                    ..
+                },
+        }) => {
+            let mut args = args.clone();
+            if let Some(output) = output {
+                args.push(String::from("--output"));
+                args.push(output.to_string_lossy().to_string());
+            }
+            return schedule::install(name, (!daily).then_some(day), time, &args);
+        }
+        Some(Command::Schedule {
+            action: ScheduleAction::Remove { ref name },
+        }) => return schedule::remove(name),
+        _ => {}
+    }
+    if let Some(Command::SupportBundle {
+        ref bundle,
+        ref result,
+        ref log,
+    }) = args.command
+    {
+        return support_bundle(&args, bundle, result.as_deref(), log.as_deref());
+    }
+    if let Some(Command::Stalest { count }) = args.command {
+        let cache_file = match args.cache {
+            Some(ref cache_file) => cache_file,
+            None => Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "`stalest` needs the cache file given with `--cache <CACHE>`",
+                )
+                .exit(),
+        };
+        return print_stalest(cache_file, count);
+    }
+    if let Some(Command::History { ref path }) = args.command {
+        let db = match args.db {
+            Some(ref db) => db,
+            None => Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "`history` needs the database given with `--db <DB>`",
+                )
+                .exit(),
+        };
+        return history::print_history(db, path, case_sensitive);
+    }
+    match args.command {
+        Some(Command::BackupSettings { ref dest }) => {
+            let usercfg = match args.cfgfile.clone().or_else(|| find_msfs_usercfg(&args)) {
+                Some(usercfg) => usercfg,
+                None => return Err(AppError::ConfigNotFound),
+            };
+            let backup_dir = settings::backup_settings(&usercfg, dest)?;
+            println!("{}", backup_dir.to_string_lossy());
+            return Ok(());
+        }
+        Some(Command::CheckLocalState) => {
+            let usercfg = match args.cfgfile.clone().or_else(|| find_msfs_usercfg(&args)) {
+                Some(usercfg) => usercfg,
+                None => return Err(AppError::ConfigNotFound),
+            };
+            let damaged = localstate::check_local_state(&usercfg);
+            if damaged > 0 {
+                return Err(AppError::VerificationFailed(damaged));
+            }
+            return Ok(());
+        }
+        Some(Command::VerifySettings { ref backup }) => {
+            let problems = settings::verify_settings(backup)?;
+            if problems > 0 {
+                return Err(AppError::VerificationFailed(problems));
+            }
+            return Ok(());
+        }
+        Some(Command::Snapshot {
+            ref store,
+            action: SnapshotAction::List,
+        }) => {
+            return snapshot::print_list(&SnapshotStore::open(store.as_deref()));
+        }
+        Some(Command::Snapshot {
+            ref store,
+            action: SnapshotAction::Compare { ref old, ref new },
+        }) => {
+            let store = SnapshotStore::open(store.as_deref());
+            return diff_result_files(&store.find(old)?, &store.find(new)?, case_sensitive, None);
+        }
+        Some(Command::Snapshot {
+            ref store,
+            action: SnapshotAction::Show { ref name, ref path },
+        }) => {
+            let store = SnapshotStore::open(store.as_deref());
+            let path = path.to_string_lossy();
+            return match store.kept_text(name)?.get(&path) {
+                Some(content) => {
+                    print!("{}", content);
+                    Ok(())
+                }
+                None => Err(AppError::NotKept(name.clone(), path.to_string())),
+            };
+        }
+        Some(Command::Diff {
+            ref old,
+            ref new,
+            ref report,
+        }) => {
+            return diff_result_files(old, new, case_sensitive, report.as_deref());
+        }
+        Some(Command::Fmt {
+            ref file,
+            ref output,
+            forward_slashes,
+        }) => {
+            return normalize::normalize(
+                file,
+                output.as_deref(),
+                forward_slashes,
+                TextStyle::new(args.line_ending, args.bom),
+            );
+        }
+        _ => {}
+    }
+    // `snapshot save` is a hashing run written into the store.
+    let snapshot = match args.command {
+        Some(Command::Snapshot {
+            ref store,
+            action:
+                SnapshotAction::Save {
+                    ref name,
+                    keep_text,
+                    keep_text_max_size,
+                    ..
+                },
+        }) => {
+            let store = SnapshotStore::open(store.as_deref());
+            let snapshot = store.new_snapshot(name)?;
+            args.hash.output = Some(store.path(&snapshot));
+            args.hash.format = OutputFormat::Text;
+            Some((
+                store,
+                snapshot,
+                keep_text.then_some(keep_text_max_size * 1024),
+            ))
+        }
+        _ => None,
+    };
+    // `(file, problems)` of `--check-rolling-cache`, which hashes the rolling
+    // cache instead of the packages.
+    let rolling_cache = match args.command {
+        None if args.hash.check_rolling_cache => Some(check_rolling_cache(&args)?),
+        _ => None,
+    };
+    let packages_dir = match rolling_cache {
+        Some((ref file, _)) => file.clone(),
+        None => resolve_packages_dir(&args)?,
+    };
+    if let Err(err) = packages_dir.metadata() {
+        return Err(AppError::PackagesDirMissing(packages_dir, err));
+    }
+    eprintln!(
+        "Using MSFS 2020 InstalledPackagesPath: {:?}\n",
+        packages_dir.to_string_lossy()
+    );
+    let image_links = args.read.image.as_deref().map(|mount_point| {
+        let links = image::image_links(&packages_dir, mount_point);
+        eprintln!(
+            "Links resolved in the image mounted at {:?}: {}\n",
+            mount_point,
+            links.len()
+        );
+        links
+    });
+
+    let network = match args.command {
+        Some(Command::Compare { ref dir_b, .. }) => {
+            args.read.io_profile.is_network(&packages_dir) || args.read.io_profile.is_network(dir_b)
+        }
+        _ => args.read.io_profile.is_network(&packages_dir),
+    };
+    if network {
+        if args.read.threads == 0 {
+            args.read.threads = network::NETWORK_THREADS;
+        }
+        if args.read.mmap {
+            eprintln!("Warning: `--mmap` is not used on a network share.");
+            args.read.mmap = false;
+        }
+        args.read.retries = args.read.retries.max(network::NETWORK_RETRIES);
+        eprintln!(
+            "Network share: reading with {} thread(s), {} retries (`--io-profile local` to disable).\n",
+            args.read.threads, args.read.retries
+        );
+    }
+    // A mapped file must not be truncated while it is read. Only Windows can
+    // forbid it (`open_unshared`); elsewhere the read would crash (SIGBUS).
+    if args.read.mmap && !cfg!(target_os = "windows") {
+        eprintln!("Warning: `--mmap` is only used on Windows, the files are read instead.");
+        args.read.mmap = false;
+    }
+    // Files may change while they are read: they are read, not mapped (which
+    // would also keep the sim from writing them meanwhile).
+    if args.read.mmap && (args.read.tolerate_activity || args.hash.watch) {
+        eprintln!("Warning: `--mmap` is not used with `--tolerate-activity` or `--watch`.");
+        args.read.mmap = false;
+    }
+    if args.read.safe_mode {
+        args.read.threads = 1;
+        args.read.buffer_size = Some(SAFE_MODE_BUFFER_SIZE);
+        args.read.mmap = false;
+        args.read.follow_links = false;
+        args.read.dedupe_links = false;
+        args.read.archives = false;
+        args.read.tree = None;
+        args.read.no_cache = true;
+        args.db = None;
+        args.read.submit_stats = None;
+        args.read.retries = args.read.retries.max(SAFE_MODE_RETRIES);
+        eprintln!(
+            "Safe mode: reading with 1 thread and a {} MiB buffer, {} retries, without the cache and the optional features.\n",
+            SAFE_MODE_BUFFER_SIZE, args.read.retries
+        );
+    }
+
+    let thread_num = if args.read.threads == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        args.read.threads
+    };
+
+    let mut filter = PathFilter::new(
+        &args.read.include,
+        &args.read.exclude,
+        args.read.skip_volatile,
+    )?;
+
+    let mut extra_roots = Vec::new();
+    for spec in &args.read.roots {
+        let (name, dir) = roots::parse_root(spec)?;
+        if let Err(err) = dir.metadata() {
+            return Err(AppError::PackagesDirMissing(dir, err));
+        }
+        extra_roots.push((name, dir));
+    }
+    for dir in args.packages.iter().skip(1) {
+        if let Err(err) = dir.metadata() {
+            return Err(AppError::PackagesDirMissing(dir.clone(), err));
+        }
+        let name = roots::packages_root_name(dir, &extra_roots);
+        if args.verbose {
+            eprintln!("Packages directory {:?} hashed as root {}", dir, name);
+        }
+        extra_roots.push((name, dir.clone()));
+    }
+
+    let usercfg = if args.read.include_config {
+        match args.cfgfile.clone().or_else(|| find_msfs_usercfg(&args)) {
+            Some(usercfg) => Some(usercfg),
+            None => return Err(AppError::ConfigNotFound),
+        }
+    } else {
+        None
+    };
+
+    let cache_file = if args.read.no_cache {
+        None
+    } else {
+        args.cache.clone()
+    };
+    // An interrupted run saves its checkpoint where the resumed one was
+    // loaded from, so it can be resumed again.
+    let checkpoint_file = match (&args.read.resume, &args.hash.output) {
+        (Some(resume), _) => resume.clone(),
+        (None, Some(outpath)) => {
+            let mut name = outpath.as_os_str().to_owned();
+            name.push(".checkpoint");
+            PathBuf::from(name)
+        }
+        (None, None) => PathBuf::from("msfs2020checksum.checkpoint"),
+    };
+
+    match args.command {
+        Some(Command::Explain { .. })
+        | Some(Command::SelfCheck { .. })
+        | Some(Command::FindInstall { .. })
+        | Some(Command::Completions { .. })
+        | Some(Command::Schedule { .. })
+        | Some(Command::Stalest { .. })
+        | Some(Command::History { .. })
+        | Some(Command::SupportBundle { .. })
+        | Some(Command::Hash { .. })
+        | Some(Command::Diff { .. })
+        | Some(Command::Fmt { .. })
+        | Some(Command::BackupSettings { .. })
+        | Some(Command::VerifySettings { .. })
+        | Some(Command::CheckLocalState) => unreachable!(),
+        Some(Command::Snapshot { .. }) => {}
+        Some(Command::Lint { .. }) => {
+            lint::lint_packages(&packages_dir, args.read.follow_links);
+            return Ok(());
+        }
+        Some(Command::Compare { ref dir_b, .. }) => {
+            if let Err(err) = dir_b.metadata() {
+                return Err(AppError::PackagesDirMissing(dir_b.clone(), err));
+            }
+            let options = HashOptions {
+                thread_num,
+                algorithm: args
+                    .read
+                    .algorithm
+                    .first()
+                    .copied()
+                    .unwrap_or(Algorithm::Xxh3_128),
+                extra_algorithms: Vec::new(),
+                chunk_size: args.read.chunk_size.map(|mib| mib * MIB),
+                sample: args.read.sample.map(|mib| mib * MIB),
+                tree: args.read.tree.map(|mib| mib * MIB),
+                live: None,
+                filter,
+                redactor: None,
+                buffer_size: args.read.buffer_size,
+                max_memory: args.read.max_memory,
+                mmap: args.read.mmap,
+                usercfg: None,
+                roots: vec![(String::from(COMPARED_ROOT), dir_b.clone())],
+                relative_to: RelativeTo::Packages,
+                image_links,
+                follow_links: args.read.follow_links,
+                retries: args.read.retries,
+                deadline: None,
+                forward_slashes: false,
+                lowercase_paths: false,
+                archives: args.read.archives,
+                dedupe_links: args.read.dedupe_links,
+                changed_since: None,
+                file_list: None,
+            };
+            return compare_dirs(&packages_dir, dir_b, &options, case_sensitive);
+        }
+        Some(Command::Verify {
+            ref reference,
+            ref remote,
+            ref baseline,
+            cross_store,
+            spot_check,
+            seed,
+            ref show_diff,
+            ref store,
+            fast,
+            ref report,
+            ref require_signature,
+            ..
+        }) => {
+            // Both result files are read before hashing, so that an unusable
+            // one is reported before the long scan instead of after it.
+            let reference_path = &match (reference, remote) {
+                (Some(reference), _) => reference.clone(),
+                (None, Some(url)) => remote::fetch(url)?,
+                (None, None) => unreachable!(),
+            };
+            if let Some(public_key) = require_signature {
+                let key = signature::verifying_key(public_key)
+                    .map_err(|err| AppError::InvalidKey(public_key.clone(), err))?;
+                signature::check(reference_path, &key)
+                    .map_err(|err| AppError::ManifestUnreadable(reference_path.clone(), err))?;
+                eprintln!("Signature of the reference: valid.\n");
+            }
+            let mut reference = load_manifest(reference_path)?;
+            // Checked before the long scan, in bytes.
+            let size_header = |key| {
+                reference
+                    .size_header(key)
+                    .map_err(|err| AppError::ManifestUnreadable(reference_path.clone(), err))
+            };
+            let chunk_size = size_header("chunk-size")?;
+            let sample = size_header("sample")?;
+            let tree = size_header("tree")?;
+            let cache_file = match cache_file {
+                None if fast => {
+                    let mut name = reference_path.as_os_str().to_owned();
+                    name.push(".cache");
+                    Some(PathBuf::from(name))
+                }
+                cache_file => cache_file,
+            };
+            let mut kept_text = match show_diff {
+                Some(name) => {
+                    let store = SnapshotStore::open(store.as_deref());
+                    Some((name, store.kept_text(name)?))
+                }
+                None => None,
+            };
+            let algorithm = match (reference.algorithm, args.read.algorithm.first().copied()) {
+                (Some(detected), Some(requested)) if detected != requested => {
+                    return Err(AppError::AlgorithmMismatch(
+                        reference_path.clone(),
+                        detected.name(),
+                        requested.name(),
+                    ));
+                }
+                (detected, requested) => detected.or(requested).unwrap_or(Algorithm::Xxh3_128),
+            };
+            let baseline_path = baseline.clone();
+            let mut baseline = match baseline {
+                Some(baseline_path) => {
+                    let baseline = load_manifest(baseline_path)?;
+                    match baseline.algorithm {
+                        Some(detected) if detected != algorithm => {
+                            return Err(AppError::AlgorithmMismatch(
+                                baseline_path.clone(),
+                                detected.name(),
+                                algorithm.name(),
+                            ));
+                        }
+                        _ => Some(baseline),
+                    }
+                }
+                None => None,
+            };
+            // Their changes are expected, the reference is not held to them.
+            if args.read.skip_volatile {
+                for manifest in std::iter::once(&mut reference).chain(baseline.as_mut()) {
+                    manifest
+                        .entries
+                        .retain(|path, _| !filter.is_volatile(Path::new(&path.to_string())));
+                }
+            }
+            // `(sample size, reference size, seed)`. The other roots are left
+            // out of the sample.
+            let spot_check = spot_check.map(|percent| {
+                let seed = seed.unwrap_or_else(spotcheck::random_seed);
+                let total = reference.entries.len();
+                let paths: Vec<String> = reference
+                    .entries
+                    .keys()
+                    .filter(|path| !path.starts_with("@"))
+                    .map(RecordPath::to_string)
+                    .collect();
+                let sample: HashSet<String> =
+                    spotcheck::sample(paths.iter().map(String::as_str).collect(), percent, seed)
+                        .into_iter()
+                        .map(String::from)
+                        .collect();
+                filter.restrict_to(sample.iter().map(String::as_str));
+                reference
+                    .entries
+                    .retain(|path, _| sample.contains(&path.to_string()));
+                (sample.len(), total, seed)
+            });
+            eprintln!("Hash algorithm: {}\n", algorithm.name());
+            if let Some(sample) = sample.map(|sample| sample / MIB) {
+                eprintln!(
+                    "Note: the reference was made with `--sample {}`: the files larger than {} MiB are only compared by their first and last {} MiB.\n",
+                    sample,
+                    2 * sample,
+                    sample
+                );
+            }
+            if let Some(tree) = tree.map(|tree| tree / MIB) {
+                eprintln!(
+                    "Note: the reference holds tree digests (`--tree {}`): the file is hashed in {} MiB leaves as well.\n",
+                    tree, tree
+                );
+            }
+            // With `--include` / `--exclude` / `--skip-volatile` the counts
+            // of the partial walk cannot match those of the whole reference,
+            // nor can the paths of another store type.
+            if let (Some(mut expected), true) = (
+                reference.dir_counts(),
+                args.read.include.is_empty()
+                    && args.read.exclude.is_empty()
+                    && !args.read.skip_volatile
+                    && spot_check.is_none()
+                    && !cross_store,
+            ) {
+                let sources = std::iter::once((None, packages_dir.as_path())).chain(
+                    image_links
+                        .iter()
+                        .flatten()
+                        .map(|(link, dir)| (Some(link), dir.as_path())),
+                );
+                let on_disk: Vec<String> = sources
+                    .flat_map(|(link, dir)| {
+                        walk_packages(dir, &filter, args.read.follow_links)
+                            .filter(|entry| !entry.file_type().is_dir())
+                            .map(move |entry| {
+                                let path = match entry.path().strip_prefix(dir) {
+                                    Ok(r_path) => r_path.to_path_buf(),
+                                    Err(_) => entry.into_path(),
+                                };
+                                match link {
+                                    Some(link) => Path::new(link).join(path),
+                                    None => path,
+                                }
+                                .to_string_lossy()
+                                .to_string()
+                            })
+                    })
+                    .collect();
+                // The other roots are checked by the hashing itself.
+                expected.retain(|dir, _| !dir.starts_with('@'));
+                let actual = manifest::dir_counts(on_disk.iter().map(|path| path.as_str()));
+                if !case_sensitive {
+                    let renames = manifest::case_renames(expected.keys(), actual.keys());
+                    expected = expected
+                        .into_iter()
+                        .map(|(dir, count)| (renames.get(&dir).cloned().unwrap_or(dir), count))
+                        .collect();
+                }
+                verify::compare_dir_counts(&expected, &actual);
+            }
+            let options = HashOptions {
+                thread_num,
+                algorithm,
+                extra_algorithms: Vec::new(),
+                chunk_size: chunk_size.or(args.read.chunk_size.map(|mib| mib * MIB)),
+                sample,
+                tree,
+                live: None,
+                filter,
+                redactor: None,
+                buffer_size: args.read.buffer_size,
+                max_memory: args.read.max_memory,
+                mmap: args.read.mmap,
+                usercfg: usercfg.clone(),
+                roots: roots::resolve_roots(&reference, &extra_roots, &packages_dir),
+                relative_to: RelativeTo::Packages,
+                image_links,
+                follow_links: args.read.follow_links,
+                retries: args.read.retries,
+                deadline: None,
+                forward_slashes: false,
+                lowercase_paths: false,
+                archives: args.read.archives,
+                dedupe_links: args.read.dedupe_links,
+                changed_since: None,
+                file_list: None,
+            };
+            let mut cache = load_cache(
+                cache_file.as_deref(),
+                args.read.refresh,
+                args.read.resume.as_deref(),
+                args.read.max_staleness,
+                &packages_dir,
+                algorithm,
+            );
+            if fast {
+                // The files that did not match last time are read in this
+                // pass, not after the delay of `recheck_mismatches`.
+                let suspects = cache.retain_matching(&reference.entries);
+                eprintln!(
+                    "Fast verification: {} hash(es) kept for the files unchanged since the last one, {} file(s) that did not match then are read again.\n",
+                    cache.len(),
+                    suspects
+                );
+            }
+            let started = Instant::now();
+            let started_at = SystemTime::now();
+            let (mut results, read_errors, thread_bytes, reused, _) =
+                session.hash_packages(&packages_dir, &options, &cache)?;
+            let stats = RunStats::new(
+                &results,
+                thread_bytes,
+                reused,
+                read_errors.len(),
+                started.elapsed(),
+            );
+            session.totals = Some((packages_dir.clone(), stats.to_json()));
+            report_stats(&stats, args.read.stats, args.read.stats_file.as_deref())?;
+            if let (Some(ref url), false) = (&args.read.submit_stats, interrupt::interrupted()) {
+                let provenance = Provenance::collect(
+                    &packages_dir,
+                    args.read.sim_build.clone(),
+                    args.read.follow_links,
+                );
+                stats::submit(url, &stats.anonymized("verify", algorithm, &provenance));
+            }
+            if args.read.tolerate_activity && !interrupt::interrupted() {
+                settle_activity(&mut results, &packages_dir, &options)?;
+            }
+            if !case_sensitive {
+                reference.match_case(results.iter().map(|r| r.0.to_string()));
+                if let Some(ref mut baseline) = baseline {
+                    baseline.match_case(results.iter().map(|r| r.0.to_string()));
+                }
+            }
+            if !interrupt::interrupted() {
+                recheck_mismatches(&mut results, &reference.entries, &packages_dir, &options)?;
+            }
+            if interrupt::interrupted() {
+                return Err(save_checkpoint(
+                    checkpoint_file,
+                    &packages_dir,
+                    algorithm,
+                    &results,
+                    &cache,
+                ));
+            }
+            let local = Provenance::collect(
+                &packages_dir,
+                args.read.sim_build.clone(),
+                args.read.follow_links,
+            );
+            provenance::check_reference(&reference, &local, cross_store);
+            let (problems, findings) = if cross_store {
+                let (results, reference, baseline) = verify::cross_store(
+                    &results,
+                    &reference.entries,
+                    baseline.as_ref().map(|baseline| &baseline.entries),
+                );
+                verify::verify(&results, &reference, baseline.as_ref())
+            } else {
+                let verified = verify::verify(
+                    &results,
+                    &reference.entries,
+                    baseline.as_ref().map(|baseline| &baseline.entries),
+                );
+                if let Some(chunk_size) = options.chunk_size {
+                    verify::report_chunks(
+                        &results,
+                        &reference.entries,
+                        &reference.chunks(),
+                        chunk_size,
+                    );
+                }
+                verified
+            };
+            if let Some(ref report) = report {
+                let mut about = vec![
+                    ("packages", packages_dir.to_string_lossy().to_string()),
+                    ("reference", reference_path.to_string_lossy().to_string()),
+                    ("files", results.len().to_string()),
+                    ("problems", problems.to_string()),
+                ];
+                if let Some(ref baseline_path) = baseline_path {
+                    about.insert(2, ("baseline", baseline_path.to_string_lossy().to_string()));
+                }
+                findings_report(report, "msfs2020checksum verify", &about, &findings)?;
+            }
+            if let Some((name, ref mut kept_text)) = kept_text {
+                show_text_diffs(
+                    kept_text,
+                    name,
+                    &results,
+                    &reference.entries,
+                    &packages_dir,
+                    &options,
+                );
+            }
+            if let Some(cache_path) = cache_file {
+                save_cache(
+                    &cache_path,
+                    &packages_dir,
+                    algorithm,
+                    &results,
+                    &cache,
+                    |path| options.source_file(&packages_dir, path),
+                )
+                .map_err(|err| AppError::OutputUnwritable(cache_path, err))?;
+            }
+            if let Some(ref db) = args.db {
+                history::record_run(db, started_at, &packages_dir, algorithm, &results)?;
+            }
+            remove_checkpoint(args.read.resume.as_deref());
+            if let Some((sampled, total, seed)) = spot_check {
+                spotcheck::report(sampled, problems, total, seed);
+            }
+            report_read_errors(&read_errors);
+            antivirus::report_interference(&read_errors, |path| {
+                options
+                    .source_file(&packages_dir, path)?
+                    .metadata()
+                    .ok()?
+                    .modified()
+                    .ok()
+            });
+            if problems > 0 {
+                return Err(AppError::VerificationFailed(problems));
+            }
+            if !read_errors.is_empty() {
+                return Err(AppError::ReadErrorsOccurred(read_errors.len()));
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    if args.hash.check_layout {
+        let problems = layout::check_layouts(&packages_dir, &filter, args.read.follow_links);
+        if problems > 0 {
+            return Err(AppError::VerificationFailed(problems));
+        }
+        return Ok(());
+    }
+
+    // The size of the previous run, to catch a mistyped `-P` or a drive
+    // not mounted yet before the result is trusted.
+    let previous_files = args
+        .hash
+        .output
+        .iter()
+        .filter(|_| args.hash.format == OutputFormat::Text)
+        .chain(cache_file.iter())
+        .filter(|file| file.is_file())
+        .find_map(|file| manifest::count_entries(file).ok());
+    match args.hash.output {
+        Some(ref outpath) => {
+            if outpath.exists() {
+                if outpath.is_dir() {
+                    return Err(AppError::OutputUnwritable(
+                        outpath.clone(),
+                        IoError::other("the output path is a directory"),
+                    ));
+                } else {
+                    eprintln!("Warning: output file will be overwritten: {:?}\n", outpath);
+                }
+            }
+        }
+        None => {
+            eprintln!("The hash values will be output to the stdout.\n");
+        }
+    }
+    if args.hash.watch
+        && (args.hash.format != OutputFormat::Text
+            || args.hash.template.is_some()
+            || !packages_dir.is_dir())
+    {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "`--watch` needs a packages directory and the text format, without `--template`",
+            )
+            .exit();
+    }
+    let signing_key = match args.hash.sign {
+        // The signature is a `#` line, which only the text format has.
+        Some(_) if args.hash.format != OutputFormat::Text => Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "`--sign` needs the text format, the one `verify` checks signatures of",
+            )
+            .exit(),
+        Some(ref key_file) => {
+            let key = signature::signing_key(key_file)
+                .map_err(|err| AppError::InvalidKey(key_file.to_string_lossy().to_string(), err))?;
+            eprintln!(
+                "Signing with the key whose public key is {} (`verify --require-signature`).\n",
+                signature::public_hex(&key)
+            );
+            Some(key)
+        }
+        None => None,
+    };
+
+    let options = HashOptions {
+        thread_num,
+        algorithm: args
+            .read
+            .algorithm
+            .first()
+            .copied()
+            .unwrap_or(Algorithm::Xxh3_128),
+        extra_algorithms: args.read.algorithm.iter().skip(1).copied().collect(),
+        chunk_size: args.read.chunk_size.map(|mib| mib * MIB),
+        sample: args.read.sample.map(|mib| mib * MIB),
+        tree: args.read.tree.map(|mib| mib * MIB),
+        // Every `--split-roots` file is written at the end.
+        live: match args.hash.format {
+            OutputFormat::Text if args.hash.output.is_none() => {
+                Some(Live::Text(args.hash.template.clone()))
+            }
+            OutputFormat::Jsonl if !args.hash.split_roots => Some(Live::Jsonl(
+                args.hash.output.clone(),
+                args.hash
+                    .output
+                    .as_deref()
+                    .and_then(|outpath| Compression::of_output(args.hash.compress, outpath)),
+            )),
+            _ => None,
+        },
+        filter,
+        redactor: if args.hash.redact_paths {
+            Some(Redactor::new(&packages_dir, args.hash.redact_community))
+        } else {
+            None
+        },
+        buffer_size: args.read.buffer_size,
+        max_memory: args.read.max_memory,
+        mmap: args.read.mmap,
+        usercfg: usercfg.clone(),
+        roots: extra_roots,
+        relative_to: args.hash.relative_to,
+        image_links,
+        follow_links: args.read.follow_links,
+        retries: args.read.retries,
+        deadline: args.hash.time_budget.map(|budget| Instant::now() + budget),
+        forward_slashes: args.hash.forward_slashes,
+        lowercase_paths: args.hash.lowercase_paths,
+        archives: args.read.archives,
+        dedupe_links: args.read.dedupe_links,
+        changed_since: match args.hash.changed_since {
+            Some(ref scan) => Some(
+                quick::read_scan(scan)
+                    .map_err(|err| AppError::ManifestUnreadable(scan.clone(), err))?,
+            ),
+            None => None,
+        },
+        file_list: if args.hash.stdin {
+            Some(
+                read_file_list(args.hash.null)
+                    .map_err(|err| AppError::ManifestUnreadable(PathBuf::from("<stdin>"), err))?,
+            )
+        } else {
+            None
+        },
+    };
+    if args.hash.quick {
+        return quick_scan(
+            &packages_dir,
+            &options,
+            args.hash.output.as_deref(),
+            TextStyle::new(args.line_ending, args.bom),
+        );
+    }
+    let cache = load_cache(
+        cache_file.as_deref(),
+        args.read.refresh,
+        args.read.resume.as_deref(),
+        args.read.max_staleness,
+        &packages_dir,
+        options.algorithm,
+    );
+    let started = Instant::now();
+    let started_at = SystemTime::now();
+    let (mut results, read_errors, thread_bytes, reused, coverage) =
+        session.hash_packages(&packages_dir, &options, &cache)?;
+    let mut stats = RunStats::new(
+        &results,
+        thread_bytes,
+        reused,
+        read_errors.len(),
+        started.elapsed(),
+    );
+    if let Some(ref coverage) = coverage {
+        coverage.print();
+    }
+    stats.coverage = coverage;
+    session.totals = Some((packages_dir.clone(), stats.to_json()));
+    report_stats(&stats, args.read.stats, args.read.stats_file.as_deref())?;
+    if let (Some(ref url), false) = (&args.read.submit_stats, interrupt::interrupted()) {
+        let provenance = Provenance::collect(
+            &packages_dir,
+            args.read.sim_build.clone(),
+            args.read.follow_links,
+        );
+        stats::submit(
+            url,
+            &stats.anonymized("hash", options.algorithm, &provenance),
+        );
+    }
+    if args.read.tolerate_activity && !interrupt::interrupted() {
+        settle_activity(&mut results, &packages_dir, &options)?;
+    }
+    if args.hash.find_duplicates {
+        let sets = duplicates::find_duplicates(&results);
+        duplicates::print_duplicates(&sets, |path| options.emitted_path(&packages_dir, path));
+    }
+    let provenance = if args.hash.authoritative {
+        Some(Provenance::collect(
+            &packages_dir,
+            args.read.sim_build.clone(),
+            args.read.follow_links,
+        ))
+    } else {
+        None
+    };
+    // Declared only when there are other roots than the packages directory.
+    let declared_roots: Vec<(String, String)> = if options.roots.is_empty() || args.hash.split_roots
+    {
+        Vec::new()
+    } else {
+        let packages_root = (roots::PACKAGES_ROOT.to_string(), packages_dir.clone());
+        std::iter::once(packages_root)
+            .chain(options.roots.iter().cloned())
+            .map(|(name, dir)| {
+                let dir = fs::canonicalize(&dir).unwrap_or(dir);
+                let dir = dir.to_string_lossy();
+                match options.redactor {
+                    Some(ref redactor) => (name, redactor.redact(&dir)),
+                    None => (name, dir.to_string()),
+                }
+            })
+            .collect()
+    };
+    let errors: Vec<(String, &IoError)> = read_errors
+        .iter()
+        .map(|(path, err)| (path.clone(), err))
+        .collect();
+    // `(root name, output path, results, read errors)`. With `--split-roots`
+    // every root gets its own result file and summary, with the paths
+    // relative to the root, so that each file can be verified on its own.
+    let mut jobs = vec![(
+        String::from(roots::PACKAGES_ROOT),
+        args.hash.output.clone(),
+        results.clone(),
+        errors,
+    )];
+    if args.hash.split_roots {
+        for (name, _) in &options.roots {
+            let (job_results, job_errors) = {
+                let (_, _, ref mut results, ref mut errors) = jobs[0];
+                (
+                    roots::take_root(results, name, |record| record.0.to_string()),
+                    roots::take_root(errors, name, |error| error.0.clone()),
+                )
+            };
+            let outpath = args
+                .hash
+                .output
+                .as_deref()
+                .map(|outpath| roots::split_output_path(outpath, name));
+            jobs.push((name.clone(), outpath, job_results, job_errors));
+        }
+    }
+    let algorithms = options.algorithms();
+    let header = RunHeader {
+        provenance: provenance.as_ref(),
+        algorithms: &algorithms,
+        chunk_size: options.chunk_size,
+        sample: options.sample.map(|sample| sample / MIB),
+        tree: options.tree.map(|tree| tree / MIB),
+        roots: &declared_roots,
+    };
+    for (name, outpath, mut emitted, mut errors) in jobs {
+        // The cache and the checkpoint keep the real paths.
+        let emit = |path: &str| {
+            let path = options.emitted_path(&packages_dir, path);
+            if args.hash.split_roots {
+                roots::strip_root(&path, &options.normalized(&name))
+            } else {
+                path
+            }
+        };
+        for record in emitted.iter_mut() {
+            record.0 = intern::path(&emit(&record.0.to_string()));
+            if let Some(ref mut original) = record.4.alias_of {
+                *original = intern::path(&emit(&original.to_string()));
+            }
+        }
+        emitted.sort_unstable();
+        for error in errors.iter_mut() {
+            error.0 = emit(&error.0);
+        }
+        let summary = if args.hash.summary {
+            let summary = summary::summarize(&emitted, options.algorithm);
+            if args.hash.split_roots {
+                eprintln!("\n{}:", name);
+            }
+            summary::print_summary(&summary);
+            Some(summary)
+        } else {
+            None
+        };
+        order_results(&mut emitted, args.hash.sort, args.hash.group_by);
+        let compression = outpath
+            .as_deref()
+            .and_then(|outpath| Compression::of_output(args.hash.compress, outpath));
+        match (args.hash.format, outpath) {
+            (OutputFormat::Text, Some(outpath)) if args.hash.template.is_some() => {
+                write_templated(
+                    &outpath,
+                    &emitted,
+                    args.hash.template.as_ref().unwrap(),
+                    TextStyle::new(args.line_ending, args.bom),
+                    compression,
+                )
+                .map_err(|err| AppError::OutputUnwritable(outpath, err))?;
+            }
+            (OutputFormat::Text, Some(outpath)) => {
+                write_output(
+                    &outpath,
+                    &header,
+                    &emitted,
+                    &errors,
+                    args.hash.group_by.filter(|_| args.hash.subtotals),
+                    TextStyle::new(args.line_ending, args.bom),
+                    compression,
+                )
+                .and_then(|_| match signing_key {
+                    Some(ref key) => signature::sign(
+                        &outpath,
+                        key,
+                        compression,
+                        TextStyle::new(args.line_ending, args.bom).eol(),
+                    ),
+                    None => Ok(()),
+                })
+                .map_err(|err| AppError::OutputUnwritable(outpath, err))?;
+            }
+            (OutputFormat::Text, None) => {
+                write_error_lines(&mut stdout().lock(), &errors, "\n")
+                    .map_err(|err| AppError::OutputUnwritable(PathBuf::from("<stdout>"), err))?;
+            }
+            (OutputFormat::Html, outpath) => {
+                let summary = match summary {
+                    Some(summary) => summary,
+                    None => summary::summarize(&emitted, options.algorithm),
+                };
+                let page = html::hash_page(&header, &emitted, &errors, &summary);
+                write_report(outpath.as_deref(), &page, compression)?;
+            }
+            (OutputFormat::Markdown, outpath) => {
+                let summary = match summary {
+                    Some(summary) => summary,
+                    None => summary::summarize(&emitted, options.algorithm),
+                };
+                let report = markdown::hash_report(&header, &emitted, &errors, &summary);
+                write_report(outpath.as_deref(), &report, compression)?;
+            }
+            (OutputFormat::Json, outpath) => {
+                let json = json_output(&header, &emitted, &errors, summary.as_ref());
+                let written = match outpath {
+                    Some(ref outpath) => OutputFile::create(outpath, compression).and_then(|fhw| {
+                        let style = TextStyle::new(args.line_ending, args.bom);
+                        let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, fhw);
+                        style.start(&mut writer)?;
+                        let text = serde_json::to_string_pretty(&json)?;
+                        write!(writer, "{}{}", style.convert(&text), style.eol())?;
+                        compress::finish(writer)
+                    }),
+                    None => {
+                        let mut writer = stdout().lock();
+                        serde_json::to_writer_pretty(&mut writer, &json)
+                            .map_err(IoError::from)
+                            .and_then(|_| writeln!(writer))
+                    }
+                };
+                written.map_err(|err| {
+                    AppError::OutputUnwritable(outpath.unwrap_or_else(|| PathBuf::from("-")), err)
+                })?;
+            }
+            (OutputFormat::Coreutils, outpath) => {
+                let written = match outpath {
+                    Some(ref outpath) => OutputFile::create(outpath, compression).and_then(|fhw| {
+                        let style = TextStyle::new(args.line_ending, args.bom);
+                        let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, fhw);
+                        write_coreutils(&mut writer, &emitted, style.eol())?;
+                        compress::finish(writer)
+                    }),
+                    None => write_coreutils(&mut stdout().lock(), &emitted, "\n"),
+                };
+                written.map_err(|err| {
+                    AppError::OutputUnwritable(outpath.unwrap_or_else(|| PathBuf::from("-")), err)
+                })?;
+            }
+            (OutputFormat::Jsonl, outpath) => {
+                // The files were written while hashing, but for the
+                // `--split-roots` files; the read errors follow them.
+                let streamed = options.live.is_some();
+                let write_lines = |writer: &mut dyn Write| -> IoResult<()> {
+                    if !streamed {
+                        for record in &emitted {
+                            let file = json_file(
+                                &record.0.to_string(),
+                                record,
+                                &algorithms,
+                                options.chunk_size,
+                                options.sample,
+                            );
+                            writeln!(writer, "{}", file)?;
+                        }
+                    }
+                    for (path, err) in &errors {
+                        writeln!(writer, "{}", json!({ "error": json_error(path, err) }))?;
+                    }
+                    Ok(())
+                };
+                // A compressed file gets the errors as a second stream,
+                // read back as one.
+                let written = match outpath {
+                    Some(ref outpath) => if streamed {
+                        OpenOptions::new().append(true).open(outpath)
+                    } else {
+                        File::create(outpath)
+                    }
+                    .and_then(|fhw| OutputFile::new(fhw, compression))
+                    .and_then(|fhw| {
+                        let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, fhw);
+                        write_lines(&mut writer)?;
+                        compress::finish(writer)
+                    }),
+                    None => {
+                        let mut writer =
+                            BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, stdout().lock());
+                        write_lines(&mut writer).and_then(|_| writer.flush())
+                    }
+                };
+                written.map_err(|err| {
+                    AppError::OutputUnwritable(outpath.unwrap_or_else(|| PathBuf::from("-")), err)
+                })?;
+            }
+        }
+    }
+    // The partial results are written to the output as well, but the cache
+    // is left alone: the checkpoint holds them until the run is resumed.
+    if interrupt::interrupted() {
+        return Err(save_checkpoint(
+            checkpoint_file,
+            &packages_dir,
+            options.algorithm,
+            &results,
+            &cache,
+        ));
+    }
+    if let Some(cache_path) = cache_file {
+        save_cache(
+            &cache_path,
+            &packages_dir,
+            options.algorithm,
+            &results,
+            &cache,
+            |path| options.source_file(&packages_dir, path),
+        )
+        .map_err(|err| AppError::OutputUnwritable(cache_path, err))?;
+    }
+    if let Some(ref db) = args.db {
+        history::record_run(db, started_at, &packages_dir, options.algorithm, &results)?;
+    }
+    remove_checkpoint(args.read.resume.as_deref());
+    if let Some((store, mut snapshot, keep_text)) = snapshot {
+        snapshot.files = results.len();
+        if let Some(max_size) = keep_text {
+            let archive = store.content_path(&snapshot);
+            let kept = textdiff::save_content(
+                &archive,
+                results.iter().filter_map(|(path, _, filesize, _, _)| {
+                    let path = path.to_string();
+                    let file = options.source_file(&packages_dir, &path)?;
+                    Some((path, file, *filesize))
+                }),
+                max_size,
+            )
+            .map_err(|err| AppError::OutputUnwritable(archive, err))?;
+            eprintln!("Kept the content of {} text files.", kept);
+        }
+        eprintln!(
+            "Saved snapshot {} ({} files).",
+            snapshot.name, snapshot.files
+        );
+        store.register(snapshot)?;
+    }
+    report_read_errors(&read_errors);
+    antivirus::report_interference(&read_errors, |path| {
+        options
+            .source_file(&packages_dir, path)?
+            .metadata()
+            .ok()?
+            .modified()
+            .ok()
+    });
+    if args.hash.watch {
+        let outpath = args.hash.output.as_deref().unwrap();
+        let style = TextStyle::new(args.line_ending, args.bom);
+        let compression = Compression::of_output(args.hash.compress, outpath);
+        watch::watch(&packages_dir, outpath, &options, &results, |results| {
+            let mut emitted: Vec<FileRecord> = results
+                .iter()
+                .map(|record| {
+                    let mut record = record.clone();
+                    record.0 =
+                        intern::path(&options.emitted_path(&packages_dir, &record.0.to_string()));
+                    record
+                })
+                .collect();
+            emitted.sort_unstable();
+            order_results(&mut emitted, args.hash.sort, args.hash.group_by);
+            write_output(
+                outpath,
+                &header,
+                &emitted,
+                &[],
+                args.hash.group_by.filter(|_| args.hash.subtotals),
+                style,
+                compression,
+            )
+            .and_then(|_| match signing_key {
+                Some(ref key) => signature::sign(outpath, key, compression, style.eol()),
+                None => Ok(()),
+            })
+        })?;
+    }
+    if args.hash.compare_live {
+        compare_live(&args, &packages_dir, options, &results, case_sensitive)?;
+    }
+    // A partial run finds fewer files on purpose. Checked before the read
+    // errors: a drive lost halfway is both, and the banner matters more.
+    let partial = !args.read.include.is_empty()
+        || !args.read.exclude.is_empty()
+        || args.hash.time_budget.is_some()
+        || args.hash.changed_since.is_some()
+        || args.hash.stdin
+        || interrupt::interrupted();
+    let too_few = match previous_files {
+        _ if results.is_empty() => args.hash.changed_since.is_none(),
+        Some(previous) => !partial && results.len() * 2 < previous,
+        None => false,
+    };
+    if too_few && !args.hash.allow_few_files {
+        let banner = "*".repeat(80);
+        eprintln!(
+            "\n{}\nWarning: this result looks incomplete, do not keep it as a reference.\n{}",
+            banner, banner
+        );
+        return Err(AppError::TooFewFiles(
+            results.len(),
+            previous_files.filter(|_| !results.is_empty()),
+        ));
+    }
+    if !read_errors.is_empty() {
+        return Err(AppError::ReadErrorsOccurred(read_errors.len()));
+    }
+    if let Some((file, problems)) = rolling_cache.filter(|(_, problems)| *problems > 0) {
+        return Err(AppError::RollingCacheInvalid(file, problems));
+    }
+    Ok(())
+}
+
+/// Locate and check the rolling cache (`--check-rolling-cache`), returning
+/// the file and the number of problems found. A missing file is an error
+/// right away, there is nothing to hash.
+fn check_rolling_cache(args: &Args) -> Result<(PathBuf, usize), AppError> {
+    let usercfg = match args.cfgfile.clone().or_else(|| find_msfs_usercfg(args)) {
+        Some(usercfg) => usercfg,
+        None => return Err(AppError::ConfigNotFound),
+    };
+    let settings = UserCfg::read(&usercfg)
+        .map_err(|err| AppError::ManifestUnreadable(usercfg.clone(), err))?;
+    let rolling_cache = RollingCache::locate(&usercfg, &settings);
+    let problems = rolling_cache.check().len();
+    if !rolling_cache.file.is_file() {
+        return Err(AppError::RollingCacheInvalid(
+            rolling_cache.file,
+            problems.max(1),
+        ));
+    }
+    Ok((rolling_cache.file, problems))
+}
+
+/// Hash the live install the way `packages_dir` was hashed and print the
+/// files of `packages_dir` that differ from it (`--compare-live`). The other
+/// roots and the config files are left out.
+fn compare_live(
+    args: &Args,
+    packages_dir: &Path,
+    options: HashOptions,
+    results: &[FileRecord],
+    case_sensitive: bool,
+) -> Result<(), AppError> {
+    let live_dir = live_packages_dir(args)?;
+    if let Err(err) = live_dir.metadata() {
+        return Err(AppError::PackagesDirMissing(live_dir, err));
+    }
+    if fs::canonicalize(&live_dir).ok() == fs::canonicalize(packages_dir).ok() {
+        eprintln!("\nThe packages directory is the live install, nothing to compare.");
+        return Ok(());
+    }
+    eprintln!(
+        "\nComparing with the live install: {:?}\n",
+        live_dir.to_string_lossy()
+    );
+    let options = HashOptions {
+        live: None,
+        usercfg: None,
+        roots: Vec::new(),
+        image_links: None,
+        deadline: None,
+        ..options
+    };
+    let (live_results, read_errors, _, _, _) =
+        hash_packages(&live_dir, &options, &HashCache::empty())?;
+    if interrupt::interrupted() {
+        eprintln!("Interrupted, the comparison with the live install is skipped.");
+        return Ok(());
+    }
+    let results: Vec<FileRecord> = results
+        .iter()
+        .filter(|record| !record.0.starts_with("@"))
+        .cloned()
+        .collect();
+    let mut live = manifest::Manifest::from_records(&live_results, options.algorithm);
+    let image = manifest::Manifest::from_records(&results, options.algorithm);
+    if !case_sensitive {
+        live.match_case(image.entries.keys().map(RecordPath::to_string));
+    }
+    verify::diff(&live, &image);
+    report_read_errors(&read_errors);
+    if !read_errors.is_empty() {
+        return Err(AppError::ReadErrorsOccurred(read_errors.len()));
+    }
+    Ok(())
+}
+
+/// Print a unified diff of the text files that differ from the reference,
+/// against their content kept in snapshot `name` (`verify --show-diff`).
+fn show_text_diffs(
+    kept_text: &mut textdiff::SavedContent,
+    name: &str,
+    results: &[FileRecord],
+    reference: &HashMap<RecordPath, (Digest, Option<u64>)>,
+    packages_dir: &Path,
+    options: &HashOptions,
+) {
+    for (path, hash, filesize, _, _) in results {
+        let path = &path.to_string();
+        let differs = match reference.get(&intern::path(path)) {
+            Some((expected, expected_size)) => {
+                expected != hash || expected_size.is_some_and(|size| size != *filesize)
+            }
+            None => false,
+        };
+        if !differs || !textdiff::is_text(path) {
+            continue;
+        }
+        let old = match kept_text.get(path) {
+            Some(old) => old,
+            None => continue,
+        };
+        let new = match options
+            .source_file(packages_dir, path)
+            .and_then(|file| fs::read(longpath::extended(&file)).ok())
+        {
+            Some(new) => String::from_utf8_lossy(&new).into_owned(),
+            None => continue,
+        };
+        if old != new {
+            textdiff::print_diff(path, &old, &new, name);
+        }
+    }
+}
+
+/// Root the second directory of `compare` is hashed as, in the same run as
+/// the first one.
+const COMPARED_ROOT: &str = "compared";
+
+/// Hash `dir_a` and `dir_b` together and list their differences (the
+/// `compare` command).
+fn compare_dirs(
+    dir_a: &Path,
+    dir_b: &Path,
+    options: &HashOptions,
+    case_sensitive: bool,
+) -> Result<(), AppError> {
+    eprintln!("Comparing with: {:?}\n", dir_b.to_string_lossy());
+    let (mut results_a, mut read_errors, _, _, _) =
+        hash_packages(dir_a, options, &HashCache::empty())?;
+    if interrupt::interrupted() {
+        eprintln!("Interrupted, nothing compared.");
+        return Ok(());
+    }
+    let results_b: Vec<FileRecord> =
+        roots::take_root(&mut results_a, COMPARED_ROOT, |record| record.0.to_string())
+            .into_iter()
+            .map(|mut record| {
+                record.0 = intern::path(&roots::strip_root(&record.0.to_string(), COMPARED_ROOT));
+                record
+            })
+            .collect();
+    for error in read_errors.iter_mut() {
+        error.0 = match Path::new(&error.0).strip_prefix(roots::root_prefix(COMPARED_ROOT)) {
+            Ok(r_path) => dir_b.join(r_path).to_string_lossy().to_string(),
+            Err(_) => dir_a.join(&error.0).to_string_lossy().to_string(),
+        };
+    }
+    let mut a = manifest::Manifest::from_records(&results_a, options.algorithm);
+    let b = manifest::Manifest::from_records(&results_b, options.algorithm);
+    if !case_sensitive {
+        a.match_case(b.entries.keys().map(RecordPath::to_string));
+    }
+    let differences = verify::compare(&a, &b);
+    report_read_errors(&read_errors);
+    if !read_errors.is_empty() {
+        return Err(AppError::ReadErrorsOccurred(read_errors.len()));
+    }
+    if differences > 0 {
+        return Err(AppError::VerificationFailed(differences));
+    }
+    Ok(())
+}
+
+/// Compare two result files (of `diff` or `snapshot compare`).
+fn diff_result_files(
+    old_path: &Path,
+    new_path: &Path,
+    case_sensitive: bool,
+    report: Option<&Path>,
+) -> Result<(), AppError> {
+    match (
+        quick::is_quick_scan(old_path),
+        quick::is_quick_scan(new_path),
+    ) {
+        (true, true) => {
+            let read = |path: &Path| {
+                quick::read_scan(path)
+                    .map_err(|err| AppError::ManifestUnreadable(path.to_path_buf(), err))
+            };
+            quick::diff(&read(old_path)?, &read(new_path)?);
+            if report.is_some() {
+                eprintln!("Note: no report is written for quick scans.");
+            }
+            return Ok(());
+        }
+        (false, false) => {}
+        // The one that is a result file cannot be read as a quick scan.
+        (false, true) => return Err(quick_scan_mismatch(old_path)),
+        (true, false) => return Err(quick_scan_mismatch(new_path)),
+    }
+    let mut old = load_manifest(old_path)?;
+    let new = load_manifest(new_path)?;
+    if !case_sensitive {
+        old.match_case(new.entries.keys().map(RecordPath::to_string));
+    }
+    if let (Some(old_algorithm), Some(new_algorithm)) = (old.algorithm, new.algorithm) {
+        if old_algorithm != new_algorithm {
+            return Err(AppError::AlgorithmMismatch(
+                old_path.to_path_buf(),
+                old_algorithm.name(),
+                new_algorithm.name(),
+            ));
+        }
+    }
+    let findings = verify::diff(&old, &new);
+    if let Some(report) = report {
+        let about = [
+            ("old", old_path.to_string_lossy().to_string()),
+            ("new", new_path.to_string_lossy().to_string()),
+        ];
+        findings_report(report, "msfs2020checksum diff", &about, &findings)?;
+    }
+    Ok(())
+}
+
+/// Write the findings of `verify` or `diff` to `report`: as Markdown to a
+/// `.md` file, as an HTML page otherwise.
+fn findings_report(
+    report: &Path,
+    title: &str,
+    about: &[(&str, String)],
+    findings: &verify::Findings,
+) -> Result<(), AppError> {
+    let is_markdown = report
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"));
+    let text = if is_markdown {
+        markdown::findings_report(title, about, findings)
+    } else {
+        html::findings_page(title, about, findings)
+    };
+    write_report(Some(report), &text, None)
+}
+
+/// Write a report page to `outpath`, or to the stdout.
+fn write_report(
+    outpath: Option<&Path>,
+    page: &str,
+    compression: Option<Compression>,
+) -> Result<(), AppError> {
+    match outpath {
+        Some(outpath) => OutputFile::create(outpath, compression)
+            .and_then(|mut fhw| {
+                fhw.write_all(page.as_bytes())?;
+                fhw.finish()
+            })
+            .map_err(|err| AppError::OutputUnwritable(outpath.to_path_buf(), err)),
+        None => stdout()
+            .lock()
+            .write_all(page.as_bytes())
+            .map_err(|err| AppError::OutputUnwritable(PathBuf::from("-"), err)),
+    }
+}
+
+fn quick_scan_mismatch(result_file: &Path) -> AppError {
+    AppError::ManifestUnreadable(
+        result_file.to_path_buf(),
+        IoError::other("a quick scan can only be compared with another quick scan"),
+    )
+}
+
+/// List the size and modification time of every file (`--quick`), without
+/// reading them, into `outpath` or the stdout.
+fn quick_scan(
+    packages_dir: &Path,
+    options: &HashOptions,
+    outpath: Option<&Path>,
+    style: TextStyle,
+) -> Result<(), AppError> {
+    let mut sources = vec![(None, packages_dir)];
+    for (name, dir) in &options.roots {
+        sources.push((Some(roots::root_prefix(name)), dir.as_path()));
+    }
+    for (link, dir) in options.image_links.iter().flatten() {
+        sources.push((Some(link.clone()), dir.as_path()));
+    }
+    let mut scan = QuickScan::new();
+    for (prefix, dir) in sources {
+        let files: Box<dyn Iterator<Item = (PathBuf, Option<Metadata>)>> = if dir.is_file() {
+            Box::new(std::iter::once((dir.to_path_buf(), dir.metadata().ok())))
+        } else {
+            Box::new(
+                walk_packages(dir, &options.filter, options.follow_links)
+                    .filter(|entry| !entry.file_type().is_dir())
+                    .map(|entry| {
+                        let meta = entry.metadata().ok();
+                        (entry.into_path(), meta)
+                    }),
+            )
+        };
+        for (file, meta) in files {
+            let path_string = match (file.strip_prefix(dir), &prefix) {
+                (Ok(r_path), Some(prefix)) if r_path != Path::new("") => {
+                    Path::new(prefix).join(r_path).to_string_lossy().to_string()
+                }
+                (Ok(r_path), None) if r_path != Path::new("") => {
+                    r_path.to_string_lossy().to_string()
+                }
+                _ => single_file_path(&file),
+            };
+            let entry = meta.map_or((0, 0), |meta| (meta.len(), modified_nanos(&meta)));
+            scan.insert(options.emitted_path(packages_dir, &path_string), entry);
+        }
+    }
+    quick::write_scan(outpath, &scan, style).map_err(|err| {
+        AppError::OutputUnwritable(
+            outpath.map_or_else(|| PathBuf::from("<stdout>"), Path::to_path_buf),
+            err,
+        )
+    })?;
+    eprintln!("{} files listed, none read.", scan.len());
+    Ok(())
+}
+
+/// Print the statistics of the hashing and write them to `--stats-file`.
+fn report_stats(stats: &RunStats, print: bool, stats_file: Option<&Path>) -> Result<(), AppError> {
+    if print {
+        stats.print();
+    }
+    if let Some(stats_file) = stats_file {
+        stats
+            .write(stats_file)
+            .map_err(|err| AppError::OutputUnwritable(stats_file.to_path_buf(), err))?;
+    }
+    Ok(())
+}
+
+/// Save the results of an interrupted run, returning the error to exit with.
+fn save_checkpoint(
+    checkpoint_file: PathBuf,
+    packages_dir: &Path,
+    algorithm: Algorithm,
+    results: &[FileRecord],
+    cache: &HashCache,
+) -> AppError {
+    // The IDs can wait for the run that completes.
+    match save_cache(
+        &checkpoint_file,
+        packages_dir,
+        algorithm,
+        results,
+        cache,
+        |_| None,
+    ) {
+        Ok(()) => AppError::Interrupted(checkpoint_file, results.len()),
+        Err(err) => AppError::OutputUnwritable(checkpoint_file, err),
+    }
+}
+
+/// The checkpoint of a resumed run is no longer needed once it completes.
+fn remove_checkpoint(resume: Option<&Path>) {
+    if let Some(checkpoint) = resume {
+        if let Err(err) = fs::remove_file(checkpoint) {
+            if err.kind() != ErrorKind::NotFound {
+                eprintln!(
+                    "Warning: unable to remove the checkpoint {:?}: {}",
+                    checkpoint, err
+                );
+            }
+        }
+    }
+}
+
+fn load_cache(
+    cache_file: Option<&Path>,
+    refresh: bool,
+    resume: Option<&Path>,
+    max_staleness: Option<Duration>,
+    packages_dir: &Path,
+    algorithm: Algorithm,
+) -> HashCache {
+    let mut cache = match cache_file {
+        Some(cache_path) if !refresh => {
+            match HashCache::load(cache_path, packages_dir, algorithm) {
+                Ok(cache) => {
+                    eprintln!(
+                        "Loaded {} cached hashes from {:?}\n",
+                        cache.len(),
+                        cache_path
+                    );
+                    cache
+                }
+                Err(err) => {
+                    eprintln!(
+                        "Warning: unable to read the cache file {:?}: {}\n",
+                        cache_path, err
+                    );
+                    HashCache::empty()
+                }
+            }
+        }
+        _ => HashCache::empty(),
+    };
+    if let Some(checkpoint) = resume {
+        match HashCache::load(checkpoint, packages_dir, algorithm) {
+            Ok(resumed) => {
+                eprintln!(
+                    "Resuming from {:?}: {} files already hashed.\n",
+                    checkpoint,
+                    resumed.len()
+                );
+                cache.extend(resumed);
+            }
+            Err(err) => {
+                eprintln!(
+                    "Warning: unable to read the checkpoint {:?}: {}\n",
+                    checkpoint, err
+                );
+            }
+        }
+    }
+    if let Some(max_staleness) = max_staleness {
+        cache.set_max_staleness(max_staleness);
+    }
+    cache
+}
+
+/// Print the `count` files of the cache read the longest time ago, and how
+/// recently the others were read (the `stalest` command).
+fn print_stalest(cache_file: &Path, count: usize) -> Result<(), AppError> {
+    const DAY: u64 = 24 * 3600;
+    let cache = HashCache::read(cache_file)
+        .map_err(|err| AppError::ManifestUnreadable(cache_file.to_path_buf(), err))?;
+    let entries = cache.by_staleness();
+    let show = |hashed: u64| {
+        if hashed == 0 {
+            String::from("unknown")
+        } else {
+            snapshot::display_created(&settings::format_timestamp(
+                UNIX_EPOCH + Duration::from_secs(hashed),
+            ))
+        }
+    };
+    for (hashed, path) in entries.iter().take(count) {
+        println!("{}\t{}", show(*hashed), path);
+    }
+    let now = unix_secs(SystemTime::now());
+    let within = |days: u64| {
+        entries
+            .iter()
+            .filter(|(hashed, _)| *hashed > 0 && *hashed + days * DAY >= now)
+            .count()
+    };
+    let unknown = entries.iter().filter(|(hashed, _)| *hashed == 0).count();
+    eprintln!(
+        "\n{} files in the cache: {} read in the last 7 days, {} in the last 30 days, {} before, {} at an unknown time.",
+        entries.len(),
+        within(7),
+        within(30),
+        entries.len() - within(30) - unknown,
+        unknown
+    );
+    Ok(())
+}
+
+/// What the header of a result file (text or JSON) records about the run.
+struct RunHeader<'a> {
+    provenance: Option<&'a Provenance>,
+    algorithms: &'a [Algorithm],
+    chunk_size: Option<u64>,
+    /// `--sample`, in MiB: the digests of the larger files are partial.
+    sample: Option<u64>,
+    /// `--tree`, in MiB: the digest is a tree digest.
+    tree: Option<u64>,
+    roots: &'a [(String, String)],
+}
+
+fn write_output(
+    outpath: &Path,
+    header: &RunHeader,
+    results: &[FileRecord],
+    read_errors: &[(String, &IoError)],
+    subtotals: Option<GroupBy>,
+    style: TextStyle,
+    compression: Option<Compression>,
+) -> IoResult<()> {
+    let RunHeader {
+        provenance,
+        algorithms,
+        chunk_size,
+        sample,
+        tree,
+        roots,
+    } = *header;
+    let fhw = OutputFile::create(outpath, compression)?;
+    let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, fhw);
+    let eol = style.eol();
+    style.start(&mut writer)?;
+    if let Some(provenance) = provenance {
+        provenance.write_header(&mut writer, eol)?;
+    }
+    // The default algorithm is recognized by its digest length, so the
+    // header is only needed for the others, or to tell the digest columns
+    // of several algorithms apart.
+    if provenance.is_some() || algorithms != [Algorithm::Xxh3_128] {
+        let names: Vec<&str> = algorithms
+            .iter()
+            .map(|algorithm| algorithm.name())
+            .collect();
+        write!(writer, "# algorithm: {}{}", names.join(","), eol)?;
+    }
+    if let Some(chunk_size) = chunk_size {
+        write!(writer, "# chunk-size: {}{}", chunk_size / MIB, eol)?;
+    }
+    if let Some(sample) = sample {
+        write!(writer, "# sample: {}{}", sample, eol)?;
+    }
+    if let Some(tree) = tree {
+        write!(writer, "# tree: {}{}", tree, eol)?;
+    }
+    // Lets `verify` find the `@NAME` files where the roots are on its machine.
+    for (name, dir) in roots {
+        write!(writer, "# root: {}\t{}{}", name, dir, eol)?;
+    }
+    for (path, _, _, _, extra) in results {
+        if let Some(ref original) = extra.alias_of {
+            write!(writer, "# alias: {}\t{}{}", path, original, eol)?;
+        }
+    }
+    // Lets `verify` tell where files were added or removed before hashing.
+    for (dir, count) in manifest::dir_counts(results.iter().map(|r| r.0.to_string())) {
+        write!(writer, "# directory: {}\t{}{}", count, dir, eol)?;
+    }
+    write_error_lines(&mut writer, read_errors, eol)?;
+    // `(group, files, bytes)` of the entries written since the last
+    // `# subtotal:` line.
+    let mut subtotal: Option<(String, u64, u64)> = None;
+    for (path, hash, filesize, _, extra) in results {
+        if let Some(group_by) = subtotals {
+            let group = group_of(path, group_by);
+            match subtotal {
+                Some((ref current, ref mut files, ref mut bytes)) if *current == group => {
+                    *files += 1;
+                    *bytes += filesize;
+                }
+                _ => {
+                    if let Some((group, files, bytes)) = subtotal.take() {
+                        write!(writer, "# subtotal: {}\t{}\t{}{}", files, bytes, group, eol)?;
+                    }
+                    subtotal = Some((group, 1, *filesize));
+                }
+            }
+        }
+        writer.write_fmt(format_args!(
+            "{}\t{:10}\t{}{}",
+            digest_columns(hash, extra),
+            filesize,
+            path,
+            eol
+        ))?;
+        for (index, hash) in extra.chunks.iter().enumerate() {
+            write!(writer, "# chunk: {}\t{}\t{}{}", index, hash, path, eol)?;
+        }
+    }
+    if let Some((group, files, bytes)) = subtotal {
+        write!(writer, "# subtotal: {}\t{}\t{}{}", files, bytes, group, eol)?;
+    }
+    compress::finish(writer)
+}
+
+/// Write the results as the lines of `--template` only: the tools the
+/// layout is taken from do not know the `#` lines.
+fn write_templated(
+    outpath: &Path,
+    results: &[FileRecord],
+    template: &Template,
+    style: TextStyle,
+    compression: Option<Compression>,
+) -> IoResult<()> {
+    let fhw = OutputFile::create(outpath, compression)?;
+    let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, fhw);
+    let eol = style.eol();
+    style.start(&mut writer)?;
+    for record in results {
+        write!(writer, "{}{}", template.render(record), eol)?;
+    }
+    compress::finish(writer)
+}
+
+/// Write the results as the lines of `sha256sum` (`--format coreutils`),
+/// the main hash only. The tools do not know the `#` lines.
+fn write_coreutils<W: Write>(writer: &mut W, results: &[FileRecord], eol: &str) -> IoResult<()> {
+    for (path, hash, _, _, _) in results {
+        write!(
+            writer,
+            "{}{}",
+            manifest::coreutils_line(hash, &path.to_string()),
+            eol
+        )?;
+    }
+    writer.flush()
+}
+
+/// The group of the entry `path` for `--group-by`.
+fn group_of(path: &RecordPath, group_by: GroupBy) -> String {
+    match group_by {
+        GroupBy::Package => summary::package_of(&path.to_string()),
+    }
+}
+
+/// Sort the (path-sorted) results for the output file: `--group-by`, then
+/// `--sort`, then the path.
+fn order_results(results: &mut [FileRecord], sort: SortKey, group_by: Option<GroupBy>) {
+    if sort == SortKey::Path && group_by.is_none() {
+        return;
+    }
+    // A stable sort keeps the path order among equal keys.
+    results.sort_by_cached_key(|(path, hash, filesize, _, _)| {
+        let group = group_by.map(|group_by| group_of(path, group_by));
+        match sort {
+            SortKey::Path => (group, None, None),
+            SortKey::Size => (group, Some(Reverse(*filesize)), None),
+            SortKey::Hash => (group, None, Some(hash.clone())),
+        }
+    });
+}
+
+/// The files that could not be read, so that the results do not look
+/// complete: `# error: <path><TAB><message>`.
+fn write_error_lines(
+    writer: &mut impl Write,
+    read_errors: &[(String, &IoError)],
+    eol: &str,
+) -> IoResult<()> {
+    for (path, err) in read_errors {
+        write!(writer, "# error: {}\t{}{}", path, err, eol)?;
+    }
+    Ok(())
+}
+
+/// List the files that could not be read at the end of the run, after the
+/// lines printed while hashing.
+fn report_read_errors(read_errors: &[(String, IoError)]) {
+    if read_errors.is_empty() {
+        return;
+    }
+    eprintln!("\n{} file(s) could not be read:", read_errors.len());
+    for (path, err) in read_errors {
+        eprintln!("    {}: {}", path, err);
+    }
+}
 
-    match args.output {
-        Some(ref outpath) => {
-            if outpath.exists() {
-                let meta = outpath.metadata().unwrap();
-                if meta.is_dir() {
-                    panic!("Output path is a directory: {:?}", outpath);
-                } else {
-                    eprintln!("Warning: output file will be overwritten: {:?}\n", outpath);
-                }
-            }
-        }
-        None => {
-            eprintln!("The hash values will be output to the stdout.\n");
+fn json_output(
+    header: &RunHeader,
+    results: &[FileRecord],
+    read_errors: &[(String, &IoError)],
+    summary: Option<&BTreeMap<String, summary::PackageSummary>>,
+) -> serde_json::Value {
+    let RunHeader {
+        provenance,
+        algorithms,
+        chunk_size,
+        sample,
+        tree,
+        roots,
+    } = *header;
+    let files: Vec<serde_json::Value> = results
+        .iter()
+        .map(|record| {
+            json_file(
+                &record.0.to_string(),
+                record,
+                algorithms,
+                chunk_size,
+                sample.map(|sample| sample * MIB),
+            )
+        })
+        .collect();
+    let errors: Vec<serde_json::Value> = read_errors
+        .iter()
+        .map(|(path, err)| json_error(path, err))
+        .collect();
+    let mut json = json!({
+        "msfs2020checksum": env!("CARGO_PKG_VERSION"),
+        "algorithm": algorithms[0].name(),
+        "files": files,
+        "errors": errors,
+    });
+    if let Some(chunk_size) = chunk_size {
+        json["chunk_size"] = json!(chunk_size);
+    }
+    if let Some(sample) = sample {
+        json["sample"] = json!(sample);
+    }
+    if let Some(tree) = tree {
+        json["tree"] = json!(tree);
+    }
+    if !roots.is_empty() {
+        let roots: serde_json::Map<String, serde_json::Value> = roots
+            .iter()
+            .map(|(name, dir)| (name.clone(), json!(dir)))
+            .collect();
+        json["roots"] = roots.into();
+    }
+    if let Some(provenance) = provenance {
+        json["provenance"] = provenance.to_json();
+    }
+    if let Some(summary) = summary {
+        json["packages"] = summary::summary_json(summary);
+    }
+    json
+}
+
+/// A file of the JSON output under `path`, also a line of `--format jsonl`.
+/// `sample` is in bytes.
+fn json_file(
+    path: &str,
+    record: &FileRecord,
+    algorithms: &[Algorithm],
+    chunk_size: Option<u64>,
+    sample: Option<u64>,
+) -> serde_json::Value {
+    let (_, hash, filesize, _, extra) = record;
+    let mut file = json!({
+        "path": path,
+        "hash": hash.to_string(),
+        "size": filesize,
+    });
+    if !extra.algorithms.is_empty() {
+        let hashes: serde_json::Map<String, serde_json::Value> = algorithms
+            .iter()
+            .zip(std::iter::once(hash).chain(&extra.algorithms))
+            .map(|(algorithm, hash)| (algorithm.name().to_string(), json!(hash.to_string())))
+            .collect();
+        file["hashes"] = hashes.into();
+    }
+    if let Some(chunk_size) = chunk_size {
+        if !extra.chunks.is_empty() {
+            let chunks: Vec<serde_json::Value> = extra
+                .chunks
+                .iter()
+                .enumerate()
+                .map(|(index, hash)| {
+                    let offset = index as u64 * chunk_size;
+                    json!({
+                        "offset": offset,
+                        "size": chunk_size.min(filesize - offset),
+                        "hash": hash.to_string(),
+                    })
+                })
+                .collect();
+            file["chunks"] = chunks.into();
         }
     }
+    if let Some(ref original) = extra.alias_of {
+        file["alias_of"] = json!(original.to_string());
+    }
+    if sample.is_some_and(|sample| is_sampled(*filesize, sample)) {
+        file["sampled"] = json!(true);
+    }
+    file
+}
 
-    let thread_num = if args.threads == 0 {
-        thread::available_parallelism().unwrap().into()
-    } else {
-        args.threads
-    };
+fn json_error(path: &str, err: &IoError) -> serde_json::Value {
+    json!({
+        "path": path,
+        "code": error::io_error_code(err),
+        "message": err.to_string(),
+    })
+}
+
+/// Read a result file given as input, making sure its algorithm is known.
+fn load_manifest(path: &Path) -> Result<manifest::Manifest, AppError> {
+    let manifest = manifest::read_manifest(path)
+        .map_err(|err| AppError::ManifestUnreadable(path.to_path_buf(), err))?;
+    if manifest.algorithm.is_none() && !manifest.entries.is_empty() {
+        return Err(AppError::UnknownAlgorithm(path.to_path_buf()));
+    }
+    Ok(manifest)
+}
 
-    let print_screen = args.output.is_none();
+/// Hash every file under `packages_dir` (or `packages_dir` itself if it is a
+/// file) and the `--root` directories, returning `(path, hash, size, mtime)`
+/// sorted by path, the files that could not be read and the bytes handled by
+/// each thread.
+fn hash_packages(
+    packages_dir: &Path,
+    options: &HashOptions,
+    cache: &HashCache,
+) -> Result<HashRun, AppError> {
+    let thread_num = options.thread_num;
+    let algorithms = options.algorithms();
+    let algorithms = &algorithms[..];
+    let print_screen = matches!(
+        options.live,
+        Some(Live::Text(_)) | Some(Live::Jsonl(None, _))
+    );
     let mut results = Vec::new();
+    let mut thread_bytes = Vec::new();
+    let mut reused = (0, 0);
+    let read_errors = Mutex::new(Vec::new());
+    let mut coverage = None;
+    // The progress line would be torn apart by the hash values if both go
+    // to the same terminal.
+    let show_progress = stderr().is_terminal() && !(print_screen && stdout().is_terminal());
+    let (live_outpath, compression) = match options.live {
+        Some(Live::Jsonl(Some(ref outpath), compression)) => (Some(outpath.as_path()), compression),
+        _ => (None, None),
+    };
+    let live_output = match options.live {
+        Some(_) => Some(LiveOutput::new(live_outpath, compression).map_err(|err| {
+            AppError::OutputUnwritable(live_outpath.unwrap_or(Path::new("<stdout>")).into(), err)
+        })?),
+        None => None,
+    };
 
-    if packages_dir.metadata().unwrap().is_dir() {
-        let s_package_files = Mutex::new(
-            WalkDir::new(&packages_dir)
-                .follow_links(true) // Do we really need to follow the link?
-                .into_iter()
-                .filter_map(|res| res.ok()),
-        );
-        let buffersize = get_buffer_size(thread_num) as usize;
+    if packages_dir.is_dir() {
+        // `(prefix of the emitted paths, directory)`; the packages directory
+        // has no prefix.
+        let mut sources = vec![(None, packages_dir)];
+        for (name, dir) in &options.roots {
+            sources.push((Some(roots::root_prefix(name)), dir.as_path()));
+        }
+        for (link, dir) in options.image_links.iter().flatten() {
+            sources.push((Some(link.clone()), dir.as_path()));
+        }
+        let sources = &sources;
+        let buffersize = get_buffer_size(thread_num, options)?;
         eprintln!(
             "Threads: {}\nMemory buffer: {} MiB per thread.\n",
             thread_num,
             buffersize / 1024 / 1024
         );
-        thread::scope(|s| {
+        let listed = options
+            .file_list
+            .as_ref()
+            .map(|list| listed_files(list, packages_dir, sources, options));
+        let job_name = |prefix: &Option<String>| match prefix {
+            Some(prefix) => prefix.trim_start_matches('@').to_string(),
+            None => String::from(roots::PACKAGES_ROOT),
+        };
+        let progress = match listed {
+            _ if !show_progress => None,
+            Some(ref listed) => Some(Progress::with_totals(sources.iter().enumerate().map(
+                |(source, (prefix, _))| {
+                    let files = listed.iter().filter(|file| file.1 == source);
+                    let (count, bytes) =
+                        files.fold((0, 0), |(count, bytes), file| (count + 1, bytes + file.0));
+                    (job_name(prefix), count, bytes)
+                },
+            ))),
+            // Counted by the walk itself.
+            None => Some(Progress::counting(
+                sources.iter().map(|(prefix, _)| (job_name(prefix), 0, 0)),
+            )),
+        };
+        let path_string_of = |source: usize, package_file: &Path| {
+            let (ref prefix, source_dir) = sources[source];
+            match (package_file.strip_prefix(source_dir), prefix) {
+                (Ok(r_path), Some(prefix)) => {
+                    Path::new(prefix).join(r_path).to_string_lossy().to_string()
+                }
+                (Ok(r_path), None) => r_path.to_string_lossy().to_string(),
+                (Err(_), _) => package_file.to_string_lossy().to_string(),
+            }
+        };
+        let expired = || {
+            options
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        };
+        let ((total_files, total_bytes), aliases) = thread::scope(|s| {
+            if let Some(ref progress) = progress {
+                s.spawn(|| progress.draw_loop());
+            }
+            // A dedicated thread walks the directories, once, and hands out
+            // the files largest first: a multi-gigabyte file taken last
+            // would keep one thread busy long after the others are idle.
+            let (sender, receiver) = bounded(WORK_QUEUE_SIZE);
+            let walker_progress = progress.as_ref();
+            let walker = s.spawn(move || {
+                let counted = listed.is_none();
+                let walk: Box<dyn Iterator<Item = (u64, usize, PathBuf, u64)>> = match listed {
+                    Some(listed) => Box::new(listed.into_iter()),
+                    None => Box::new(sources.iter().enumerate().flat_map(|(source, (_, dir))| {
+                        walk_packages(dir, &options.filter, options.follow_links)
+                            .filter(|entry| !entry.file_type().is_dir())
+                            .map(move |entry| {
+                                let (filesize, mtime) = entry
+                                    .metadata()
+                                    .map_or((0, 0), |meta| (meta.len(), modified_nanos(&meta)));
+                                (filesize, source, entry.into_path(), mtime)
+                            })
+                    })),
+                };
+                let walk = walk.filter(|(filesize, source, path, mtime)| {
+                    options.changed_since.as_ref().is_none_or(|scan| {
+                        scan.get(&path_string_of(*source, path)) != Some(&(*filesize, *mtime))
+                    })
+                });
+                let walk = walk.inspect(|(filesize, source, _, _)| {
+                    if let Some(progress) = walker_progress.filter(|_| counted) {
+                        progress.add_total(*source, *filesize);
+                    }
+                });
+                let walk_finished = || {
+                    if let Some(progress) = walker_progress {
+                        progress.walk_finished();
+                    }
+                };
+                let send = |(_, source, path, _): (u64, usize, PathBuf, u64)| {
+                    // Every worker is gone once interrupted.
+                    !interrupt::interrupted() && !expired() && sender.send((source, path)).is_ok()
+                };
+                // Without links to dedupe nor a time budget to plan, the files
+                // are handed out while the walk goes on, the largest first
+                // among those found and not yet handed out.
+                if !options.dedupe_links && options.deadline.is_none() {
+                    let mut window = BinaryHeap::with_capacity(REORDER_WINDOW + 1);
+                    let mut walked = (0, 0);
+                    for file in walk {
+                        walked = (walked.0 + 1, walked.1 + file.0);
+                        window.push(file);
+                        if window.len() > REORDER_WINDOW && !send(window.pop().unwrap()) {
+                            return (walked, Vec::new());
+                        }
+                    }
+                    walk_finished();
+                    while let Some(file) = window.pop() {
+                        if !send(file) {
+                            break;
+                        }
+                    }
+                    return (walked, Vec::new());
+                }
+                let mut files: Vec<(u64, usize, PathBuf, u64)> = walk.collect();
+                walk_finished();
+                let aliases = if options.dedupe_links {
+                    dedupe_links(&mut files, path_string_of)
+                } else {
+                    Vec::new()
+                };
+                let walked = (files.len(), files.iter().map(|file| file.0).sum());
+                if options.deadline.is_some() {
+                    // Within a `--time-budget` the cached files come first, as
+                    // they cost nothing, then the files never hashed, then the
+                    // most recently modified ones.
+                    files.sort_by_cached_key(|(filesize, source, path, mtime)| {
+                        let path_string = path_string_of(*source, path);
+                        let rank = if cache.lookup(&path_string, *filesize, *mtime).is_some() {
+                            0
+                        } else if !cache.contains(&path_string) {
+                            1
+                        } else {
+                            2
+                        };
+                        (rank, Reverse(*mtime))
+                    });
+                } else {
+                    files.sort_unstable_by(|a, b| b.cmp(a));
+                }
+                for file in files {
+                    if !send(file) {
+                        break;
+                    }
+                }
+                (walked, aliases)
+            });
             let mut t_handles = Vec::new();
             for _ in 0..thread_num {
-                let thread_package_files = &s_package_files;
-                let thread_packages_dir = &packages_dir;
+                let thread_receiver = receiver.clone();
+                let thread_cache = cache;
+                let thread_progress = progress.as_ref();
+                let thread_read_errors = &read_errors;
+                let thread_live_output = live_output.as_ref();
                 let handle = s.spawn(move || {
                     let mut result = Vec::new();
+                    let (mut bytes, mut reused) = (0, (0, 0));
                     let mut buffer = vec![0xFF; buffersize];
                     loop {
-                        let package_file;
-                        {
-                            let mut file_iter = thread_package_files.lock().unwrap();
-                            package_file = match file_iter.next() {
-                                Some(entry) => entry.into_path(),
-                                None => break,
-                            };
+                        if interrupt::interrupted() || expired() {
+                            break;
                         }
-                        match get_xxhash3_128_and_size(&package_file, &mut buffer[..]) {
-                            Ok(Some((hash, filesize))) => {
-                                let relative_path =
-                                    match package_file.strip_prefix(&thread_packages_dir) {
-                                        Ok(r_path) => r_path.to_path_buf(),
-                                        Err(_) => package_file,
-                                    };
-                                let path_string = relative_path.to_string_lossy().to_string();
-                                if print_screen {
-                                    println!("{:032x}\t{:10}\t{}", hash, filesize, path_string)
+                        let (source, package_file) = match thread_receiver.recv() {
+                            Ok(work) => work,
+                            Err(_) => break,
+                        };
+                        let path_string = path_string_of(source, &package_file);
+                        match with_retries(options.retries, || {
+                            hash_file(
+                                options,
+                                &package_file,
+                                &path_string,
+                                algorithms,
+                                &mut buffer[..],
+                                thread_cache,
+                            )
+                        }) {
+                            Ok(Some((hash, filesize, mtime, extra, read))) => {
+                                if let Some(progress) = thread_progress {
+                                    progress.add(source, filesize);
+                                }
+                                let record =
+                                    (intern::path(&path_string), hash, filesize, mtime, extra);
+                                if let Some(live_output) = thread_live_output {
+                                    send_live(live_output, packages_dir, options, &record);
+                                }
+                                if read {
+                                    bytes += filesize;
+                                } else {
+                                    reused.0 += 1;
+                                    reused.1 += filesize;
+                                }
+                                if options.archives && archive::is_archive(&package_file) {
+                                    match archive_digests(
+                                        &package_file,
+                                        &path_string,
+                                        algorithms,
+                                        &mut buffer[..],
+                                    ) {
+                                        Ok(members) => {
+                                            for member in members {
+                                                if let Some(live_output) = thread_live_output {
+                                                    send_live(
+                                                        live_output,
+                                                        packages_dir,
+                                                        options,
+                                                        &member,
+                                                    );
+                                                }
+                                                result.push(member);
+                                            }
+                                        }
+                                        Err(_) if interrupt::interrupted() => break,
+                                        Err(err) => {
+                                            eprintln!(
+                                                "Fail to read archive {} {}",
+                                                package_file.to_string_lossy(),
+                                                err
+                                            );
+                                            thread_read_errors
+                                                .lock()
+                                                .unwrap()
+                                                .push((record.0.to_string(), err));
+                                        }
+                                    }
                                 }
-                                result.push((path_string, hash, filesize));
+                                result.push(record);
                             }
                             Ok(_) => {}
+                            // The file was abandoned halfway, it is not broken.
+                            Err(_) if interrupt::interrupted() => break,
                             Err(err) => {
+                                if let Some(progress) = thread_progress {
+                                    progress.add(source, 0);
+                                }
                                 eprintln!(
                                     "Fail to read file {} {}",
                                     package_file.to_string_lossy(),
                                     err
                                 );
+                                thread_read_errors.lock().unwrap().push((path_string, err));
                             }
                         }
                     }
                     result.sort_unstable();
-                    result
+                    (result, bytes, reused)
                 });
                 t_handles.push(handle);
             }
+            drop(receiver);
             for handle in t_handles {
-                let mut result = handle.join().unwrap();
+                let (mut result, bytes, (files, reused_bytes)) = handle.join().unwrap();
                 results.append(&mut result);
+                thread_bytes.push(bytes);
+                reused.0 += files;
+                reused.1 += reused_bytes;
+            }
+            if let Some(ref progress) = progress {
+                progress.finish();
             }
+            walker.join().unwrap()
         });
+        // The aliases of the files hashed get their hashes; those of the
+        // files not hashed (read errors, `--time-budget`) are left out.
+        results.sort_unstable();
+        let copies: Vec<FileRecord> = aliases
+            .into_iter()
+            .filter_map(|(alias, original)| {
+                let original = intern::path(&original);
+                let index = results
+                    .binary_search_by(|record| record.0.cmp(&original))
+                    .ok()?;
+                let (ref original, ref hash, filesize, mtime, ref extra) = results[index];
+                let extra = ExtraHashes {
+                    alias_of: Some(original.clone()),
+                    ..extra.clone()
+                };
+                Some((intern::path(&alias), hash.clone(), filesize, mtime, extra))
+            })
+            .collect();
+        for record in copies {
+            if let Some(ref live_output) = live_output {
+                send_live(live_output, packages_dir, options, &record);
+            }
+            results.push(record);
+        }
         results.sort_unstable();
+        let hashed = results
+            .iter()
+            .filter(|record| !(options.archives && archive::is_member(&record.0.to_string())))
+            .count()
+            + read_errors.lock().unwrap().len();
+        if expired() && hashed < total_files {
+            coverage = Some(Coverage {
+                files: hashed,
+                bytes: results.iter().map(|r| r.2).sum(),
+                total_files,
+                total_bytes,
+            });
+        }
     } else {
-        eprintln!(
-            "{} is a file. Processing with single-threaded.\n",
-            packages_dir.to_string_lossy()
-        );
-        let buffersize = get_buffer_size(1) as usize;
+        let threads = match options.tree {
+            Some(tree) => {
+                eprintln!(
+                    "{} is a file. Processing in {} MiB leaves with {} threads (tree digest).\n",
+                    packages_dir.to_string_lossy(),
+                    tree / MIB,
+                    options.thread_num
+                );
+                options.thread_num
+            }
+            None => {
+                eprintln!(
+                    "{} is a file. Processing with single-threaded.\n",
+                    packages_dir.to_string_lossy()
+                );
+                1
+            }
+        };
+        let buffersize = get_buffer_size(threads, options)?;
         eprintln!("Memory buffer: {} MiB.\n", buffersize / 1024 / 1024);
-        let mut buffer = vec![0xFF; buffersize];
-        match get_xxhash3_128_and_size(&packages_dir, &mut buffer[..]) {
-            Ok(Some((hash, filesize))) => {
-                let path_string = packages_dir.to_string_lossy().to_string();
-                if print_screen {
-                    println!("{:032x}\t{:10}\t{}", hash, filesize, path_string)
+        // The threads of `--tree` have their own buffers.
+        let mut buffer = match options.tree {
+            Some(_) => Vec::new(),
+            None => vec![0xFF; buffersize],
+        };
+        let path_string = single_file_path(packages_dir);
+        match with_retries(options.retries, || match options.tree {
+            Some(tree) => {
+                tree_digest_and_size(packages_dir, algorithms, tree, threads, buffersize).map(Some)
+            }
+            None => hash_file(
+                options,
+                packages_dir,
+                &path_string,
+                algorithms,
+                &mut buffer[..],
+                cache,
+            ),
+        }) {
+            Ok(Some((hash, filesize, mtime, extra, read))) => {
+                let record = (intern::path(&path_string), hash, filesize, mtime, extra);
+                if let Some(ref live_output) = live_output {
+                    send_live(live_output, packages_dir, options, &record);
+                }
+                if read {
+                    thread_bytes.push(filesize);
+                } else {
+                    thread_bytes.push(0);
+                    reused = (1, filesize);
+                }
+                if options.archives && archive::is_archive(packages_dir) {
+                    buffer.resize(buffersize, 0xFF);
+                    match archive_digests(packages_dir, &path_string, algorithms, &mut buffer[..]) {
+                        Ok(members) => {
+                            for member in members {
+                                if let Some(ref live_output) = live_output {
+                                    send_live(live_output, packages_dir, options, &member);
+                                }
+                                results.push(member);
+                            }
+                        }
+                        Err(err) => {
+                            eprintln!(
+                                "Fail to read archive {} {}",
+                                packages_dir.to_string_lossy(),
+                                err
+                            );
+                            read_errors
+                                .lock()
+                                .unwrap()
+                                .push((record.0.to_string(), err));
+                        }
+                    }
                 }
-                results.push((path_string, hash, filesize));
+                results.push(record);
+                results.sort_unstable();
             }
             Ok(_) => {
                 unreachable!();
@@ -177,108 +3863,890 @@ fn main() {
                     packages_dir.to_string_lossy(),
                     err
                 );
+                read_errors.lock().unwrap().push((path_string, err));
             }
         }
     }
-    if let Some(outpath) = args.output {
-        let fhw = File::create(outpath).unwrap();
-        let mut writer = BufWriter::new(fhw);
-        for (path, hash, filesize) in results {
-            writer
-                .write_fmt(format_args!("{:032x}\t{:10}\t{}\r\n", hash, filesize, path))
-                .unwrap();
+    if let Some(ref usercfg) = options.usercfg {
+        let buffersize = get_buffer_size(1, options)?;
+        let mut buffer = vec![0xFF; buffersize];
+        for (path_string, config_file) in config::config_files(usercfg) {
+            match with_retries(options.retries, || {
+                get_digest_and_size(
+                    &config_file,
+                    &path_string,
+                    algorithms,
+                    None,
+                    false,
+                    &mut buffer[..],
+                    cache,
+                )
+            }) {
+                Ok(Some((hash, filesize, mtime, extra, _))) => {
+                    let record = (intern::path(&path_string), hash, filesize, mtime, extra);
+                    if let Some(ref live_output) = live_output {
+                        send_live(live_output, packages_dir, options, &record);
+                    }
+                    results.push(record);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!(
+                        "Fail to read file {} {}",
+                        config_file.to_string_lossy(),
+                        err
+                    );
+                    read_errors.lock().unwrap().push((path_string, err));
+                }
+            }
         }
-        writer.flush().unwrap();
+        results.sort_unstable();
     }
+    if let Some(live_output) = live_output {
+        live_output.finish().map_err(|err| {
+            AppError::OutputUnwritable(live_outpath.unwrap_or(Path::new("<stdout>")).into(), err)
+        })?;
+    }
+    let mut read_errors = read_errors.into_inner().unwrap();
+    read_errors.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    Ok((results, read_errors, thread_bytes, reused, coverage))
 }
 
-fn find_msfs_usercfg() -> Option<PathBuf> {
-    const STORE_MSFS_DIR_NAME: &str = "Microsoft.FlightSimulator_8wekyb3d8bbwe";
-    const STEAM_MSFS_DIR_NAME: &str = "Microsoft Flight Simulator";
-    {
-        let mut store_cfg = data_local_dir().unwrap();
-        store_cfg.push("Packages");
-        store_cfg.push(STORE_MSFS_DIR_NAME);
-        store_cfg.push("LocalCache");
-        store_cfg.push("UserCfg.opt");
-        if store_cfg.is_file() {
-            return Some(store_cfg);
+/// Print the line of `record` while hashing.
+fn send_live(
+    live_output: &LiveOutput,
+    packages_dir: &Path,
+    options: &HashOptions,
+    record: &FileRecord,
+) {
+    let (path_string, hash, filesize, _, extra) = record;
+    let path = options.emitted_path(packages_dir, &path_string.to_string());
+    match options.live {
+        Some(Live::Jsonl(..)) => live_output.send(format!(
+            "{}\n",
+            json_file(
+                &path,
+                record,
+                &options.algorithms(),
+                options.chunk_size,
+                options.sample
+            )
+        )),
+        Some(Live::Text(Some(ref template))) => {
+            let record = (
+                intern::path(&path),
+                hash.clone(),
+                *filesize,
+                record.3,
+                extra.clone(),
+            );
+            live_output.send(format!("{}\n", template.render(&record)))
         }
+        _ => live_output.send(format!(
+            "{}\t{:10}\t{}\n",
+            digest_columns(hash, extra),
+            filesize,
+            path
+        )),
     }
-    {
-        let mut steam_cfg = data_dir().unwrap();
-        steam_cfg.push(STEAM_MSFS_DIR_NAME);
-        steam_cfg.push("UserCfg.opt");
-        if steam_cfg.is_file() {
-            return Some(steam_cfg);
+}
+
+/// Write the lines of `results`, as `hash_packages` does while hashing, for
+/// results that were not hashed again.
+pub(crate) fn replay_live(
+    packages_dir: &Path,
+    options: &HashOptions,
+    results: &[FileRecord],
+) -> Result<(), AppError> {
+    let (outpath, compression) = match options.live {
+        Some(Live::Jsonl(Some(ref outpath), compression)) => (Some(outpath.as_path()), compression),
+        Some(_) => (None, None),
+        None => return Ok(()),
+    };
+    let unwritable =
+        |err| AppError::OutputUnwritable(outpath.unwrap_or(Path::new("<stdout>")).into(), err);
+    let live_output = LiveOutput::new(outpath, compression).map_err(unwritable)?;
+    for record in results {
+        send_live(&live_output, packages_dir, options, record);
+    }
+    live_output.finish().map_err(unwritable)
+}
+
+/// The records of the files inside the zip archive `file` (`--archives`),
+/// under `path_string` of the archive. They have no modification time.
+fn archive_digests(
+    file: &Path,
+    path_string: &str,
+    algorithms: &[Algorithm],
+    buffer: &mut [u8],
+) -> IoResult<Vec<FileRecord>> {
+    let mut records = Vec::new();
+    archive::for_each_member(file, |name, filesize, reader| {
+        let mut hashers = FileHashers::new(algorithms, None);
+        loop {
+            if interrupt::interrupted() {
+                return Err(IoError::other("interrupted"));
+            }
+            let read_size = reader.read(buffer)?;
+            if read_size == 0 {
+                break;
+            }
+            hashers.update(&buffer[..read_size]);
+        }
+        let (hash, extra) = hashers.finish();
+        records.push((
+            intern::path(&archive::member_path(path_string, name)),
+            hash,
+            filesize,
+            0,
+            extra,
+        ));
+        Ok(())
+    })?;
+    Ok(records)
+}
+
+/// Wait before the first `--retries` attempt, doubled before each next one.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Run `read` again, up to `retries` times, while it fails: the sim locks
+/// the files it is updating for a moment. A missing file is not retried.
+fn with_retries<T>(retries: u32, mut read: impl FnMut() -> IoResult<T>) -> IoResult<T> {
+    let mut delay = RETRY_DELAY;
+    for _ in 0..retries {
+        match read() {
+            Err(err) if err.kind() != ErrorKind::NotFound && !interrupt::interrupted() => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
         }
     }
-    {
-        for entry in WalkDir::new(data_dir().unwrap())
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|res| res.ok())
-        {
-            if entry.file_type().is_file() && entry.file_name() == "UserCfg.opt" {
-                let path = entry.path().to_str().unwrap();
-                let path = path.to_ascii_lowercase();
-                if path.contains("microsoft") && path.contains("flight") {
-                    return Some(entry.path().to_path_buf());
-                }
+    read()
+}
+
+/// Delay before the files that do not match the reference are read again.
+const RECHECK_DELAY: Duration = Duration::from_secs(2);
+
+/// Read again, once and without the cache, the files whose hash or size
+/// differs from the reference, so that a file written (by the sim, an
+/// updater, a backup tool) during the first read is not reported as broken.
+/// The new results replace the old ones.
+fn recheck_mismatches(
+    results: &mut [FileRecord],
+    reference: &HashMap<RecordPath, (Digest, Option<u64>)>,
+    packages_dir: &Path,
+    options: &HashOptions,
+) -> Result<(), AppError> {
+    let mismatches: Vec<usize> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, (path, hash, filesize, _, _))| {
+            reference.get(path).is_some_and(|expected| {
+                expected.0 != *hash || expected.1.is_some_and(|size| size != *filesize)
+            })
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "Reading again {} file(s) that do not match the reference...",
+        mismatches.len()
+    );
+    thread::sleep(RECHECK_DELAY);
+    let changed = rehash_files(results, &mismatches, packages_dir, options)?;
+    if changed > 0 {
+        eprintln!("{} file(s) changed since they were first read.", changed);
+    }
+    eprintln!();
+    Ok(())
+}
+
+/// Passes of `--tolerate-activity` before giving up on files that keep
+/// changing.
+const ACTIVITY_PASSES: usize = 3;
+
+/// For `--tolerate-activity`: hash again the files whose size or modification
+/// time changed since they were read (downloaded or updated by the sim during
+/// the scan), until none changes or after `ACTIVITY_PASSES` passes.
+fn settle_activity(
+    results: &mut [FileRecord],
+    packages_dir: &Path,
+    options: &HashOptions,
+) -> Result<(), AppError> {
+    for _ in 0..ACTIVITY_PASSES {
+        // Files removed since are left as they were read.
+        let changed: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, (path, _, filesize, mtime, _))| {
+                options
+                    .source_file(packages_dir, &path.to_string())
+                    .and_then(|file| file.metadata().ok())
+                    .is_some_and(|meta| meta.len() != *filesize || modified_nanos(&meta) != *mtime)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if changed.is_empty() || interrupt::interrupted() {
+            return Ok(());
+        }
+        eprintln!(
+            "{} file(s) changed during the scan, hashing them again...",
+            changed.len()
+        );
+        rehash_files(results, &changed, packages_dir, options)?;
+    }
+    eprintln!(
+        "Warning: files are still changing after {} passes, the results may be inconsistent.",
+        ACTIVITY_PASSES
+    );
+    Ok(())
+}
+
+/// Hash again, without the cache, the results at `indices`, returning how
+/// many have a different hash or size. A file that cannot be read this time
+/// keeps its first result.
+fn rehash_files(
+    results: &mut [FileRecord],
+    indices: &[usize],
+    packages_dir: &Path,
+    options: &HashOptions,
+) -> Result<usize, AppError> {
+    let algorithms = options.algorithms();
+    let mut buffer = vec![0xFF; get_buffer_size(1, options)?];
+    let no_cache = HashCache::empty();
+    let mut changed = 0;
+    for &index in indices {
+        if interrupt::interrupted() {
+            break;
+        }
+        let path_string = &results[index].0.to_string();
+        let file = match options.source_file(packages_dir, path_string) {
+            Some(file) => file,
+            None => continue,
+        };
+        if let Ok(Some((hash, filesize, mtime, extra, _))) = hash_file(
+            options,
+            &file,
+            path_string,
+            &algorithms,
+            &mut buffer[..],
+            &no_cache,
+        ) {
+            let record = &mut results[index];
+            if record.1 != hash || record.2 != filesize {
+                changed += 1;
             }
+            (record.1, record.2, record.3, record.4) = (hash, filesize, mtime, extra);
         }
     }
+    Ok(changed)
+}
+
+/// Keep the first path (in path order) of each physical file among `files`
+/// (`(size, source, path, mtime)` of the walk) for `--dedupe-links`.
+/// Returns the other paths, as `(alias, path kept)`.
+fn dedupe_links(
+    files: &mut Vec<(u64, usize, PathBuf, u64)>,
+    path_string_of: impl Fn(usize, &Path) -> String,
+) -> Vec<(String, String)> {
+    files.sort_by_cached_key(|(_, source, path, _)| path_string_of(*source, path));
+    let mut seen: HashMap<(u64, u64), String> = HashMap::new();
+    let mut aliases = Vec::new();
+    files.retain(|(_, source, path, _)| {
+        // A file whose ID cannot be read is hashed on its own.
+        let Some(id) = fileid::file_id(path) else {
+            return true;
+        };
+        let path_string = path_string_of(*source, path);
+        match seen.get(&id) {
+            Some(original) => {
+                aliases.push((path_string, original.clone()));
+                false
+            }
+            None => {
+                seen.insert(id, path_string);
+                true
+            }
+        }
+    });
+    if !aliases.is_empty() {
+        eprintln!(
+            "{} file(s) reached through several paths are hashed once.\n",
+            aliases.len()
+        );
+    }
+    aliases
+}
+
+/// The paths of `--stdin`, one per line or separated by NULs, the empty
+/// ones left out.
+fn read_file_list(null: bool) -> IoResult<Vec<String>> {
+    let mut input = Vec::new();
+    stdin().lock().read_to_end(&mut input)?;
+    let separator = if null { b'\0' } else { b'\n' };
+    Ok(input
+        .split(|&byte| byte == separator)
+        .map(|path| {
+            String::from_utf8_lossy(path)
+                .trim_end_matches('\r')
+                .to_string()
+        })
+        .filter(|path| !path.is_empty())
+        .collect())
+}
+
+/// `(size, source, file, mtime)` of the `--stdin` files, as the walk gives
+/// them. A relative path is under the packages directory, or under a root
+/// for `@NAME\...`. A file that does not exist is kept, to be reported as
+/// unreadable; a directory is left out.
+fn listed_files(
+    list: &[String],
+    packages_dir: &Path,
+    sources: &[(Option<String>, &Path)],
+    options: &HashOptions,
+) -> Vec<(u64, usize, PathBuf, u64)> {
+    list.iter()
+        .filter_map(|path| {
+            let file = if Path::new(path).is_absolute() {
+                // Under the source as given, which may be relative.
+                let current_dir = std::env::current_dir().unwrap_or_default();
+                sources
+                    .iter()
+                    .rev()
+                    .find_map(|(_, dir)| {
+                        let r_path = Path::new(path).strip_prefix(current_dir.join(dir)).ok()?;
+                        Some(dir.join(r_path))
+                    })
+                    .unwrap_or_else(|| PathBuf::from(path))
+            } else {
+                options
+                    .source_file(packages_dir, path)
+                    .unwrap_or_else(|| packages_dir.join(path))
+            };
+            let meta = file.metadata().ok();
+            if meta.as_ref().is_some_and(|meta| meta.is_dir()) {
+                return None;
+            }
+            let (filesize, mtime) = meta.map_or((0, 0), |meta| (meta.len(), modified_nanos(&meta)));
+            // Files outside every source keep their whole path.
+            let source = sources
+                .iter()
+                .rposition(|(_, dir)| file.starts_with(dir))
+                .unwrap_or(0);
+            Some((filesize, source, file, mtime))
+        })
+        .collect()
+}
+
+/// Walk `packages_dir` (see `fileid::walk`), keeping the directories and
+/// the files accepted by `filter`. With `--image`, the links left out are
+/// walked from their target in the image.
+fn walk_packages<'a>(
+    packages_dir: &'a Path,
+    filter: &'a PathFilter,
+    follow_links: bool,
+) -> impl Iterator<Item = DirEntry> + 'a {
+    fileid::walk(WalkDir::new(packages_dir), follow_links).filter(move |entry| {
+        entry.file_type().is_dir()
+            || filter.is_match(
+                entry
+                    .path()
+                    .strip_prefix(packages_dir)
+                    .unwrap_or(entry.path()),
+            )
+    })
+}
+
+fn resolve_packages_dir(args: &Args) -> Result<PathBuf, AppError> {
+    if let Some(Command::Compare { ref dir_a, .. }) = args.command {
+        return Ok(dir_a.clone());
+    }
+    match args.packages.first() {
+        Some(packages_path) if args.exact_packages => Ok(packages_path.clone()),
+        Some(packages_path) => match official_level(packages_path) {
+            Some(official) => {
+                eprintln!(
+                    "Note: {:?} is not the `Official` folder, hashing {:?} as without `--packages` (add `--exact-packages` to hash it as given).\n",
+                    packages_path, official
+                );
+                Ok(official)
+            }
+            None => Ok(packages_path.clone()),
+        },
+        None => live_packages_dir(args),
+    }
+}
+
+/// The `Official` folder of the install when `dir` is one level off: its
+/// `OneStore` / `Steam` folder, or the `InstalledPackagesPath` above it.
+/// The relative paths are then those of a run without `--packages`.
+fn official_level(dir: &Path) -> Option<PathBuf> {
+    let is_named = |path: &Path, name: &str| {
+        path.file_name()
+            .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+    };
+    if is_named(dir, "OneStore") || is_named(dir, "Steam") {
+        let parent = dir.parent()?;
+        return is_named(parent, "Official").then(|| parent.to_path_buf());
+    }
+    let official = dir.join("Official");
+    // `Community` sits next to `Official` in every install, which tells the
+    // `InstalledPackagesPath` from a folder that merely has an `Official`
+    // folder.
+    if !is_named(dir, "Official") && official.is_dir() && dir.join("Community").is_dir() {
+        return Some(official);
+    }
     None
 }
 
+/// The `Official` directory of the install on this machine, from
+/// `UserCfg.opt`.
+fn live_packages_dir(args: &Args) -> Result<PathBuf, AppError> {
+    let usercfg = match args.cfgfile {
+        Some(ref cfg_path) => cfg_path.clone(),
+        None => match find_msfs_usercfg(args) {
+            Some(cfg_path) => cfg_path,
+            None => {
+                return Err(AppError::ConfigNotFound);
+            }
+        },
+    };
+    match get_msfs_packages_dir(&usercfg) {
+        Some(mut packages_path) => {
+            packages_path.push("Official");
+            Ok(packages_path)
+        }
+        None => Err(AppError::PackagesPathNotConfigured(usercfg)),
+    }
+}
+
+/// `UserCfg.opt`, found by the discovery strategies not skipped. The
+/// strategies tried are listed with `--verbose`, or when none finds it.
+fn find_msfs_usercfg(args: &Args) -> Option<PathBuf> {
+    let (usercfg, trail) = discovery::find_usercfg(&args.skip_discovery);
+    if usercfg.is_none() || args.verbose {
+        eprintln!("Looking for `UserCfg.opt`:");
+        discovery::print_trail(&trail);
+        eprintln!();
+    }
+    usercfg
+}
+
 fn get_msfs_packages_dir(usercfg: &Path) -> Option<PathBuf> {
-    let fhr = File::open(usercfg).unwrap();
-    for line in BufReader::new(fhr).lines() {
-        let line = line.unwrap();
-        let line = line.trim();
-        if line.starts_with("InstalledPackagesPath") {
-            let path_txt = line.split_once(' ').unwrap().1;
-            return Some(PathBuf::from(&path_txt[1..(path_txt.len() - 1)]));
+    let settings = UserCfg::read(usercfg).ok()?;
+    let path_txt = settings.installed_packages_path()?;
+    // Inside a Proton prefix the sim writes a Windows path.
+    if !cfg!(target_os = "windows") {
+        if let Some(host_path) = steam::translate_prefix_path(usercfg, path_txt) {
+            return Some(host_path);
         }
     }
-    None
+    Some(PathBuf::from(path_txt))
+}
+
+/// The digest columns of a result line: the main hash, then the extra ones.
+fn digest_columns(hash: &Digest, extra: &ExtraHashes) -> String {
+    let mut columns = hash.to_string();
+    for hash in &extra.algorithms {
+        columns.push('\t');
+        columns.push_str(&hash.to_string());
+    }
+    columns
+}
+
+/// `get_digest_and_size` with the settings of the run: `--sample`.
+fn hash_file(
+    options: &HashOptions,
+    file: &Path,
+    path_string: &str,
+    algorithms: &[Algorithm],
+    buffer: &mut [u8],
+    cache: &HashCache,
+) -> IoResult<Option<FileHashes>> {
+    match options.sample {
+        Some(sample) => sampled_digest_and_size(file, path_string, algorithms, sample, buffer),
+        None => get_digest_and_size(
+            file,
+            path_string,
+            algorithms,
+            options.chunk_size,
+            options.mmap,
+            buffer,
+            cache,
+        ),
+    }
+}
+
+/// The tree digests of `file` (`--tree`): its leaves of `leaf` bytes are
+/// hashed on `thread_num` threads, then each algorithm hashes the digests of
+/// the leaves, in order, and the size, little-endian. They only match the
+/// tree digests of the same leaf size, never the digest of the whole file.
+fn tree_digest_and_size(
+    file: &Path,
+    algorithms: &[Algorithm],
+    leaf: u64,
+    thread_num: usize,
+    buffersize: usize,
+) -> IoResult<FileHashes> {
+    let extended = longpath::extended(file);
+    let meta = extended.metadata()?;
+    let filesize = meta.len();
+    // An empty file is one empty leaf.
+    let leaves = filesize.div_ceil(leaf).max(1) as usize;
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let digests: Mutex<Vec<Vec<Digest>>> = Mutex::new(vec![Vec::new(); leaves]);
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..thread_num.min(leaves))
+            .map(|_| {
+                s.spawn(|| -> IoResult<()> {
+                    let mut fhr = File::open(&extended)?;
+                    let mut buffer = vec![0xFF; buffersize.min(leaf as usize)];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= leaves || failed.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+                        let start = index as u64 * leaf;
+                        let mut hashers: Vec<_> = algorithms
+                            .iter()
+                            .map(|algorithm| algorithm.hasher())
+                            .collect();
+                        fhr.seek(SeekFrom::Start(start))?;
+                        let mut left = leaf.min(filesize - start);
+                        while left > 0 {
+                            if interrupt::interrupted() {
+                                return Err(IoError::other("interrupted"));
+                            }
+                            let wanted = left.min(buffer.len() as u64) as usize;
+                            let read_size = fhr.read(&mut buffer[..wanted])?;
+                            if read_size == 0 {
+                                return Err(IoError::from(ErrorKind::UnexpectedEof));
+                            }
+                            for hasher in hashers.iter_mut() {
+                                hasher.update(&buffer[..read_size]);
+                            }
+                            left -= read_size as u64;
+                        }
+                        digests.lock().unwrap()[index] =
+                            hashers.iter().map(|hasher| hasher.digest()).collect();
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|worker| {
+            let result = worker.join().unwrap();
+            if result.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+            result
+        })
+    })?;
+    let digests = digests.into_inner().unwrap();
+    let mut hashes: Vec<Digest> = algorithms
+        .iter()
+        .enumerate()
+        .map(|(index, algorithm)| {
+            let mut hasher = algorithm.hasher();
+            for leaf in &digests {
+                hasher.update(leaf[index].as_bytes());
+            }
+            hasher.update(&filesize.to_le_bytes());
+            hasher.digest()
+        })
+        .collect();
+    let hash = hashes.remove(0);
+    Ok((
+        hash,
+        filesize,
+        modified_nanos(&meta),
+        ExtraHashes {
+            algorithms: hashes,
+            ..ExtraHashes::default()
+        },
+        true,
+    ))
+}
+
+/// Whether a file of `filesize` bytes is sampled by `--sample` (`sample`
+/// bytes at each end) rather than hashed in full.
+fn is_sampled(filesize: u64, sample: u64) -> bool {
+    filesize > 2 * sample
+}
+
+/// The partial digests of `file` (`--sample`): its first and last `sample`
+/// bytes then its size, little-endian. The smaller files are hashed in full,
+/// so that the digests of most files can still be compared with those of a
+/// full run.
+fn sampled_digest_and_size(
+    file: &Path,
+    path_string: &str,
+    algorithms: &[Algorithm],
+    sample: u64,
+    buffer: &mut [u8],
+) -> IoResult<Option<FileHashes>> {
+    let extended = longpath::extended(file);
+    let meta = extended.metadata()?;
+    if meta.is_dir() {
+        return Ok(None);
+    }
+    let filesize = meta.len();
+    if !is_sampled(filesize, sample) {
+        let no_cache = HashCache::empty();
+        return get_digest_and_size(
+            file,
+            path_string,
+            algorithms,
+            None,
+            false,
+            buffer,
+            &no_cache,
+        );
+    }
+    let mut hashers = FileHashers::new(algorithms, None);
+    let mut fhr = File::open(&extended)?;
+    for start in [0, filesize - sample] {
+        fhr.seek(SeekFrom::Start(start))?;
+        let mut left = sample;
+        while left > 0 {
+            if interrupt::interrupted() {
+                return Err(IoError::other("interrupted"));
+            }
+            let wanted = left.min(buffer.len() as u64) as usize;
+            let read_size = fhr.read(&mut buffer[..wanted])?;
+            if read_size == 0 {
+                return Err(IoError::from(ErrorKind::UnexpectedEof));
+            }
+            hashers.update(&buffer[..read_size]);
+            left -= read_size as u64;
+        }
+    }
+    hashers.update(&filesize.to_le_bytes());
+    let (hash, extra) = hashers.finish();
+    Ok(Some((hash, filesize, modified_nanos(&meta), extra, true)))
 }
 
-fn get_xxhash3_128_and_size(file: &Path, buffer: &mut [u8]) -> IoResult<Option<(u128, u64)>> {
+/// Hash `file` with every algorithm of `algorithms` (the main one first)
+/// and, if it is larger than `chunk_size`, chunk by chunk, returning
+/// `(main hash, size, mtime, extra hashes, whether it was read)`: it is not
+/// when its hash comes from `cache`.
+fn get_digest_and_size(
+    file: &Path,
+    path_string: &str,
+    algorithms: &[Algorithm],
+    chunk_size: Option<u64>,
+    mmap: bool,
+    buffer: &mut [u8],
+    cache: &HashCache,
+) -> IoResult<Option<FileHashes>> {
+    let file = &longpath::extended(file);
     let meta = file.metadata()?;
     if meta.is_dir() {
         return Ok(None);
     }
     let filesize = meta.len();
-    let hash = if filesize > buffer.len() as u64 {
-        bigfile_xxhash3_128(file, buffer)
+    let mtime = modified_nanos(&meta);
+    let chunk_size = chunk_size.filter(|&chunk_size| filesize > chunk_size);
+    // The cache only holds the whole-file hashes of the main algorithm.
+    if algorithms.len() == 1 && chunk_size.is_none() {
+        if let Some(hash) = cache
+            .lookup(path_string, filesize, mtime)
+            .or_else(|| cache.lookup_moved(file, filesize, mtime))
+        {
+            return Ok(Some((hash, filesize, mtime, ExtraHashes::default(), false)));
+        }
+    }
+    let hashers = FileHashers::new(algorithms, chunk_size);
+    let (hash, extra) = if filesize > buffer.len() as u64 {
+        // A file another process has open for writing is read instead.
+        match open_unshared(file).ok().filter(|_| mmap) {
+            Some(fhr) => mmap_digest(fhr, hashers)?,
+            None => bigfile_digest(file, hashers, buffer)?,
+        }
     } else {
-        smallfile_xxhash3_128(file, buffer)
+        smallfile_digest(file, hashers, buffer)?
     };
-    Ok(Some((hash, filesize)))
+    Ok(Some((hash, filesize, mtime, extra, true)))
+}
+
+/// Below this, the hashers of `FileHashers::update` are fed one after the
+/// other, on the calling thread.
+const PARALLEL_UPDATE_MIN_SIZE: usize = 4 * 1024 * 1024;
+
+/// The hashers of one file: one per algorithm, and the chunk hasher of the
+/// files larger than `--chunk-size`.
+struct FileHashers {
+    algorithms: Vec<Algorithm>,
+    hashers: Vec<Box<dyn Hasher + Send>>,
+    chunks: Option<ChunkHasher>,
+}
+
+impl FileHashers {
+    fn new(algorithms: &[Algorithm], chunk_size: Option<u64>) -> Self {
+        FileHashers {
+            algorithms: algorithms.to_vec(),
+            hashers: algorithms
+                .iter()
+                .map(|algorithm| algorithm.hasher())
+                .collect(),
+            chunks: chunk_size.map(|chunk_size| ChunkHasher::new(algorithms[0], chunk_size)),
+        }
+    }
+
+    /// Feed `data` to every hasher. With several slow ones (SHA-256,
+    /// BLAKE3) and enough data, each slow one gets a thread of its own, so
+    /// that the slowest one sets the pace instead of the sum; otherwise a
+    /// thread would cost more than it saves.
+    fn update(&mut self, data: &[u8]) {
+        let chunks_fast = self.algorithms[0].is_fast();
+        let slow = self
+            .algorithms
+            .iter()
+            .filter(|algorithm| !algorithm.is_fast())
+            .count()
+            + usize::from(self.chunks.is_some() && !chunks_fast);
+        if slow < 2 || data.len() < PARALLEL_UPDATE_MIN_SIZE {
+            for hasher in self.hashers.iter_mut() {
+                hasher.update(data);
+            }
+            if let Some(ref mut chunks) = self.chunks {
+                chunks.update(data);
+            }
+            return;
+        }
+        let (fast, slow): (Vec<_>, Vec<_>) = self
+            .hashers
+            .iter_mut()
+            .zip(&self.algorithms)
+            .partition(|(_, algorithm)| algorithm.is_fast());
+        thread::scope(|s| {
+            for (hasher, _) in slow {
+                s.spawn(move || hasher.update(data));
+            }
+            let mut chunks = self.chunks.as_mut();
+            if let Some(chunks) = chunks.take_if(|_| !chunks_fast) {
+                s.spawn(move || chunks.update(data));
+            }
+            // The fast ones meanwhile, on this thread.
+            for (hasher, _) in fast {
+                hasher.update(data);
+            }
+            if let Some(chunks) = chunks {
+                chunks.update(data);
+            }
+        });
+    }
+
+    /// A whole file content already in memory.
+    fn hash_oneshot(self, data: &[u8]) -> (Digest, ExtraHashes) {
+        let mut hashes: Vec<Digest> = self
+            .algorithms
+            .iter()
+            .map(|algorithm| algorithm.hash_oneshot(data))
+            .collect();
+        let chunks = match self.chunks {
+            Some(mut chunks) => {
+                chunks.update(data);
+                chunks.finish()
+            }
+            None => Vec::new(),
+        };
+        let hash = hashes.remove(0);
+        (
+            hash,
+            ExtraHashes {
+                algorithms: hashes,
+                chunks,
+                alias_of: None,
+            },
+        )
+    }
+
+    fn finish(self) -> (Digest, ExtraHashes) {
+        let mut hashes: Vec<Digest> = self.hashers.iter().map(|hasher| hasher.digest()).collect();
+        let hash = hashes.remove(0);
+        (
+            hash,
+            ExtraHashes {
+                algorithms: hashes,
+                chunks: self.chunks.map_or_else(Vec::new, ChunkHasher::finish),
+                alias_of: None,
+            },
+        )
+    }
 }
 
-fn bigfile_xxhash3_128(file: &Path, buffer: &mut [u8]) -> u128 {
-    let mut fhr = File::open(file).unwrap();
-    let mut hasher = xxh3::Xxh3::new();
+fn bigfile_digest(
+    file: &Path,
+    mut hashers: FileHashers,
+    buffer: &mut [u8],
+) -> IoResult<(Digest, ExtraHashes)> {
+    let mut fhr = File::open(file)?;
     loop {
-        let read_size = fhr.read(buffer).unwrap();
+        if interrupt::interrupted() {
+            return Err(IoError::other("interrupted"));
+        }
+        let read_size = fhr.read(buffer)?;
         if read_size != 0 {
-            hasher.update(&buffer[..read_size]);
+            hashers.update(&buffer[..read_size]);
         } else {
             break;
         }
     }
-    hasher.digest128()
+    Ok(hashers.finish())
+}
+
+/// Open `file` to map it, sharing it for reading only: while it is open no
+/// other process can open it to write, truncate or delete it, and opening it
+/// fails (a sharing violation) while one has it open for writing.
+#[cfg(target_os = "windows")]
+fn open_unshared(file: &Path) -> IoResult<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows::Win32::Storage::FileSystem::FILE_SHARE_READ;
+    OpenOptions::new()
+        .read(true)
+        .share_mode(FILE_SHARE_READ.0)
+        .open(file)
+}
+
+/// Other systems cannot forbid the truncation of an open file.
+#[cfg(not(target_os = "windows"))]
+fn open_unshared(_: &Path) -> IoResult<File> {
+    Err(IoError::from(ErrorKind::Unsupported))
+}
+
+/// Hash the file `fhr`, opened by `open_unshared`, memory-mapped.
+fn mmap_digest(fhr: File, mut hashers: FileHashers) -> IoResult<(Digest, ExtraHashes)> {
+    // Fed in slices so that Ctrl+C is still noticed within a huge file.
+    const SLICE_SIZE: usize = 64 * 1024 * 1024;
+    // SAFETY: the mapping is valid only as long as no process truncates the
+    // file, or writes to it, while it is mapped. `fhr` shares the file for
+    // reading only, so no other handle able to write it exists while `fhr`
+    // is open, and `fhr` outlives `mapping`, declared after it.
+    let mapping = unsafe { Mmap::map(&fhr)? };
+    for slice in mapping.chunks(SLICE_SIZE) {
+        if interrupt::interrupted() {
+            return Err(IoError::other("interrupted"));
+        }
+        hashers.update(slice);
+    }
+    Ok(hashers.finish())
 }
 
-fn smallfile_xxhash3_128(file: &Path, buffer: &mut [u8]) -> u128 {
-    let mut fhr = File::open(file).unwrap();
-    let read_size = fhr.read(buffer).unwrap();
-    xxh3::xxh3_128(&buffer[..read_size])
+fn smallfile_digest(
+    file: &Path,
+    hashers: FileHashers,
+    buffer: &mut [u8],
+) -> IoResult<(Digest, ExtraHashes)> {
+    let mut fhr = File::open(file)?;
+    let read_size = fhr.read(buffer)?;
+    Ok(hashers.hash_oneshot(&buffer[..read_size]))
 }
 
-fn get_buffer_size(thread_number: usize) -> usize {
+fn get_buffer_size(thread_number: usize, options: &HashOptions) -> Result<usize, AppError> {
     // A large buffer can take advantage of the sequential read performance of the
     // hard disk as much as possible, whether it is a mechanical hard disk or a
     // solid-state disk.
@@ -288,18 +4756,36 @@ fn get_buffer_size(thread_number: usize) -> usize {
     const DEFAULT_BUFFERIZE: u64 = 256 * 1024 * 1024;
     const MINIMAL_BUFFERIZE: u64 = 16 * 1024 * 1024;
     let available_memory_all = available_memory();
-    let available_memory_per_thread = available_memory_all / thread_number as u64;
+    let memory_limit = match options.max_memory {
+        Some(max_memory) => (max_memory * MIB).min(available_memory_all),
+        None => available_memory_all,
+    };
+    let available_memory_per_thread = memory_limit / thread_number as u64;
+    // The small files still go through the buffer.
+    const MMAP_BUFFERIZE: u64 = 16 * 1024 * 1024;
+    let buffer_size = match options.buffer_size {
+        None if options.mmap => Some(MMAP_BUFFERIZE / MIB),
+        buffer_size => buffer_size,
+    };
+    if let Some(buffer_size) = buffer_size {
+        let bufferize = buffer_size * MIB;
+        if bufferize > available_memory_per_thread {
+            return Err(AppError::BufferTooLarge(
+                bufferize,
+                thread_number,
+                memory_limit,
+            ));
+        }
+        return Ok(bufferize as usize);
+    }
     let mut bufferize = DEFAULT_BUFFERIZE;
     while bufferize > available_memory_per_thread && bufferize >= MINIMAL_BUFFERIZE {
         bufferize /= 2;
     }
     if bufferize > available_memory_per_thread {
-        panic!(
-            "No enough memory: current {:.3} MiB",
-            available_memory_all as f64 / 1024.0 / 1024.0
-        );
+        return Err(AppError::InsufficientMemory(memory_limit));
     }
-    bufferize as usize
+    Ok(bufferize as usize)
 }
 
 #[cfg(target_os = "windows")]
@@ -309,7 +4795,56 @@ fn available_memory() -> u64 {
     unsafe {
         let mut mem_info: MEMORYSTATUSEX = zeroed();
         mem_info.dwLength = size_of::<MEMORYSTATUSEX>() as u32;
-        GlobalMemoryStatusEx(&mut mem_info).unwrap();
-        mem_info.ullAvailPhys
+        match GlobalMemoryStatusEx(&mut mem_info) {
+            Ok(()) => mem_info.ullAvailPhys,
+            Err(_) => 0,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn available_memory() -> u64 {
+    // `MemAvailable` counts the page cache that can be reclaimed, unlike
+    // `MemFree`, which older kernels are limited to. A sandbox may hide
+    // `/proc`: unknown, so the default buffers are used.
+    let meminfo = std::fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            let kib: u64 = value.trim().trim_end_matches("kB").trim().parse().ok()?;
+            Some(kib * 1024)
+        })
+    };
+    field("MemAvailable")
+        .or_else(|| field("MemFree"))
+        .unwrap_or(u64::MAX)
+}
+
+#[cfg(target_os = "macos")]
+#[allow(deprecated)] // libc suggests the mach2 crate for the Mach calls.
+fn available_memory() -> u64 {
+    use std::mem::{size_of, zeroed};
+    unsafe {
+        let mut stats: libc::vm_statistics64 = zeroed();
+        let mut count = (size_of::<libc::vm_statistics64>() / size_of::<libc::integer_t>())
+            as libc::mach_msg_type_number_t;
+        let result = libc::host_statistics64(
+            libc::mach_host_self(),
+            libc::HOST_VM_INFO64,
+            &mut stats as *mut libc::vm_statistics64 as libc::host_info64_t,
+            &mut count,
+        );
+        // Unknown, so the default buffers are used.
+        if result != libc::KERN_SUCCESS {
+            return u64::MAX;
+        }
+        // Inactive pages are given back as soon as they are needed.
+        (stats.free_count as u64 + stats.inactive_count as u64) * libc::vm_page_size as u64
     }
 }
+
+/// Elsewhere the available memory is unknown, so the default buffers are used.
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+fn available_memory() -> u64 {
+    u64::MAX
+}
@@ -0,0 +1,466 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Error as IoError, Result as IoResult};
+use std::path::Path;
+
+use crate::compress;
+use crate::hasher::{Algorithm, Digest};
+use crate::intern::{self, RecordPath};
+use crate::output::BOM;
+use crate::FileRecord;
+
+/// A result file written by `--output`, or a BSD-style checksum list.
+pub struct Manifest {
+    /// `# key: value` lines, in file order.
+    pub header: Vec<(String, String)>,
+    /// The algorithm the digests were computed with, if it could be detected.
+    pub algorithm: Option<Algorithm>,
+    /// Path -> (digest, size). BSD-style lines have no size.
+    pub entries: HashMap<RecordPath, (Digest, Option<u64>)>,
+}
+
+impl Manifest {
+    /// The results of a hashing, as if written to a result file and loaded.
+    pub fn from_records(records: &[FileRecord], algorithm: Algorithm) -> Self {
+        Manifest {
+            header: Vec::new(),
+            algorithm: Some(algorithm),
+            entries: records
+                .iter()
+                .map(|(path, hash, filesize, _, _)| (path.clone(), (hash.clone(), Some(*filesize))))
+                .collect(),
+        }
+    }
+
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn header_values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.header
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The `# root: name<TAB>path` lines, as `(name, path)`.
+    pub fn roots(&self) -> Vec<(String, String)> {
+        self.header_values("root")
+            .filter_map(|value| {
+                let (name, path) = value.split_once('\t')?;
+                Some((name.to_string(), path.to_string()))
+            })
+            .collect()
+    }
+
+    /// A size header given in MiB (`# chunk-size:`, `# sample:`, `# tree:`),
+    /// in bytes. A size that is not a number, is 0 or does not fit in bytes is
+    /// an error: the reference may come from anywhere, and hashing with such
+    /// a size would never end.
+    pub fn size_header(&self, key: &str) -> IoResult<Option<u64>> {
+        let Some(value) = self.header_value(key) else {
+            return Ok(None);
+        };
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|&mib| mib > 0)
+            .and_then(|mib| mib.checked_mul(crate::MIB))
+            .map(Some)
+            .ok_or_else(|| IoError::other(format!("invalid `# {}: {}` header", key, value)))
+    }
+
+    /// The `# chunk: index<TAB>hash<TAB>path` lines, as path -> index -> hash.
+    pub fn chunks(&self) -> HashMap<String, BTreeMap<usize, Digest>> {
+        let mut chunks: HashMap<String, BTreeMap<usize, Digest>> = HashMap::new();
+        for value in self.header_values("chunk") {
+            let mut fields = value.splitn(3, '\t');
+            let parsed = (|| {
+                let index: usize = fields.next()?.parse().ok()?;
+                let hash = Digest::from_hex(fields.next()?)?;
+                Some((index, hash, fields.next()?))
+            })();
+            if let Some((index, hash, path)) = parsed {
+                chunks
+                    .entry(path.to_string())
+                    .or_default()
+                    .insert(index, hash);
+            }
+        }
+        chunks
+    }
+
+    /// The `# directory: count<TAB>path` lines, if the file has them.
+    pub fn dir_counts(&self) -> Option<BTreeMap<String, usize>> {
+        let counts: BTreeMap<String, usize> = self
+            .header_values("directory")
+            .filter_map(|value| {
+                let (count, dir) = value.split_once('\t')?;
+                Some((dir.to_string(), count.parse().ok()?))
+            })
+            .collect();
+        if counts.is_empty() {
+            None
+        } else {
+            Some(counts)
+        }
+    }
+
+    /// Rename the entries (and `# chunk:` lines) whose path differs from a
+    /// path on disk only by case to the path on disk, so that exact lookups
+    /// find them. The paths are otherwise kept as recorded.
+    pub fn match_case(&mut self, on_disk: impl Iterator<Item = impl AsRef<str>>) {
+        let renames = case_renames(self.entries.keys().map(RecordPath::to_string), on_disk);
+        for (recorded, found) in &renames {
+            if let Some(entry) = self.entries.remove(&intern::path(recorded)) {
+                self.entries.insert(intern::path(found), entry);
+            }
+        }
+        for (key, value) in self.header.iter_mut() {
+            if key != "chunk" {
+                continue;
+            }
+            if let Some((fields, path)) = value.rsplit_once('\t') {
+                if let Some(found) = renames.get(path) {
+                    *value = format!("{}\t{}", fields, found);
+                }
+            }
+        }
+    }
+}
+
+/// `recorded path -> path on disk` for the recorded paths that are not on
+/// disk as such, but differ from a path on disk only by case. A path on disk
+/// that is also recorded as such is left to its own entry.
+pub fn case_renames(
+    recorded: impl Iterator<Item = impl AsRef<str>>,
+    on_disk: impl Iterator<Item = impl AsRef<str>>,
+) -> HashMap<String, String> {
+    let (recorded, on_disk): (Vec<_>, Vec<_>) = (recorded.collect(), on_disk.collect());
+    let recorded: HashSet<&str> = recorded.iter().map(AsRef::as_ref).collect();
+    let on_disk: HashSet<&str> = on_disk.iter().map(AsRef::as_ref).collect();
+    let folded: HashMap<String, &str> = on_disk
+        .iter()
+        .filter(|path| !recorded.contains(*path))
+        .map(|path| (path.to_lowercase(), *path))
+        .collect();
+    recorded
+        .iter()
+        .filter(|path| !on_disk.contains(*path))
+        .filter_map(|path| {
+            let found = folded.get(&path.to_lowercase())?;
+            Some((path.to_string(), found.to_string()))
+        })
+        .collect()
+}
+
+/// Number of files directly in each directory of the relative `paths`
+/// (`.` for the top level).
+pub fn dir_counts(paths: impl Iterator<Item = impl AsRef<str>>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for path in paths {
+        let dir = match Path::new(path.as_ref()).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().to_string(),
+            _ => String::from("."),
+        };
+        *counts.entry(dir).or_default() += 1;
+    }
+    counts
+}
+
+/// Number of entries of a result file or a cache file (the lines that are
+/// not header lines), without keeping them.
+pub fn count_entries(file: &Path) -> IoResult<usize> {
+    let mut count = 0;
+    for line in compress::open(file)?.lines() {
+        let line = line?;
+        let line = line.trim_start_matches(BOM).trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Read a result file written by `--output`.
+///
+/// Lines starting with `#` are header lines; those in the `# key: value`
+/// form are kept. The `hash<TAB>size<TAB>path` layout, BSD-style
+/// `TAG (path) = hash` lines and the `hash  path` lines of `sha256sum` and
+/// the like are accepted; other lines are skipped.
+///
+/// The algorithm is taken from the `# algorithm:` header, then from the
+/// BSD tags, then from the extension of the file (`.sha256`, `.b3`), then
+/// guessed from the length of the digests. When the header
+/// lists several algorithms (`xxh3-128,sha256`), the lines have one digest
+/// column per algorithm and only the first one is kept.
+pub fn read_manifest(manifest: &Path) -> IoResult<Manifest> {
+    let fhr = compress::open(manifest)?;
+    let mut header = Vec::new();
+    let mut entries = HashMap::new();
+    let mut tag_algorithm = None;
+    let mut digest_len = None;
+    // The header comes before the entries.
+    let mut digest_columns = 1;
+    for line in fhr.lines() {
+        let line = line?;
+        let line = line.trim_end_matches(['\r', '\n']).trim_start_matches(BOM);
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some((key, value)) = comment.split_once(':') {
+                let key = key.trim();
+                if key == "quick-scan" {
+                    return Err(IoError::other(
+                        "this is a quick scan, without hashes (compare it with `diff`)",
+                    ));
+                }
+                if key == "algorithm" {
+                    digest_columns = value.split(',').count();
+                }
+                let value = match key {
+                    "chunk" | "directory" | "alias" => native_separators(value.trim()),
+                    _ => value.trim().to_string(),
+                };
+                header.push((key.to_string(), value));
+            }
+            continue;
+        }
+        let parsed = match parse_bsd_line(line) {
+            Some((algorithm, path, hash)) => {
+                tag_algorithm = tag_algorithm.or(algorithm);
+                Some((path, (hash, None)))
+            }
+            None => parse_tsv_line(line, digest_columns).or_else(|| {
+                let (path, hash) = parse_coreutils_line(line)?;
+                Some((path, (hash, None)))
+            }),
+        };
+        match parsed {
+            Some((path, (hash, size))) => {
+                digest_len = digest_len.or(Some(hash.hex_len()));
+                entries.insert(intern::path(&native_separators(&path)), (hash, size));
+            }
+            None => eprintln!(
+                "Warning: skipping malformed line in {:?}: {}",
+                manifest, line
+            ),
+        }
+    }
+    let header_algorithm = header
+        .iter()
+        .find(|(key, _)| key == "algorithm")
+        .and_then(|(_, names)| Algorithm::from_name(names.split(',').next()?.trim()));
+    let extension_algorithm = manifest
+        .extension()
+        .and_then(|extension| Algorithm::from_extension(&extension.to_string_lossy()));
+    let algorithm = header_algorithm
+        .or(tag_algorithm)
+        .or(extension_algorithm)
+        .or_else(|| digest_len.and_then(Algorithm::from_digest_len));
+    Ok(Manifest {
+        header,
+        algorithm,
+        entries,
+    })
+}
+
+/// The paths of a result file written with `--forward-slashes` (or on
+/// Linux) with the separators of Windows, where a file name cannot contain
+/// `/`. Elsewhere a `\` may be part of a file name and is kept.
+pub fn native_separators(path: &str) -> String {
+    if cfg!(target_os = "windows") {
+        path.replace('/', "\\")
+    } else {
+        path.to_string()
+    }
+}
+
+fn parse_tsv_line(line: &str, digest_columns: usize) -> Option<(String, (Digest, Option<u64>))> {
+    let mut fields = line.splitn(digest_columns + 2, '\t');
+    let hash = Digest::from_hex(fields.next()?)?;
+    for _ in 1..digest_columns {
+        Digest::from_hex(fields.next()?)?;
+    }
+    let size = fields.next()?.trim().parse().ok()?;
+    let path = fields.next()?.to_string();
+    Some((path, (hash, Some(size))))
+}
+
+/// Parse `hash  path`, or `hash *path` (binary mode), as written by
+/// `sha256sum`, `b3sum` and `xxhsum`. On a line starting with `\`, the
+/// path has its `\` and newlines escaped.
+fn parse_coreutils_line(line: &str) -> Option<(String, Digest)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (hash, path) = line.split_once(' ')?;
+    let path = path.strip_prefix([' ', '*'])?;
+    let hash = Digest::from_hex(hash)?;
+    if !escaped {
+        return Some((path.to_string(), hash));
+    }
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(next) => unescaped.push(next),
+            None => unescaped.push('\\'),
+        }
+    }
+    Some((unescaped, hash))
+}
+
+/// The line of `path` in a `sha256sum`-style list: `hash  path`, with
+/// `/` separators, which the tools read on every system.
+pub fn coreutils_line(hash: &Digest, path: &str) -> String {
+    let path = path.replace('\\', "/");
+    if path.contains('\n') {
+        format!("\\{}  {}", hash, path.replace('\n', "\\n"))
+    } else {
+        format!("{}  {}", hash, path)
+    }
+}
+
+/// Parse `TAG (path) = hash`, as written by `xxhsum --tag` or `shasum --tag`.
+fn parse_bsd_line(line: &str) -> Option<(Option<Algorithm>, String, Digest)> {
+    let (tag, rest) = line.split_once(" (")?;
+    let (path, hash) = rest.rsplit_once(") = ")?;
+    let hash = Digest::from_hex(hash)?;
+    Some((Algorithm::from_bsd_tag(tag), path.to_string(), hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const XXH128: &str = "f4d0497cf9394caab34d1a3ab2bece70";
+    const SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    /// `content` read back as the result file `name`.
+    fn read(name: &str, content: &str) -> IoResult<Manifest> {
+        let file = std::env::temp_dir().join(format!(
+            "msfs2020checksum-manifest-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::write(&file, content).unwrap();
+        let manifest = read_manifest(&file);
+        fs::remove_file(&file).unwrap();
+        manifest
+    }
+
+    fn entry(manifest: &Manifest, path: &str) -> (String, Option<u64>) {
+        let (hash, size) = &manifest.entries[&intern::path(&native_separators(path))];
+        (hash.to_string(), *size)
+    }
+
+    #[test]
+    fn tsv_lines() {
+        let manifest = read(
+            "tsv.txt",
+            &format!(
+                "\u{feff}# algorithm: xxh3-128\r\n# chunk-size: 4\r\n{}\t3\tpkg/a b.txt\r\n",
+                XXH128
+            ),
+        )
+        .unwrap();
+        assert_eq!(manifest.algorithm, Some(Algorithm::Xxh3_128));
+        assert_eq!(manifest.header_value("chunk-size"), Some("4"));
+        assert_eq!(
+            entry(&manifest, "pkg/a b.txt"),
+            (XXH128.to_string(), Some(3))
+        );
+    }
+
+    #[test]
+    fn tsv_lines_of_several_algorithms_keep_the_first_digest() {
+        let manifest = read(
+            "multi.txt",
+            &format!(
+                "# algorithm: sha256,xxh3-128\n{}\t{}\t5\tb\n",
+                SHA256, XXH128
+            ),
+        )
+        .unwrap();
+        assert_eq!(manifest.algorithm, Some(Algorithm::Sha256));
+        assert_eq!(entry(&manifest, "b"), (SHA256.to_string(), Some(5)));
+    }
+
+    #[test]
+    fn bsd_lines() {
+        let manifest = read("bsd.txt", &format!("BLAKE3 (a (1).txt) = {}\n", SHA256)).unwrap();
+        // The tag wins over the length, which would say SHA-256.
+        assert_eq!(manifest.algorithm, Some(Algorithm::Blake3));
+        assert_eq!(entry(&manifest, "a (1).txt"), (SHA256.to_string(), None));
+    }
+
+    #[test]
+    fn coreutils_lines() {
+        let manifest = read(
+            "coreutils.txt",
+            &format!("{0}  x\n{0} *y\n\\{0}  new\\nline\\\\z\n", XXH128),
+        )
+        .unwrap();
+        assert_eq!(manifest.algorithm, Some(Algorithm::Xxh3_128));
+        assert_eq!(manifest.entries.len(), 3);
+        assert_eq!(entry(&manifest, "x"), (XXH128.to_string(), None));
+        assert_eq!(entry(&manifest, "y"), (XXH128.to_string(), None));
+        assert!(manifest.entries.contains_key(&intern::path("new\nline\\z")));
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let manifest = read("malformed.txt", &format!("not a line\n{}\t1\tx\n", XXH128)).unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+    }
+
+    #[test]
+    fn algorithm_from_the_header_first() {
+        let manifest = read(
+            "header.sha256",
+            &format!("# algorithm: blake3\nSHA256 (x) = {}\n", SHA256),
+        )
+        .unwrap();
+        assert_eq!(manifest.algorithm, Some(Algorithm::Blake3));
+    }
+
+    #[test]
+    fn algorithm_from_the_extension_then_the_length() {
+        let line = format!("{}  x\n", SHA256);
+        assert_eq!(
+            read("list.b3", &line).unwrap().algorithm,
+            Some(Algorithm::Blake3)
+        );
+        assert_eq!(
+            read("list.txt", &line).unwrap().algorithm,
+            Some(Algorithm::Sha256)
+        );
+        assert_eq!(
+            read("short.txt", "0123456789abcdef  x\n")
+                .unwrap()
+                .algorithm,
+            Some(Algorithm::Xxh3_64)
+        );
+        assert_eq!(
+            read("crc.txt", "0123abcd  x\n").unwrap().algorithm,
+            Some(Algorithm::Crc32)
+        );
+        assert_eq!(read("empty.txt", "").unwrap().algorithm, None);
+    }
+
+    #[test]
+    fn quick_scans_are_refused() {
+        assert!(read("quick.txt", "# quick-scan: 1\n").is_err());
+    }
+}
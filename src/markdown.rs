@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Error as IoError;
+
+use crate::summary::PackageSummary;
+use crate::verify::Findings;
+use crate::{FileRecord, RunHeader};
+
+/// The statuses of `verify` that are problems, shown in the open; the other
+/// findings are collapsed with the file lists.
+const PROBLEMS: [&str; 5] = ["MISMATCH", "CORRUPTED", "OUTDATED", "MISSING", "MOVED"];
+
+/// The report of a hashing run (`--format markdown`), for a forum post: the
+/// summary of each package as a table, every file in a collapsed section.
+pub fn hash_report(
+    header: &RunHeader,
+    results: &[FileRecord],
+    read_errors: &[(String, &IoError)],
+    summary: &BTreeMap<String, PackageSummary>,
+) -> String {
+    let algorithms: Vec<&str> = header.algorithms.iter().map(|a| a.name()).collect();
+    let mut report = format!(
+        "### msfs2020checksum {}\n\n{} files, {} bytes, {}",
+        env!("CARGO_PKG_VERSION"),
+        results.len(),
+        results.iter().map(|r| r.2).sum::<u64>(),
+        algorithms.join(", ")
+    );
+    if let Some(provenance) = header.provenance {
+        let _ = write!(report, ", {}", provenance.store);
+        if let Some(ref sim_build) = provenance.sim_build {
+            let _ = write!(report, " {}", sim_build);
+        }
+    }
+    if let Some(sample) = header.sample {
+        let _ = write!(report, ", sampled ({} MiB at both ends)", sample);
+    }
+    if let Some(tree) = header.tree {
+        let _ = write!(report, ", tree digest ({} MiB leaves)", tree);
+    }
+    report.push_str("\n\n| package | files | bytes | digest |\n|---|--:|--:|---|\n");
+    for (package, totals) in summary {
+        let _ = writeln!(
+            report,
+            "| {} | {} | {} | `{}` |",
+            cell(package),
+            totals.files,
+            totals.bytes,
+            totals.digest
+        );
+    }
+    if !read_errors.is_empty() {
+        let _ = write!(
+            report,
+            "\n**{} file(s) could not be read:**\n\n| path | error |\n|---|---|\n",
+            read_errors.len()
+        );
+        for (path, err) in read_errors {
+            let _ = writeln!(report, "| {} | {} |", cell(path), err);
+        }
+    }
+    let _ = write!(
+        report,
+        "\n<details><summary>Files ({})</summary>\n\n| path | size | hash |\n|---|--:|---|\n",
+        results.len()
+    );
+    for (path, hash, filesize, _, _) in results {
+        let _ = writeln!(
+            report,
+            "| {} | {} | `{}` |",
+            cell(&path.to_string()),
+            filesize,
+            hash
+        );
+    }
+    report.push_str("\n</details>\n");
+    report
+}
+
+/// The report of `verify --report` or `diff --report` to a `.md` file: the
+/// count of each status, the problems of `verify` as a table, the other
+/// findings collapsed.
+pub fn findings_report(title: &str, about: &[(&str, String)], findings: &Findings) -> String {
+    let mut report = format!("### {}\n\n", title);
+    for (key, value) in about {
+        let _ = writeln!(report, "* {}: {}", key, value);
+    }
+    if findings.is_empty() {
+        report.push_str("\nNo difference found.\n");
+        return report;
+    }
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (_, status) in findings {
+        *counts.entry(status).or_default() += 1;
+    }
+    report.push_str("\n| status | files |\n|---|--:|\n");
+    for (status, count) in &counts {
+        let _ = writeln!(report, "| {} | {} |", status, count);
+    }
+    let (problems, others): (Vec<_>, Vec<_>) = findings
+        .iter()
+        .partition(|(_, status)| PROBLEMS.contains(status));
+    if !problems.is_empty() {
+        report.push_str("\n| status | path |\n|---|---|\n");
+        for (path, status) in &problems {
+            let _ = writeln!(report, "| **{}** | {} |", status, cell(path));
+        }
+    }
+    if !others.is_empty() {
+        let _ = write!(
+            report,
+            "\n<details><summary>Other differences ({})</summary>\n\n| status | path |\n|---|---|\n",
+            others.len()
+        );
+        for (path, status) in &others {
+            let _ = writeln!(report, "| {} | {} |", status, cell(path));
+        }
+        report.push_str("\n</details>\n");
+    }
+    report
+}
+
+/// A path in a table cell: as code, so that `_` and `*` in file names are
+/// not read as emphasis, and with its `|` escaped, which would end the cell.
+fn cell(path: &str) -> String {
+    format!("`{}`", path.replace('`', "'").replace('|', "\\|"))
+}
@@ -0,0 +1,107 @@
+use clap::ValueEnum;
+use std::path::Path;
+
+/// Threads of the network profile, when `--threads` is not given. Parallel
+/// reads of a share compete for the same link and make the server seek.
+pub const NETWORK_THREADS: usize = 2;
+
+/// Least `--retries` of the network profile: a share may stall or drop the
+/// connection for a moment.
+pub const NETWORK_RETRIES: u32 = 3;
+
+/// How the files are read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum IoProfile {
+    /// The network profile on a network share, the local one otherwise
+    Auto,
+    /// As many threads as CPU threads, `--mmap` allowed
+    Local,
+    /// Few threads, each with the full read buffer, no memory mapping and
+    /// more retries
+    Network,
+}
+
+impl IoProfile {
+    pub fn is_network(self, path: &Path) -> bool {
+        match self {
+            IoProfile::Auto => is_network_path(path),
+            IoProfile::Local => false,
+            IoProfile::Network => true,
+        }
+    }
+}
+
+/// Whether `path` is on a mapped network drive or a UNC share.
+#[cfg(target_os = "windows")]
+fn is_network_path(path: &Path) -> bool {
+    use std::path::{Component, Prefix};
+    use windows::core::HSTRING;
+    use windows::Win32::Storage::FileSystem::GetDriveTypeW;
+    use windows::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let prefix = match path.components().next() {
+        Some(Component::Prefix(prefix)) => prefix.kind(),
+        _ => return false,
+    };
+    match prefix {
+        Prefix::UNC(..) | Prefix::VerbatimUNC(..) => true,
+        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+            let root = HSTRING::from(format!("{}:\\", letter as char));
+            unsafe { GetDriveTypeW(&root) == DRIVE_REMOTE }
+        }
+        _ => false,
+    }
+}
+
+/// Whether `path` is on a network file system, from the type of the mount
+/// it is under.
+#[cfg(target_os = "linux")]
+fn is_network_path(path: &Path) -> bool {
+    const NETWORK_FILE_SYSTEMS: [&str; 9] = [
+        "cifs",
+        "smb3",
+        "smbfs",
+        "nfs",
+        "nfs4",
+        "9p",
+        "afs",
+        "ceph",
+        "fuse.sshfs",
+    ];
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mounts = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    // `device mount_point type options ...`, spaces escaped as `\040`.
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            Some((mount_point, fields.next()?))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .is_some_and(|(_, fs_type)| NETWORK_FILE_SYSTEMS.contains(&fs_type))
+}
+
+/// Whether `path` is on a file system that is not local.
+#[cfg(target_os = "macos")]
+fn is_network_path(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::mem::zeroed;
+    use std::os::unix::ffi::OsStrExt;
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    unsafe {
+        let mut stat: libc::statfs = zeroed();
+        libc::statfs(path.as_ptr(), &mut stat) == 0 && stat.f_flags & libc::MNT_LOCAL as u32 == 0
+    }
+}
+
+/// Elsewhere a network path cannot be told apart, `--io-profile network`
+/// selects the profile.
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+fn is_network_path(_path: &Path) -> bool {
+    false
+}
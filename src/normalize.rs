@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::error::AppError;
+use crate::hasher::Digest;
+use crate::output::{TextStyle, BOM};
+
+/// Header keys in the order `--output` writes them. The other keys follow
+/// in file order, then the `# directory:` and `# error:` lines.
+const HEADER_ORDER: [&str; 9] = [
+    "msfs2020checksum",
+    "authoritative",
+    "sim-build",
+    "store",
+    "package",
+    "algorithm",
+    "chunk-size",
+    "root",
+    "alias",
+];
+
+/// Keys whose lines are sorted: their order says nothing.
+const SORTED_KEYS: [&str; 3] = ["package", "root", "alias"];
+
+enum Line {
+    /// `hash[<TAB>hash...]<TAB>size<TAB>path`
+    Tsv(Vec<Digest>, u64),
+    /// `TAG (path) = hash`
+    Bsd(String, Digest),
+}
+
+/// Rewrite the result file `input` (`fmt`) the way this version writes
+/// them: entries sorted by path, digests in lowercase, sizes aligned, one
+/// path separator, CRLF line endings (or `--line-ending`), the header lines in a fixed order and
+/// the `# directory:` lines counted again. Result files of other versions
+/// or platforms can then be compared with plain text tools.
+pub fn normalize(
+    input: &Path,
+    output: Option<&Path>,
+    forward_slashes: bool,
+    style: TextStyle,
+) -> Result<(), AppError> {
+    let text = fs::read_to_string(input)
+        .map_err(|err| AppError::ManifestUnreadable(input.to_path_buf(), err))?;
+    let separator = if forward_slashes { '/' } else { '\\' };
+    let normalized = |path: &str| path.replace(['\\', '/'], &separator.to_string());
+
+    let mut comments = Vec::new();
+    let mut header: Vec<(String, String)> = Vec::new();
+    let mut errors = Vec::new();
+    let mut chunks: BTreeMap<String, BTreeMap<usize, Digest>> = BTreeMap::new();
+    let mut entries: BTreeMap<String, Line> = BTreeMap::new();
+    let mut digest_columns = 1;
+    for line in text.trim_start_matches(BOM).lines() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let (key, value) = match comment.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    comments.push(line.to_string());
+                    continue;
+                }
+            };
+            match key {
+                "algorithm" => {
+                    digest_columns = value.split(',').count();
+                    header.push((key.to_string(), value.to_string()));
+                }
+                "chunk" => {
+                    let mut fields = value.splitn(3, '\t');
+                    let parsed = (|| {
+                        let index: usize = fields.next()?.parse().ok()?;
+                        let hash = Digest::from_hex(fields.next()?)?;
+                        Some((index, hash, fields.next()?))
+                    })();
+                    if let Some((index, hash, path)) = parsed {
+                        chunks
+                            .entry(normalized(path))
+                            .or_default()
+                            .insert(index, hash);
+                    }
+                }
+                // `--dedupe-links`: two paths.
+                "alias" => header.push((key.to_string(), normalized(value))),
+                // Counted again from the entries.
+                "directory" => {}
+                "error" => {
+                    let (path, message) = value.split_once('\t').unwrap_or((value, ""));
+                    errors.push((normalized(path), message.to_string()));
+                }
+                _ => header.push((key.to_string(), value.to_string())),
+            }
+            continue;
+        }
+        match parse_line(line, digest_columns) {
+            Some((path, entry)) => {
+                entries.insert(normalized(&path), entry);
+            }
+            None => eprintln!("Warning: skipping malformed line in {:?}: {}", input, line),
+        }
+    }
+    errors.sort();
+
+    let mut ordered: Vec<(&str, &str)> = Vec::new();
+    for key in HEADER_ORDER {
+        let mut values: Vec<&str> = header
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect();
+        if SORTED_KEYS.contains(&key) {
+            values.sort_unstable();
+        }
+        ordered.extend(values.into_iter().map(|value| (key, value)));
+    }
+    ordered.extend(
+        header
+            .iter()
+            .filter(|(k, _)| !HEADER_ORDER.contains(&k.as_str()))
+            .map(|(k, v)| (k.as_str(), v.as_str())),
+    );
+
+    let mut dir_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for path in entries.keys() {
+        let dir = path.rsplit_once(separator).map_or(".", |(dir, _)| dir);
+        *dir_counts.entry(dir).or_default() += 1;
+    }
+
+    let eol = style.eol();
+    let mut out = String::new();
+    if style.bom && output.is_some() {
+        out.push(BOM);
+    }
+    for comment in &comments {
+        out.push_str(&format!("{}{}", comment, eol));
+    }
+    for (key, value) in &ordered {
+        out.push_str(&format!("# {}: {}{}", key, value, eol));
+    }
+    for (dir, count) in &dir_counts {
+        out.push_str(&format!("# directory: {}\t{}{}", count, dir, eol));
+    }
+    for (path, message) in &errors {
+        out.push_str(&format!("# error: {}\t{}{}", path, message, eol));
+    }
+    for (path, entry) in &entries {
+        match entry {
+            Line::Tsv(digests, size) => {
+                let digests: Vec<String> = digests.iter().map(Digest::to_string).collect();
+                out.push_str(&format!(
+                    "{}\t{:10}\t{}{}",
+                    digests.join("\t"),
+                    size,
+                    path,
+                    eol
+                ));
+            }
+            Line::Bsd(tag, digest) => {
+                out.push_str(&format!("{} ({}) = {}{}", tag, path, digest, eol));
+            }
+        }
+        for (index, hash) in chunks.get(path).into_iter().flatten() {
+            out.push_str(&format!("# chunk: {}\t{}\t{}{}", index, hash, path, eol));
+        }
+    }
+
+    match output {
+        Some(outpath) => fs::write(outpath, out)
+            .map_err(|err| AppError::OutputUnwritable(outpath.to_path_buf(), err))?,
+        None => {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(out.as_bytes());
+        }
+    }
+    eprintln!("{} entries written.", entries.len());
+    Ok(())
+}
+
+fn parse_line(line: &str, digest_columns: usize) -> Option<(String, Line)> {
+    if let Some((tag, rest)) = line.split_once(" (") {
+        if let Some((path, hash)) = rest.rsplit_once(") = ") {
+            let digest = Digest::from_hex(hash)?;
+            return Some((path.to_string(), Line::Bsd(tag.to_string(), digest)));
+        }
+    }
+    let mut fields = line.splitn(digest_columns + 2, '\t');
+    let digests = (0..digest_columns)
+        .map(|_| Digest::from_hex(fields.next()?))
+        .collect::<Option<Vec<Digest>>>()?;
+    let size = fields.next()?.trim().parse().ok()?;
+    let path = fields.next()?.to_string();
+    Some((path, Line::Tsv(digests, size)))
+}
@@ -0,0 +1,107 @@
+use serde_json::Value;
+use std::process::Command;
+use std::time::Duration;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where `--notify` reports the end of a run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// POST the summary as JSON to this URL.
+    Webhook(String),
+    /// A desktop notification.
+    Toast,
+}
+
+/// Parse a `--notify` value: `webhook=<URL>` or `toast`.
+pub fn parse_target(value: &str) -> Result<Target, String> {
+    match value.split_once('=') {
+        Some(("webhook", url)) if url.starts_with("http://") || url.starts_with("https://") => {
+            Ok(Target::Webhook(url.to_string()))
+        }
+        Some(("webhook", url)) => Err(format!("`{}` is not an http(s) URL", url)),
+        None if value == "toast" => Ok(Target::Toast),
+        _ => Err(format!(
+            "unknown notification `{}`, use `webhook=<URL>` or `toast`",
+            value
+        )),
+    }
+}
+
+/// Report the end of a run to every target: `title` and `text` for the
+/// people, `summary` (the one of `--summary-json`) for the programs. A
+/// failure only warns: the run itself is over.
+pub fn notify(targets: &[Target], title: &str, text: &str, summary: &Value) {
+    for target in targets {
+        let (sent, to) = match target {
+            Target::Webhook(url) => (post(url, title, text, summary), url.as_str()),
+            Target::Toast => (toast(title, text), "the desktop"),
+        };
+        if let Err(err) = sent {
+            eprintln!("Warning: unable to notify {}: {}", to, err);
+        }
+    }
+}
+
+/// The summary with the line to show, as `text` (Slack, Teams) and
+/// `content` (Discord), so that a chat webhook can take it as it is.
+fn post(url: &str, title: &str, text: &str, summary: &Value) -> Result<(), String> {
+    let mut payload = summary.clone();
+    let line = format!("{}: {}", title, text);
+    payload["text"] = Value::from(line.as_str());
+    payload["content"] = Value::from(line);
+    ureq::AgentBuilder::new()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&payload.to_string())
+        .map(drop)
+        .map_err(|err| err.to_string())
+}
+
+/// The texts are passed in the environment, never in a script.
+#[cfg(target_os = "windows")]
+fn toast(title: &str, text: &str) -> Result<(), String> {
+    const SCRIPT: &str = "$n = [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime]
+$t = $n::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$l = $t.GetElementsByTagName('text')
+$l.Item(0).AppendChild($t.CreateTextNode($env:MSFS_NOTIFY_TITLE)) > $null
+$l.Item(1).AppendChild($t.CreateTextNode($env:MSFS_NOTIFY_TEXT)) > $null
+$n::CreateToastNotifier('{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe').Show([Windows.UI.Notifications.ToastNotification]::new($t))";
+    run(
+        Command::new("powershell").args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT]),
+        title,
+        text,
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn toast(title: &str, text: &str) -> Result<(), String> {
+    run(
+        Command::new("osascript").args([
+            "-e",
+            "display notification (system attribute \"MSFS_NOTIFY_TEXT\") with title (system attribute \"MSFS_NOTIFY_TITLE\")",
+        ]),
+        title,
+        text,
+    )
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn toast(title: &str, text: &str) -> Result<(), String> {
+    run(Command::new("notify-send").args([title, text]), title, text)
+}
+
+fn run(command: &mut Command, title: &str, text: &str) -> Result<(), String> {
+    let status = command
+        .env("MSFS_NOTIFY_TITLE", title)
+        .env("MSFS_NOTIFY_TEXT", text)
+        .status()
+        .map_err(|err| err.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("the notifier exited with {}", status))
+    }
+}
@@ -0,0 +1,194 @@
+use clap::ValueEnum;
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use std::io::{stdout, BufWriter, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+
+use crate::compress::{self, Compression, OutputFile};
+use crate::template::Template;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Capacity of the buffers in front of the output destinations. Large writes
+/// keep slow destinations (network shares, USB sticks) efficient.
+pub const OUTPUT_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Format of the hash results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// `hash<TAB>size<TAB>path` lines, readable by `verify`
+    Text,
+    /// One JSON document, written once hashing is done
+    Json,
+    /// A self-contained HTML page: the summary of each package and a
+    /// sortable table of the files, to share a result
+    Html,
+    /// Markdown tables of the packages, the files in a collapsed section,
+    /// to paste in a forum post or on Discord
+    Markdown,
+    /// One JSON object per line, written as soon as each file is hashed,
+    /// the read errors last: to pipe into `jq` while a long run goes on
+    Jsonl,
+    /// `hash  path` lines as `sha256sum`, `b3sum` and `xxhsum` write them,
+    /// to check with these tools (`sha256sum -c`); readable by `verify`
+    Coreutils,
+}
+
+/// What is written of each file as soon as it is hashed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Live {
+    /// The text lines, or those of a `--template`, to the stdout
+    Text(Option<Template>),
+    /// The JSON lines, to the output file (compressed so) or the stdout
+    Jsonl(Option<PathBuf>, Option<Compression>),
+}
+
+/// Order of the entries in the output file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// By path, as `verify` and `fmt` expect them
+    Path,
+    /// Largest files first
+    Size,
+    /// By digest, which brings the files of the same content together
+    Hash,
+}
+
+/// What the entries of the output file are grouped by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// The package folder (`@NAME/folder` under a `--root`)
+    Package,
+}
+
+/// Line ending of the text files written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LineEnding {
+    /// `\r\n`, as written by the earlier versions
+    Crlf,
+    /// `\n`, for the text tools of Linux
+    Lf,
+}
+
+/// The UTF-8 byte order mark, which Excel needs to read the paths with
+/// non-ASCII characters right.
+pub const BOM: char = '\u{feff}';
+
+/// How the text files (result files in both formats, `fmt`) are written:
+/// `--line-ending` and `--bom`.
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    pub line_ending: LineEnding,
+    pub bom: bool,
+}
+
+impl TextStyle {
+    pub fn new(line_ending: LineEnding, bom: bool) -> Self {
+        TextStyle { line_ending, bom }
+    }
+
+    pub fn eol(&self) -> &'static str {
+        match self.line_ending {
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Lf => "\n",
+        }
+    }
+
+    /// Write the byte order mark, if any: the first thing of a file.
+    pub fn start(&self, writer: &mut impl Write) -> IoResult<()> {
+        if self.bom {
+            write!(writer, "{}", BOM)?;
+        }
+        Ok(())
+    }
+
+    /// `text` written with `\n` (as by `serde_json`) in this style.
+    pub fn convert(&self, text: &str) -> String {
+        match self.line_ending {
+            LineEnding::Crlf => text.replace('\n', "\r\n"),
+            LineEnding::Lf => text.to_string(),
+        }
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle::new(LineEnding::Crlf, false)
+    }
+}
+
+/// Base of the paths in the hash results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RelativeTo {
+    /// Relative to the packages directory (or the `--root`, under `@NAME`)
+    Packages,
+    /// Relative to the package folder, without the package name
+    Package,
+    /// Absolute paths on this machine
+    Absolute,
+}
+
+/// Lines pending for longer than this are flushed even if the buffer is not
+/// full, so the output still looks live.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Lines waiting for the writer thread.
+const LIVE_QUEUE_SIZE: usize = 4096;
+
+/// Writes lines produced by the hashing threads on a dedicated thread.
+///
+/// The queue is bounded: when the destination is slower than the hashing
+/// (a slow pipe), the workers wait for it rather than the pending lines
+/// piling up in memory.
+pub struct LiveOutput {
+    sender: Sender<String>,
+    handle: JoinHandle<IoResult<()>>,
+}
+
+impl LiveOutput {
+    /// Write to `outpath`, created now, or to the stdout.
+    pub fn new(outpath: Option<&Path>, compression: Option<Compression>) -> IoResult<Self> {
+        let file = outpath
+            .map(|outpath| OutputFile::create(outpath, compression))
+            .transpose()?;
+        let (sender, receiver) = bounded::<String>(LIVE_QUEUE_SIZE);
+        let handle = thread::spawn(move || match file {
+            Some(file) => {
+                let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, file);
+                write_lines(&receiver, &mut writer)?;
+                compress::finish(writer)
+            }
+            None => {
+                let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, stdout().lock());
+                write_lines(&receiver, &mut writer)?;
+                writer.flush()
+            }
+        });
+        Ok(LiveOutput { sender, handle })
+    }
+
+    /// Queue one line (including its line ending), waiting only while the
+    /// queue is full.
+    pub fn send(&self, line: String) {
+        // The writer thread only stops early on an I/O error, which
+        // `finish` reports.
+        let _ = self.sender.send(line);
+    }
+
+    /// Wait until every queued line is written.
+    pub fn finish(self) -> IoResult<()> {
+        drop(self.sender);
+        self.handle.join().unwrap()
+    }
+}
+
+/// Write the lines received until the sender is dropped, flushing them
+/// after a pause.
+fn write_lines<W: Write>(receiver: &Receiver<String>, writer: &mut W) -> IoResult<()> {
+    loop {
+        match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(line) => writer.write_all(line.as_bytes())?,
+            Err(RecvTimeoutError::Timeout) => writer.flush()?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::{stderr, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::locale;
+
+const BAR_WIDTH: usize = 30;
+/// Width of the bar of each job when there are several.
+const JOB_BAR_WIDTH: usize = 10;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Shared progress counters, updated by the hashing threads and drawn on the
+/// stderr by a dedicated thread.
+pub struct Progress {
+    /// One per hashed directory (the packages directory and the `--root`
+    /// ones), each with its own bar.
+    jobs: Vec<Job>,
+    /// Whether the totals are complete. Until the walk that counts them is
+    /// over, neither a share done nor an ETA can be told.
+    walk_done: AtomicBool,
+    finished: AtomicBool,
+    start: Instant,
+}
+
+struct Job {
+    name: String,
+    /// Grow while the walk finds the files.
+    files_total: AtomicU64,
+    bytes_total: AtomicU64,
+    files_done: AtomicU64,
+    bytes_done: AtomicU64,
+}
+
+impl Progress {
+    /// `(name, files, bytes)` of every job, known beforehand.
+    pub fn with_totals(jobs: impl Iterator<Item = (String, u64, u64)>) -> Self {
+        let progress = Self::counting(jobs);
+        progress.walk_done.store(true, Ordering::Relaxed);
+        progress
+    }
+
+    /// Jobs whose totals the walk counts (`add_total`) while they are
+    /// hashed, until `walk_finished`.
+    pub fn counting(jobs: impl Iterator<Item = (String, u64, u64)>) -> Self {
+        let jobs = jobs
+            .map(|(name, files_total, bytes_total)| Job {
+                name,
+                files_total: AtomicU64::new(files_total),
+                bytes_total: AtomicU64::new(bytes_total),
+                files_done: AtomicU64::new(0),
+                bytes_done: AtomicU64::new(0),
+            })
+            .collect();
+        Progress {
+            jobs,
+            walk_done: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            start: Instant::now(),
+        }
+    }
+
+    /// Record one more file of `filesize` bytes found by the walk for `job`.
+    pub fn add_total(&self, job: usize, filesize: u64) {
+        self.jobs[job].files_total.fetch_add(1, Ordering::Relaxed);
+        self.jobs[job]
+            .bytes_total
+            .fetch_add(filesize, Ordering::Relaxed);
+    }
+
+    /// The walk found every file: the totals are complete.
+    pub fn walk_finished(&self) {
+        self.walk_done.store(true, Ordering::Relaxed);
+    }
+
+    /// Record one more processed file of `filesize` bytes for `job`.
+    pub fn add(&self, job: usize, filesize: u64) {
+        self.jobs[job].files_done.fetch_add(1, Ordering::Relaxed);
+        self.jobs[job]
+            .bytes_done
+            .fetch_add(filesize, Ordering::Relaxed);
+    }
+
+    /// Stop the drawing loop after one last redraw.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    /// Redraw the progress line until `finish` is called.
+    pub fn draw_loop(&self) {
+        while !self.finished.load(Ordering::Relaxed) {
+            self.draw();
+            thread::sleep(REDRAW_INTERVAL);
+        }
+        self.draw();
+        eprintln!("\n");
+    }
+
+    fn draw(&self) {
+        let files_done: u64 = self
+            .jobs
+            .iter()
+            .map(|job| job.files_done.load(Ordering::Relaxed))
+            .sum();
+        let bytes_done: u64 = self
+            .jobs
+            .iter()
+            .map(|job| job.bytes_done.load(Ordering::Relaxed))
+            .sum();
+        let files_total: u64 = self
+            .jobs
+            .iter()
+            .map(|job| job.files_total.load(Ordering::Relaxed))
+            .sum();
+        let bytes_total: u64 = self
+            .jobs
+            .iter()
+            .map(|job| job.bytes_total.load(Ordering::Relaxed))
+            .sum();
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let speed = if elapsed > 0.0 {
+            bytes_done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let walk_done = self.walk_done.load(Ordering::Relaxed);
+        let eta = if speed > 0.0 && walk_done {
+            format_duration(bytes_total.saturating_sub(bytes_done) as f64 / speed)
+        } else {
+            String::from("--:--")
+        };
+        // Still one line: redrawing several would need cursor movements the
+        // Windows console does not understand by default.
+        let bars = if !walk_done {
+            // The bars would shrink back as the walk finds more files.
+            let found = self
+                .jobs
+                .iter()
+                .map(|job| {
+                    format!(
+                        "{}/{}",
+                        locale::number(job.files_done.load(Ordering::Relaxed)),
+                        locale::number(job.files_total.load(Ordering::Relaxed))
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" + ");
+            format!("{} files found so far", found)
+        } else if self.jobs.len() == 1 {
+            format!(
+                "{} {}/{} files",
+                bar(bytes_done, bytes_total, BAR_WIDTH),
+                locale::number(files_done),
+                locale::number(files_total)
+            )
+        } else {
+            self.jobs
+                .iter()
+                .map(|job| {
+                    format!(
+                        "{} {}",
+                        job.name,
+                        bar(
+                            job.bytes_done.load(Ordering::Relaxed),
+                            job.bytes_total.load(Ordering::Relaxed),
+                            JOB_BAR_WIDTH
+                        )
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        };
+        let mut handle = stderr().lock();
+        let _ = write!(
+            handle,
+            "\r{}  {}/{}{} GiB  {} MiB/s  ETA {}  ",
+            bars,
+            locale::decimal(bytes_done as f64 / 1024.0 / 1024.0 / 1024.0, 1),
+            locale::decimal(bytes_total as f64 / 1024.0 / 1024.0 / 1024.0, 1),
+            if walk_done { "" } else { "+" },
+            locale::decimal(speed / 1024.0 / 1024.0, 1),
+            eta
+        );
+        let _ = handle.flush();
+    }
+}
+
+/// `[####------]  40.0%`
+fn bar(done: u64, total: u64, width: usize) -> String {
+    let ratio = if total == 0 {
+        1.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    };
+    let filled = (ratio * width as f64) as usize;
+    format!(
+        "[{}{}] {:>5}%",
+        "#".repeat(filled),
+        "-".repeat(width - filled),
+        locale::decimal(ratio * 100.0, 1)
+    )
+}
+
+/// `mm:ss`, or `h:mm:ss` from an hour.
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,129 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Result as IoResult, Write};
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::fileid;
+use crate::manifest::Manifest;
+
+/// Where the result file came from, recorded in the header of
+/// `--authoritative` result files.
+pub struct Provenance {
+    pub sim_build: Option<String>,
+    pub store: &'static str,
+    /// Package name -> `package_version` from its `manifest.json`.
+    pub packages: BTreeMap<String, String>,
+}
+
+impl Provenance {
+    /// The links are walked as for the hashes (`--follow-links`).
+    pub fn collect(packages_dir: &Path, sim_build: Option<String>, follow_links: bool) -> Self {
+        let mut packages = BTreeMap::new();
+        // Official\OneStore\<package>\manifest.json is the deepest layout.
+        for entry in fileid::walk(WalkDir::new(packages_dir).max_depth(3), follow_links) {
+            if entry.file_name() != "manifest.json" {
+                continue;
+            }
+            let package = match entry.path().parent().and_then(|dir| dir.file_name()) {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+            if let Some(version) = read_package_version(entry.path()) {
+                packages.insert(package, version);
+            }
+        }
+        // The version of `fs-base` follows the sim build.
+        let sim_build = sim_build.or_else(|| packages.get("fs-base").cloned());
+        Provenance {
+            sim_build,
+            store: detect_store(packages_dir),
+            packages,
+        }
+    }
+
+    pub fn write_header<W: Write>(&self, writer: &mut W, eol: &str) -> IoResult<()> {
+        write!(
+            writer,
+            "# msfs2020checksum: {}{}",
+            env!("CARGO_PKG_VERSION"),
+            eol
+        )?;
+        write!(writer, "# authoritative: true{}", eol)?;
+        if let Some(ref sim_build) = self.sim_build {
+            write!(writer, "# sim-build: {}{}", sim_build, eol)?;
+        }
+        write!(writer, "# store: {}{}", self.store, eol)?;
+        for (package, version) in &self.packages {
+            write!(writer, "# package: {} {}{}", package, version, eol)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "sim_build": self.sim_build,
+            "store": self.store,
+            "packages": self.packages,
+        })
+    }
+}
+
+/// Warn about references that should not be trusted for this install.
+/// The store type is not checked for a `--cross-store` comparison.
+pub fn check_reference(reference: &Manifest, local: &Provenance, cross_store: bool) {
+    if reference.header_value("authoritative") != Some("true") {
+        eprintln!("Warning: the reference is not an authoritative result file, its provenance is unknown.\n");
+        return;
+    }
+    match (reference.header_value("sim-build"), &local.sim_build) {
+        (Some(expected), Some(actual)) if expected != actual => {
+            eprintln!(
+                "Warning: the reference was generated for sim build {}, but this install is {}.\n",
+                expected, actual
+            );
+        }
+        _ => {}
+    }
+    match reference.header_value("store") {
+        Some(store) if !cross_store && store != local.store && local.store != "unknown" => {
+            eprintln!(
+                "Warning: the reference was generated from the {} version, but this install is the {} version.\n",
+                store, local.store
+            );
+        }
+        _ => {}
+    }
+    for value in reference.header_values("package") {
+        if let Some((package, version)) = value.split_once(' ') {
+            match local.packages.get(package) {
+                Some(local_version) if local_version != version => eprintln!(
+                    "Warning: package {} is version {} in the reference but {} here.",
+                    package, version, local_version
+                ),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn read_package_version(manifest_json: &Path) -> Option<String> {
+    let text = fs::read_to_string(manifest_json).ok()?;
+    let json: Value = serde_json::from_str(text.trim_start_matches('\u{feff}')).ok()?;
+    Some(json.get("package_version")?.as_str()?.to_string())
+}
+
+/// `MS Store` or `Steam`, from the store directory of the packages.
+pub fn detect_store(packages_dir: &Path) -> &'static str {
+    let is_store_dir = |name: &str| {
+        packages_dir.file_name().is_some_and(|dir| dir == name) || packages_dir.join(name).is_dir()
+    };
+    if is_store_dir("OneStore") {
+        "MS Store"
+    } else if is_store_dir("Steam") {
+        "Steam"
+    } else {
+        "unknown"
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{stdout, BufRead, BufReader, BufWriter, Result as IoResult, Write};
+use std::path::Path;
+
+use crate::manifest::native_separators;
+use crate::output::{TextStyle, BOM, OUTPUT_BUFFER_SIZE};
+
+/// First line of a quick scan, which tells it from a result file.
+const QUICK_HEADER: &str = "# quick-scan: size and modification time only";
+
+/// A `--quick` scan: path -> (size, modification time in nanoseconds since
+/// the Unix epoch).
+pub type QuickScan = BTreeMap<String, (u64, u64)>;
+
+/// Whether `file` was written by `--quick` rather than being a result file.
+pub fn is_quick_scan(file: &Path) -> bool {
+    let Ok(fhr) = File::open(file) else {
+        return false;
+    };
+    let mut first = String::new();
+    BufReader::new(fhr).read_line(&mut first).is_ok()
+        && first.trim_start_matches(BOM).trim_end() == QUICK_HEADER
+}
+
+/// Write `scan` (with the emitted paths) to `outpath`, or to the stdout:
+/// `size<TAB>mtime<TAB>path` lines.
+pub fn write_scan(outpath: Option<&Path>, scan: &QuickScan, style: TextStyle) -> IoResult<()> {
+    let (mut writer, eol): (Box<dyn Write>, &str) = match outpath {
+        Some(outpath) => {
+            let mut writer = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, File::create(outpath)?);
+            style.start(&mut writer)?;
+            (Box::new(writer), style.eol())
+        }
+        None => (Box::new(stdout().lock()), "\n"),
+    };
+    write!(writer, "{}{}", QUICK_HEADER, eol)?;
+    for (path, (filesize, mtime)) in scan {
+        write!(writer, "{:10}\t{}\t{}{}", filesize, mtime, path, eol)?;
+    }
+    writer.flush()
+}
+
+pub fn read_scan(file: &Path) -> IoResult<QuickScan> {
+    let mut scan = QuickScan::new();
+    for line in BufReader::new(File::open(file)?).lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r').trim_start_matches(BOM);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let parsed = (|| {
+            let filesize = fields.next()?.trim().parse().ok()?;
+            let mtime = fields.next()?.parse().ok()?;
+            Some((native_separators(fields.next()?), (filesize, mtime)))
+        })();
+        match parsed {
+            Some((path, entry)) => {
+                scan.insert(path, entry);
+            }
+            None => eprintln!("Warning: skipping malformed line in {:?}: {}", file, line),
+        }
+    }
+    Ok(scan)
+}
+
+/// List the files changed (other size or modification time), added and
+/// removed from one quick scan to another, as `diff` does for result files.
+/// Returns the number of such files.
+pub fn diff(old: &QuickScan, new: &QuickScan) -> usize {
+    let mut findings = BTreeMap::new();
+    for (path, entry) in new {
+        match old.get(path) {
+            Some(old_entry) if old_entry == entry => {}
+            Some(_) => {
+                findings.insert(path.as_str(), "CHANGED");
+            }
+            None => {
+                findings.insert(path.as_str(), "ADDED");
+            }
+        }
+    }
+    for path in old.keys() {
+        if !new.contains_key(path) {
+            findings.insert(path.as_str(), "REMOVED");
+        }
+    }
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (path, status) in &findings {
+        println!("{}\t{}", status, path);
+        *counts.entry(status).or_default() += 1;
+    }
+    eprintln!(
+        "\nCompared {} files with {} files by size and modification time, {} unchanged.",
+        new.len(),
+        old.len(),
+        new.len() - counts.get("CHANGED").unwrap_or(&0) - counts.get("ADDED").unwrap_or(&0)
+    );
+    for (status, count) in &counts {
+        eprintln!("{:>10}: {}", status, count);
+    }
+    findings.len()
+}
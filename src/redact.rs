@@ -0,0 +1,89 @@
+use std::env;
+use std::path::Path;
+
+use crate::hasher::Algorithm;
+
+/// Rewrites the emitted paths so that a result file can be posted in public
+/// without telling who made it.
+pub struct Redactor {
+    home: Option<String>,
+    user: Option<String>,
+    /// Also replace the Community package names, which can tell a lot about
+    /// their owner (liveries, payware).
+    community: bool,
+    /// The relative paths start with a Community package name.
+    community_root: bool,
+}
+
+impl Redactor {
+    pub fn new(packages_dir: &Path, community: bool) -> Self {
+        let home = dirs::home_dir()
+            .map(|home| home.to_string_lossy().to_string())
+            .filter(|home| home.len() > 1);
+        let user = env::var("USERNAME")
+            .or_else(|_| env::var("USER"))
+            .ok()
+            .filter(|user| !user.is_empty());
+        let community_root = packages_dir
+            .file_name()
+            .is_some_and(|name| name.eq_ignore_ascii_case("Community"));
+        Redactor {
+            home,
+            user,
+            community,
+            community_root,
+        }
+    }
+
+    /// Hide the home directory and the user name wherever they appear in
+    /// free text, such as a saved log.
+    pub fn redact_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if let Some(ref home) = self.home {
+            text = text.replace(home.as_str(), "~");
+        }
+        if let Some(ref user) = self.user {
+            text = text.replace(user.as_str(), "<user>");
+        }
+        text
+    }
+
+    pub fn redact(&self, path: &str) -> String {
+        let mut path = match self.home {
+            Some(ref home) if path.starts_with(home.as_str()) => {
+                format!("~{}", &path[home.len()..])
+            }
+            _ => path.to_string(),
+        };
+        if path.len() >= 2 && path.as_bytes()[1] == b':' && path.as_bytes()[0].is_ascii_alphabetic()
+        {
+            path.replace_range(..1, "?");
+        }
+        let separator = if path.contains('\\') { '\\' } else { '/' };
+        let mut after_community = self.community && self.community_root;
+        let components: Vec<String> = path
+            .split(separator)
+            .map(|component| {
+                let redacted = if after_community {
+                    community_alias(component)
+                } else if self.user.as_deref() == Some(component) {
+                    String::from("<user>")
+                } else {
+                    component.to_string()
+                };
+                // `@Community` is the prefix of a `--root Community=...`.
+                after_community = self.community
+                    && (component.eq_ignore_ascii_case("Community")
+                        || component.eq_ignore_ascii_case("@Community"));
+                redacted
+            })
+            .collect();
+        components.join(&separator.to_string())
+    }
+}
+
+/// A stable alias, so that the results of two redacted runs still compare.
+fn community_alias(package: &str) -> String {
+    let digest = Algorithm::Crc32.hash_oneshot(package.to_lowercase().as_bytes());
+    format!("community-{}", digest)
+}
@@ -0,0 +1,99 @@
+use dirs::data_local_dir;
+use std::fs::{self, File};
+use std::io::{self, Error as IoError, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::AppError;
+use crate::hasher::Algorithm;
+
+/// A published result file of a whole install is tens of MiB.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The local copy of the reference published at `url` (`verify --remote`),
+/// downloaded into the local application data. The copy of a previous run
+/// is kept when the server answers that the file did not change since
+/// (its ETag), or cannot be reached.
+pub fn fetch(url: &str) -> Result<PathBuf, AppError> {
+    let file = local_copy(url);
+    let etag_file = {
+        let mut name = file.as_os_str().to_owned();
+        name.push(".etag");
+        PathBuf::from(name)
+    };
+    let etag = fs::read_to_string(&etag_file)
+        .ok()
+        .filter(|_| file.is_file());
+    let unusable = |err: IoError| AppError::ManifestUnreadable(PathBuf::from(url), err);
+    eprintln!("Downloading the reference from {}", url);
+    let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+    let mut request = agent.get(url);
+    if let Some(ref etag) = etag {
+        request = request.set("If-None-Match", etag.trim());
+    }
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(err) if file.is_file() => {
+            eprintln!(
+                "Warning: {}, using the copy downloaded before: {:?}\n",
+                err, file
+            );
+            return Ok(file);
+        }
+        Err(err) => return Err(unusable(IoError::other(err.to_string()))),
+    };
+    if response.status() == 304 {
+        eprintln!("Unchanged since the last download: {:?}\n", file);
+        return Ok(file);
+    }
+    let new_etag = response.header("ETag").map(str::to_string);
+    download(response, &file).map_err(unusable)?;
+    // Without an ETag, the file is downloaded every time.
+    let _ = match new_etag {
+        Some(etag) => fs::write(&etag_file, etag),
+        None => fs::remove_file(&etag_file),
+    };
+    eprintln!("Saved to {:?}\n", file);
+    Ok(file)
+}
+
+/// Write the body of `response` to `file`, through a temporary file so that
+/// a broken download does not replace the copy of a previous run.
+fn download(response: ureq::Response, file: &Path) -> IoResult<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut partial = file.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    io::copy(&mut response.into_reader(), &mut File::create(&partial)?)?;
+    fs::rename(&partial, file)
+}
+
+/// Where the reference of `url` is kept: named after the last segment of
+/// the URL, behind a hash of the whole URL so that two `Official.txt` of two
+/// sites do not overwrite each other.
+fn local_copy(url: &str) -> PathBuf {
+    let name: String = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    data_local_dir()
+        .unwrap_or_default()
+        .join("msfs2020checksum")
+        .join("remote")
+        .join(format!(
+            "{}-{}",
+            Algorithm::Xxh3_64.hash_oneshot(url.as_bytes()),
+            name
+        ))
+}
@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::locale;
+use crate::usercfg::UserCfg;
+
+/// File name of the rolling cache, in the `RollingCache.Path` directory.
+pub const FILE_NAME: &str = "ROLLINGCACHE.CCC";
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// The first bytes of the file, where the sim keeps the index of the cached
+/// tiles. A rolling cache whose index is gone reads as all zeros there.
+const HEADER_LEN: usize = 64 * 1024;
+
+/// The rolling cache as configured in `UserCfg.opt`.
+pub struct RollingCache {
+    pub file: PathBuf,
+    /// `RollingCache.Enable`.
+    pub enabled: bool,
+    /// `RollingCache.Size`, in bytes.
+    pub limit: Option<u64>,
+}
+
+impl RollingCache {
+    /// The `{RollingCache` section of `UserCfg.opt`. The file is in the
+    /// directory of `UserCfg.opt` when `Path` is not set.
+    pub fn locate(usercfg: &Path, settings: &UserCfg) -> Self {
+        let dir = match settings.get("RollingCache.Path") {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => usercfg.parent().unwrap_or(Path::new("")).to_path_buf(),
+        };
+        RollingCache {
+            file: dir.join(FILE_NAME),
+            enabled: settings.get("RollingCache.Enable") != Some("0"),
+            // In GB in the settings of the sim.
+            limit: settings
+                .get("RollingCache.Size")
+                .and_then(|size| size.parse::<u64>().ok())
+                .map(|size| size * GIB),
+        }
+    }
+
+    /// Print where the rolling cache is and how big, and return what looks
+    /// wrong with it.
+    ///
+    /// The format of the file is not documented; only what shows from the
+    /// outside is checked: the sim allocates the file to the configured size
+    /// at once, and keeps its index at the start.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        eprintln!("Rolling cache: {:?}", self.file);
+        eprintln!("  enabled: {}", if self.enabled { "yes" } else { "no" });
+        match self.limit {
+            Some(limit) => eprintln!(
+                "  configured size: {} GiB",
+                locale::decimal(limit as f64 / GIB as f64, 1)
+            ),
+            None => eprintln!("  configured size: not set"),
+        }
+        let size = match self.file.metadata() {
+            Ok(meta) => meta.len(),
+            Err(err) => {
+                if self.enabled {
+                    problems.push(format!("the file cannot be accessed: {}", err));
+                }
+                return problems;
+            }
+        };
+        eprintln!(
+            "  file size: {} GiB",
+            locale::decimal(size as f64 / GIB as f64, 1)
+        );
+        match self.limit {
+            _ if size == 0 => problems.push(String::from("the file is empty")),
+            Some(limit) if size < limit => problems.push(format!(
+                "the file is {} bytes short of the configured size: it was truncated",
+                locale::number(limit - size)
+            )),
+            Some(limit) if size > limit => problems.push(String::from(
+                "the file is larger than the configured size: the size was lowered without deleting the cache",
+            )),
+            _ => {}
+        }
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        let read = File::open(&self.file)
+            .and_then(|file| file.take(HEADER_LEN as u64).read_to_end(&mut header));
+        match read {
+            Err(err) => problems.push(format!("the file cannot be read: {}", err)),
+            Ok(_) if size > 0 && header.iter().all(|&byte| byte == 0) => {
+                problems.push(String::from("its index is blank (all zeros)"))
+            }
+            Ok(_) => {}
+        }
+        for problem in &problems {
+            eprintln!("  Problem: {}", problem);
+        }
+        eprintln!();
+        problems
+    }
+}
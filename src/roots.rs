@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::manifest::Manifest;
+
+/// Name of the packages directory in the `# root:` header lines. Its files
+/// are written without a prefix, as in result files without roots.
+pub const PACKAGES_ROOT: &str = "packages";
+
+/// Names taken by the tool itself (`@config` for `--include-config`).
+const RESERVED_NAMES: [&str; 2] = [PACKAGES_ROOT, "config"];
+
+/// Parse a `--root NAME=PATH` argument. The files of the root are written as
+/// `@NAME\relative\path`.
+pub fn parse_root(spec: &str) -> Result<(String, PathBuf), AppError> {
+    let (name, path) = match spec.split_once('=') {
+        Some((name, path)) if !path.is_empty() => (name, path),
+        _ => return Err(AppError::InvalidRoot(spec.to_string())),
+    };
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !RESERVED_NAMES
+            .iter()
+            .any(|reserved| name.eq_ignore_ascii_case(reserved));
+    if !valid_name {
+        return Err(AppError::InvalidRoot(spec.to_string()));
+    }
+    Ok((name.to_string(), PathBuf::from(path)))
+}
+
+/// Name of the root of a further `--packages` directory: its folder name
+/// (`D:\MSFS\Community` -> `Community`), reduced to the characters allowed
+/// in root names, with a number added when `taken` already has it.
+pub fn packages_root_name(dir: &Path, taken: &[(String, PathBuf)]) -> String {
+    let folder: String = dir
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    let base = if folder.is_empty() {
+        String::from("root")
+    } else {
+        folder
+    };
+    let is_free = |name: &str| {
+        !RESERVED_NAMES
+            .iter()
+            .copied()
+            .chain(taken.iter().map(|(taken, _)| taken.as_str()))
+            .any(|used| used.eq_ignore_ascii_case(name))
+    };
+    let mut name = base.clone();
+    let mut number = 2;
+    while !is_free(&name) {
+        name = format!("{}{}", base, number);
+        number += 1;
+    }
+    name
+}
+
+/// Prefix of the emitted paths of the files of root `name`.
+pub fn root_prefix(name: &str) -> String {
+    format!("@{}", name)
+}
+
+/// Move the entries of root `name` out of `entries`.
+pub fn take_root<T, P: AsRef<str>>(
+    entries: &mut Vec<T>,
+    name: &str,
+    path_of: impl Fn(&T) -> P,
+) -> Vec<T> {
+    let prefix = root_prefix(name);
+    let (taken, kept) = entries
+        .drain(..)
+        .partition(|entry| Path::new(path_of(entry).as_ref()).starts_with(&prefix));
+    *entries = kept;
+    taken
+}
+
+/// Remove the `@NAME` prefix of root `name` from `path`, if it has one.
+pub fn strip_root(path: &str, name: &str) -> String {
+    match Path::new(path).strip_prefix(root_prefix(name)) {
+        Ok(r_path) => r_path.to_string_lossy().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// `D:\MyMSFS.xxhash` -> `D:\MyMSFS.Community.xxhash`
+pub fn split_output_path(outpath: &Path, name: &str) -> PathBuf {
+    let mut file_name = outpath.file_stem().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(name);
+    if let Some(extension) = outpath.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    outpath.with_file_name(file_name)
+}
+
+/// Find on this machine the roots declared by a reference.
+///
+/// A root given with `--root` wins. Otherwise `Community` is looked for next
+/// to `Official`, then the location recorded in the reference is tried.
+/// Roots that cannot be found are left out, with a warning: their files are
+/// then reported as missing.
+pub fn resolve_roots(
+    reference: &Manifest,
+    given: &[(String, PathBuf)],
+    packages_dir: &Path,
+) -> Vec<(String, PathBuf)> {
+    let mut resolved = given.to_vec();
+    for (name, recorded) in reference.roots() {
+        if name == PACKAGES_ROOT
+            || resolved
+                .iter()
+                .any(|(given_name, _)| given_name.eq_ignore_ascii_case(&name))
+        {
+            continue;
+        }
+        let community = packages_dir
+            .file_name()
+            .filter(|dir| {
+                dir.eq_ignore_ascii_case("Official") && name.eq_ignore_ascii_case("Community")
+            })
+            .and_then(|_| packages_dir.parent())
+            .map(|parent| parent.join("Community"));
+        match community.filter(|dir| dir.is_dir()).or_else(|| {
+            let recorded = PathBuf::from(recorded);
+            recorded.is_dir().then_some(recorded)
+        }) {
+            Some(dir) => {
+                eprintln!("Root {} of the reference: {:?}", name, dir);
+                resolved.push((name, dir));
+            }
+            None => eprintln!(
+                "Warning: root {} of the reference not found, give it with `--root {}=<path>`.",
+                name, name
+            ),
+        }
+    }
+    resolved
+}
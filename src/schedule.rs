@@ -0,0 +1,286 @@
+use clap::ValueEnum;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::AppError;
+use crate::settings::format_timestamp;
+
+/// The name of the task unless `--name` is given.
+pub const DEFAULT_TASK_NAME: &str = "msfs2020checksum";
+
+/// Replaced in `--output` by the date of the run, so that every scheduled
+/// run keeps its own result file.
+const DATE_PLACEHOLDER: &str = "%date%";
+
+/// The day of the week of `schedule install --weekly`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// The element of the task XML.
+    fn element(self) -> &'static str {
+        match self {
+            Weekday::Monday => "<Monday />",
+            Weekday::Tuesday => "<Tuesday />",
+            Weekday::Wednesday => "<Wednesday />",
+            Weekday::Thursday => "<Thursday />",
+            Weekday::Friday => "<Friday />",
+            Weekday::Saturday => "<Saturday />",
+            Weekday::Sunday => "<Sunday />",
+        }
+    }
+}
+
+/// Parse the `--time` of `schedule install`, `HH:MM`.
+pub fn parse_time(value: &str) -> Result<(u8, u8), String> {
+    value
+        .split_once(':')
+        .and_then(|(hours, minutes)| Some((hours.parse().ok()?, minutes.parse().ok()?)))
+        .filter(|&(hours, minutes)| hours < 24 && minutes < 60)
+        .ok_or_else(|| format!("`{}` is not a time of the day as `HH:MM`", value))
+}
+
+/// `outpath` with `%date%` replaced by the date of today on this machine,
+/// `YYYYMMDD`: that of the scheduled time, wherever the machine is.
+pub fn expand_date(outpath: &Path) -> PathBuf {
+    match outpath.to_str() {
+        Some(path) if path.contains(DATE_PLACEHOLDER) => {
+            PathBuf::from(path.replace(DATE_PLACEHOLDER, &local_date()))
+        }
+        _ => outpath.to_path_buf(),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn local_date() -> String {
+    use windows::Win32::System::SystemInformation::GetLocalTime;
+    let now = unsafe { GetLocalTime() };
+    format!("{:04}{:02}{:02}", now.wYear, now.wMonth, now.wDay)
+}
+
+#[cfg(unix)]
+fn local_date() -> String {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return format_timestamp(SystemTime::now())[..8].to_string();
+        }
+        format!(
+            "{:04}{:02}{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday
+        )
+    }
+}
+
+/// Elsewhere the time zone is unknown: the UTC date.
+#[cfg(not(any(target_os = "windows", unix)))]
+fn local_date() -> String {
+    format_timestamp(SystemTime::now())[..8].to_string()
+}
+
+/// Register the scheduled task `name`, replacing a task of the same name:
+/// every day, or every week on `weekly`, at `time`, it runs this executable
+/// with `args` from the current directory. It runs as the current user, only
+/// while logged on (the sim settings are theirs), and as soon as possible
+/// after a missed run.
+pub fn install(
+    name: &str,
+    weekly: Option<Weekday>,
+    time: (u8, u8),
+    args: &[String],
+) -> Result<(), AppError> {
+    let failed = |err: String| AppError::ScheduleFailed(name.to_string(), err);
+    let exe = env::current_exe().map_err(|err| failed(err.to_string()))?;
+    let dir = env::current_dir().map_err(|err| failed(err.to_string()))?;
+    let arguments = args
+        .iter()
+        .map(|arg| quote_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let schedule = match weekly {
+        Some(day) => format!(
+            "<ScheduleByWeek><DaysOfWeek>{}</DaysOfWeek><WeeksInterval>1</WeeksInterval></ScheduleByWeek>",
+            day.element()
+        ),
+        None => String::from("<ScheduleByDay><DaysInterval>1</DaysInterval></ScheduleByDay>"),
+    };
+    let today = format_timestamp(SystemTime::now());
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Integrity check of the MSFS packages, registered by `msfs2020checksum schedule install`.</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <CalendarTrigger>
+      <StartBoundary>{}-{}-{}T{:02}:{:02}:00</StartBoundary>
+      {}
+    </CalendarTrigger>
+  </Triggers>
+  <Settings>
+    <StartWhenAvailable>true</StartWhenAvailable>
+    <DisallowStartIfOnBatteries>true</DisallowStartIfOnBatteries>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <ExecutionTimeLimit>PT12H</ExecutionTimeLimit>
+    <Priority>7</Priority>
+  </Settings>
+  <Actions>
+    <Exec>
+      <Command>{}</Command>
+      <Arguments>{}</Arguments>
+      <WorkingDirectory>{}</WorkingDirectory>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        &today[..4],
+        &today[4..6],
+        &today[6..8],
+        time.0,
+        time.1,
+        schedule,
+        escape_xml(&exe.to_string_lossy()),
+        escape_xml(&arguments),
+        escape_xml(&dir.to_string_lossy()),
+    );
+    // The Task Scheduler reads UTF-16 XML files with their BOM.
+    let xml_file = env::temp_dir().join(format!("{}.task.xml", DEFAULT_TASK_NAME));
+    let bytes: Vec<u8> = std::iter::once('\u{feff}' as u16)
+        .chain(xml.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    fs::write(&xml_file, bytes).map_err(|err| failed(err.to_string()))?;
+    let registered = schtasks(&[
+        OsStr::new("/Create"),
+        OsStr::new("/TN"),
+        OsStr::new(name),
+        OsStr::new("/XML"),
+        xml_file.as_os_str(),
+        OsStr::new("/F"),
+    ]);
+    let _ = fs::remove_file(&xml_file);
+    registered.map_err(failed)?;
+    eprintln!(
+        "Registered the scheduled task {:?}: {} at {:02}:{:02}, running\n  {} {}",
+        name,
+        match weekly {
+            Some(day) => format!("every {:?}", day),
+            None => String::from("every day"),
+        },
+        time.0,
+        time.1,
+        exe.to_string_lossy(),
+        arguments
+    );
+    Ok(())
+}
+
+/// Remove the scheduled task `name`.
+pub fn remove(name: &str) -> Result<(), AppError> {
+    schtasks(&[
+        OsStr::new("/Delete"),
+        OsStr::new("/TN"),
+        OsStr::new(name),
+        OsStr::new("/F"),
+    ])
+    .map_err(|err| AppError::ScheduleFailed(name.to_string(), err))?;
+    eprintln!("Removed the scheduled task {:?}.", name);
+    Ok(())
+}
+
+/// Run `schtasks`, the message it printed as the error.
+#[cfg(target_os = "windows")]
+fn schtasks(args: &[&OsStr]) -> Result<(), String> {
+    let output = std::process::Command::new("schtasks")
+        .args(args)
+        .output()
+        .map_err(|err| err.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let message = String::from_utf8_lossy(&output.stderr);
+        Err(message
+            .trim()
+            .trim_start_matches("ERROR:")
+            .trim()
+            .to_string())
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn schtasks(_: &[&OsStr]) -> Result<(), String> {
+    Err(String::from(
+        "the Task Scheduler is only on Windows, use cron or a systemd timer",
+    ))
+}
+
+/// `arg` quoted for the command line of a Windows program, as the C runtime
+/// splits it.
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_as_the_windows_crt_parses() {
+        for (arg, quoted) in [
+            ("a", "a"),
+            ("C:\\dir\\", "C:\\dir\\"),
+            ("", "\"\""),
+            ("a b", "\"a b\""),
+            ("a\"b", "\"a\\\"b\""),
+            // Backslashes are doubled before a quote, the closing one too.
+            ("C:\\my dir\\", "\"C:\\my dir\\\\\""),
+            ("a\\\"b c", "\"a\\\\\\\"b c\""),
+        ] {
+            assert_eq!(quote_arg(arg), quoted, "{}", arg);
+        }
+    }
+}
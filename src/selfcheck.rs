@@ -0,0 +1,86 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Result as IoResult};
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::hasher::{Algorithm, Digest};
+
+/// Hash the running executable and compare it with the SHA-256 published
+/// with its release: `expected` (a hash or a `.sha256` file), else the
+/// `<exe>.sha256` file next to the executable, as uploaded by the release
+/// workflow.
+pub fn self_check(expected: Option<&str>) -> Result<(), AppError> {
+    let exe = env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|err| {
+            eprintln!("Unable to locate the running executable: {}", err);
+            AppError::ReadErrorsOccurred(1)
+        })?;
+    let actual = hash_file(&exe).map_err(|err| {
+        eprintln!("Fail to read file {} {}", exe.to_string_lossy(), err);
+        AppError::ReadErrorsOccurred(1)
+    })?;
+    println!("SHA256\t{}\t{}", actual, exe.to_string_lossy());
+
+    let (expected, source) = match expected {
+        Some(expected) => match Digest::from_hex(expected) {
+            Some(hash) if hash.hex_len() == 64 => (hash, String::from("--expected")),
+            _ => {
+                let sidecar = PathBuf::from(expected);
+                (read_sidecar(&sidecar)?, expected.to_string())
+            }
+        },
+        None => {
+            let mut sidecar = exe.clone().into_os_string();
+            sidecar.push(".sha256");
+            let sidecar = PathBuf::from(sidecar);
+            if !sidecar.is_file() {
+                eprintln!(
+                    "\nNo {:?} next to the executable. Compare the hash above with the `.sha256` file of the release,\nor run `self-check --expected <hash or .sha256 file>`.",
+                    sidecar.file_name().unwrap_or_default()
+                );
+                return Ok(());
+            }
+            (
+                read_sidecar(&sidecar)?,
+                sidecar.to_string_lossy().to_string(),
+            )
+        }
+    };
+    if expected == actual {
+        eprintln!("\nThe executable matches {}.", source);
+        Ok(())
+    } else {
+        println!("MISMATCH\t{}", exe.to_string_lossy());
+        eprintln!(
+            "\nThe executable does not match {} ({}). Download it again from the releases page.",
+            source, expected
+        );
+        Err(AppError::VerificationFailed(1))
+    }
+}
+
+fn hash_file(path: &Path) -> IoResult<Digest> {
+    let mut hasher = Algorithm::Sha256.hasher();
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.digest());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// The hash of a `sha256sum` line: `<hash>  <file name>`.
+fn read_sidecar(sidecar: &Path) -> Result<Digest, AppError> {
+    let text = fs::read_to_string(sidecar)
+        .map_err(|err| AppError::ManifestUnreadable(sidecar.to_path_buf(), err))?;
+    text.split_whitespace()
+        .next()
+        .and_then(Digest::from_hex)
+        .filter(|hash| hash.hex_len() == 64)
+        .ok_or_else(|| AppError::UnknownAlgorithm(sidecar.to_path_buf()))
+}
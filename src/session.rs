@@ -0,0 +1,163 @@
+use serde_json::Value;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::cache::HashCache;
+use crate::error::AppError;
+use crate::hasher::Algorithm;
+use crate::interrupt;
+use crate::{hash_packages, replay_live, FileRecord, HashOptions, HashRun};
+
+/// Separates the commands chained in one invocation.
+pub const THEN: &str = "--then";
+
+/// The command lines of the chained commands, each with the program name
+/// first, as `Args::parse_from` takes them. `None` when a `--then` has no
+/// command after it.
+pub fn split_steps(mut args: impl Iterator<Item = OsString>) -> Option<Vec<Vec<OsString>>> {
+    let program = args.next().unwrap_or_default();
+    let mut steps = vec![vec![program.clone()]];
+    for arg in args {
+        if arg == THEN {
+            steps.push(vec![program.clone()]);
+        } else {
+            steps.last_mut().unwrap().push(arg);
+        }
+    }
+    let dangling = steps.len() > 1 && steps.iter().any(|step| step.len() == 1);
+    (!dangling).then_some(steps)
+}
+
+/// What the commands chained with `--then` share: the results of the last
+/// hashing run, which a later command hashing the same files the same way
+/// takes instead of walking and reading the packages directory again.
+/// Nothing else is: each command looks for the install and starts its
+/// hashing threads itself.
+#[derive(Default)]
+pub struct Session {
+    last_run: Option<(RunKey, Vec<FileRecord>)>,
+    /// The packages directory and the statistics of the hashing of the
+    /// running command, for `--summary-json`.
+    pub totals: Option<(PathBuf, Value)>,
+}
+
+impl Session {
+    /// `hash_packages`, or the results of the previous command when they
+    /// are those of the same files. These are written out as the hashing
+    /// would.
+    pub fn hash_packages(
+        &mut self,
+        packages_dir: &Path,
+        options: &HashOptions,
+        cache: &HashCache,
+    ) -> Result<HashRun, AppError> {
+        let key = RunKey::new(packages_dir, options);
+        if let Some((_, results)) = self
+            .last_run
+            .as_ref()
+            .filter(|(previous, _)| key.as_ref() == Some(previous))
+        {
+            eprintln!(
+                "Reusing the {} file(s) hashed by the previous command.\n",
+                results.len()
+            );
+            replay_live(packages_dir, options, results)?;
+            // Nothing is read: every file is reused.
+            let reused = (results.len(), results.iter().map(|record| record.2).sum());
+            return Ok((results.clone(), Vec::new(), Vec::new(), reused, None));
+        }
+        let run = hash_packages(packages_dir, options, cache)?;
+        let (ref results, ref read_errors, ..) = run;
+        // An incomplete run is not the packages directory: the next command
+        // hashes it again.
+        self.last_run = key
+            .filter(|_| read_errors.is_empty() && !interrupt::interrupted())
+            .map(|key| (key, results.clone()));
+        Ok(run)
+    }
+}
+
+/// What the records of a run depend on. The options of the emitted paths
+/// and of the reading itself do not change them.
+#[derive(PartialEq)]
+struct RunKey {
+    packages_dir: PathBuf,
+    algorithms: Vec<Algorithm>,
+    chunk_size: Option<u64>,
+    sample: Option<u64>,
+    tree: Option<u64>,
+    globs: (Vec<String>, Vec<String>, bool),
+    usercfg: Option<PathBuf>,
+    roots: Vec<(String, PathBuf)>,
+    image_links: Option<Vec<(String, PathBuf)>>,
+    follow_links: bool,
+    archives: bool,
+    dedupe_links: bool,
+}
+
+impl RunKey {
+    /// `None` for a run that hashes only some of the files: a
+    /// `--time-budget`, `--changed-since`, `--stdin` or `--spot-check` run.
+    fn new(packages_dir: &Path, options: &HashOptions) -> Option<Self> {
+        if options.deadline.is_some()
+            || options.changed_since.is_some()
+            || options.file_list.is_some()
+        {
+            return None;
+        }
+        Some(RunKey {
+            packages_dir: packages_dir.to_path_buf(),
+            algorithms: options.algorithms(),
+            chunk_size: options.chunk_size,
+            sample: options.sample,
+            tree: options.tree,
+            globs: options.filter.globs()?,
+            usercfg: options.usercfg.clone(),
+            roots: options.roots.clone(),
+            image_links: options.image_links.clone(),
+            follow_links: options.follow_links,
+            archives: options.archives,
+            dedupe_links: options.dedupe_links,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(line: &str) -> Option<Vec<Vec<String>>> {
+        let args = line.split_whitespace().map(OsString::from);
+        split_steps(args).map(|steps| {
+            steps
+                .into_iter()
+                .map(|step| {
+                    step.into_iter()
+                        .map(|arg| arg.to_string_lossy().into_owned())
+                        .collect()
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn splits_at_each_then() {
+        assert_eq!(
+            steps("prog -o a.txt --then verify --reference a.txt").unwrap(),
+            [
+                &["prog", "-o", "a.txt"][..],
+                &["prog", "verify", "--reference", "a.txt"][..],
+            ]
+        );
+        assert_eq!(steps("prog").unwrap(), [&["prog"][..]]);
+    }
+
+    #[test]
+    fn rejects_a_then_without_a_command() {
+        // Leading, trailing and doubled.
+        assert_eq!(steps("prog --then verify"), None);
+        assert_eq!(steps("prog -o a.txt --then"), None);
+        assert_eq!(steps("prog -o a.txt --then --then verify"), None);
+        assert_eq!(steps("prog --then"), None);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cache::HashCache;
+use crate::config;
+use crate::error::AppError;
+use crate::filter::PathFilter;
+use crate::hasher::Algorithm;
+use crate::output::{RelativeTo, TextStyle};
+use crate::{hash_packages, load_manifest, verify, write_output, HashOptions, RunHeader};
+
+/// Result file of the copies, inside the backup directory.
+const CHECKSUMS_FILE: &str = "checksums.xxhash";
+
+/// Copy the sim settings files (see `config::config_files`) into a new
+/// timestamped directory under `dest`, then hash the copies into its
+/// `checksums.xxhash`.
+pub fn backup_settings(usercfg: &Path, dest: &Path) -> Result<PathBuf, AppError> {
+    let backup_dir = dest.join(format!(
+        "msfs-settings-{}",
+        format_timestamp(SystemTime::now())
+    ));
+    let files = config::config_files(usercfg);
+    for (path_string, source) in &files {
+        let relative_path = Path::new(path_string)
+            .strip_prefix(config::CONFIG_PREFIX)
+            .unwrap_or(Path::new(path_string));
+        let target = backup_dir.join(relative_path);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| AppError::OutputUnwritable(dir.to_path_buf(), err))?;
+        }
+        fs::copy(source, &target).map_err(|err| AppError::OutputUnwritable(target.clone(), err))?;
+    }
+    eprintln!("Copied {} file(s) to {:?}\n", files.len(), backup_dir);
+
+    let (results, read_errors, _, _, _) =
+        hash_packages(&backup_dir, &settings_options()?, &HashCache::empty())?;
+    let checksums = backup_dir.join(CHECKSUMS_FILE);
+    let errors: Vec<(String, &IoError)> = read_errors
+        .iter()
+        .map(|(path, err)| (path.clone(), err))
+        .collect();
+    write_output(
+        &checksums,
+        &RunHeader {
+            provenance: None,
+            algorithms: &[Algorithm::Xxh3_128],
+            chunk_size: None,
+            sample: None,
+            tree: None,
+            roots: &[],
+        },
+        &results,
+        &errors,
+        None,
+        TextStyle::default(),
+        None,
+    )
+    .map_err(|err| AppError::OutputUnwritable(checksums, err))?;
+    if !read_errors.is_empty() {
+        return Err(AppError::ReadErrorsOccurred(read_errors.len()));
+    }
+    Ok(backup_dir)
+}
+
+/// Hash the files of a backup made by `backup_settings` and compare them with
+/// its `checksums.xxhash`. Returns the number of problems.
+pub fn verify_settings(backup_dir: &Path) -> Result<usize, AppError> {
+    let checksums = load_manifest(&backup_dir.join(CHECKSUMS_FILE))?;
+    let (results, read_errors, _, _, _) =
+        hash_packages(backup_dir, &settings_options()?, &HashCache::empty())?;
+    let (problems, _) = verify::verify(&results, &checksums.entries, None);
+    if problems == 0 && !read_errors.is_empty() {
+        return Err(AppError::ReadErrorsOccurred(read_errors.len()));
+    }
+    Ok(problems)
+}
+
+/// The settings files are small, one thread is plenty.
+fn settings_options() -> Result<HashOptions, AppError> {
+    Ok(HashOptions {
+        thread_num: 1,
+        algorithm: Algorithm::Xxh3_128,
+        extra_algorithms: Vec::new(),
+        chunk_size: None,
+        sample: None,
+        tree: None,
+        live: None,
+        filter: PathFilter::new(&[], &[CHECKSUMS_FILE.to_string()], false)?,
+        redactor: None,
+        buffer_size: None,
+        max_memory: None,
+        mmap: false,
+        usercfg: None,
+        roots: Vec::new(),
+        relative_to: RelativeTo::Packages,
+        image_links: None,
+        follow_links: true,
+        retries: 0,
+        deadline: None,
+        forward_slashes: false,
+        lowercase_paths: false,
+        archives: false,
+        dedupe_links: false,
+        changed_since: None,
+        file_list: None,
+    })
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    // Civil date from the days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
@@ -0,0 +1,111 @@
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, Error as IoError, Result as IoResult, Write};
+use std::path::Path;
+
+use crate::compress::{self, Compression, OutputFile};
+use crate::hasher::Digest;
+use crate::output::BOM;
+
+/// The header line of the signature, `# signature: ed25519 <hex>`.
+const SIGNATURE_PREFIX: &str = "# signature:";
+
+/// The private key of `--sign`: a PKCS#8 PEM file, as written by
+/// `openssl genpkey -algorithm ed25519`, or a file holding the 32 bytes of
+/// the key in hex.
+pub fn signing_key(file: &Path) -> Result<SigningKey, String> {
+    let text = fs::read_to_string(file).map_err(|err| err.to_string())?;
+    if text.contains("-----BEGIN") {
+        return SigningKey::from_pkcs8_pem(&text).map_err(|err| err.to_string());
+    }
+    let bytes = key_bytes(text.trim()).ok_or("not a PEM private key nor 64 hex digits")?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// The public key of `--require-signature`: 64 hex digits, or a file holding
+/// them or the PEM public key (`openssl pkey -pubout`).
+pub fn verifying_key(value: &str) -> Result<VerifyingKey, String> {
+    let text = match key_bytes(value) {
+        Some(_) => value.to_string(),
+        None => fs::read_to_string(value).map_err(|err| format!("{}: {}", value, err))?,
+    };
+    if text.contains("-----BEGIN") {
+        return VerifyingKey::from_public_key_pem(&text).map_err(|err| err.to_string());
+    }
+    let bytes = key_bytes(text.trim()).ok_or("not a PEM public key nor 64 hex digits")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|err| err.to_string())
+}
+
+fn key_bytes(hex: &str) -> Option<[u8; 32]> {
+    Digest::from_hex(hex)?.as_bytes().try_into().ok()
+}
+
+/// The public key of `key` in hex, to publish with the signed result files.
+pub fn public_hex(key: &SigningKey) -> String {
+    key.verifying_key()
+        .as_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// What is signed of a result file: every line but the signature, without
+/// the BOM and the line endings, each ended by `\n`. Converting the line
+/// endings or adding a BOM keeps the signature valid; any other change
+/// breaks it.
+fn signed_body(file: &Path) -> IoResult<(Vec<u8>, Option<String>)> {
+    let mut body = Vec::new();
+    let mut signature = None;
+    for line in compress::open(file)?.lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r').trim_start_matches(BOM);
+        if let Some(value) = line.strip_prefix(SIGNATURE_PREFIX) {
+            signature = Some(value.trim().to_string());
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        body.extend_from_slice(line.as_bytes());
+        body.push(b'\n');
+    }
+    Ok((body, signature))
+}
+
+/// Append the signature of the result file `file`, just written, as its
+/// last line.
+pub fn sign(
+    file: &Path,
+    key: &SigningKey,
+    compression: Option<Compression>,
+    eol: &str,
+) -> IoResult<()> {
+    let (body, _) = signed_body(file)?;
+    let signature = key.sign(&body);
+    let hex: String = signature
+        .to_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let fhw = OpenOptions::new().append(true).open(file)?;
+    let mut writer = OutputFile::new(fhw, compression)?;
+    write!(writer, "{} ed25519 {}{}", SIGNATURE_PREFIX, hex, eol)?;
+    writer.finish()
+}
+
+/// Check that the result file `file` is signed by `key`, unchanged since.
+pub fn check(file: &Path, key: &VerifyingKey) -> IoResult<()> {
+    let (body, signature) = signed_body(file)?;
+    let signature =
+        signature.ok_or_else(|| IoError::other("the result file is not signed (`--sign`)"))?;
+    let bytes = signature
+        .strip_prefix("ed25519")
+        .and_then(|hex| Digest::from_hex(hex.trim()))
+        .and_then(|digest| <[u8; 64]>::try_from(digest.as_bytes()).ok())
+        .ok_or_else(|| IoError::other("the signature line is not a valid ed25519 signature"))?;
+    key.verify(&body, &Signature::from_bytes(&bytes))
+        .map_err(|_| {
+            IoError::other("the signature does not match: the result file was changed, or signed with another key")
+        })
+}
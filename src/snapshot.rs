@@ -0,0 +1,199 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use dirs::data_local_dir;
+
+use crate::error::AppError;
+use crate::settings::format_timestamp;
+use crate::textdiff::SavedContent;
+
+/// `name<TAB>created<TAB>files<TAB>result file` per snapshot, oldest first.
+const INDEX_FILE: &str = "snapshots.index";
+
+/// A result file kept in the store under a name, e.g. `pre-SU15`.
+pub struct Snapshot {
+    pub name: String,
+    /// `YYYYMMDD-HHMMSS`, UTC.
+    pub created: String,
+    pub files: usize,
+    /// Name of the result file, inside the store directory.
+    pub file: String,
+}
+
+/// A directory of result files saved by `snapshot save`, with their index.
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    /// The store in `dir`, or in the local application data directory.
+    pub fn open(dir: Option<&Path>) -> Self {
+        let dir = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => data_local_dir()
+                .unwrap_or_default()
+                .join("msfs2020checksum")
+                .join("snapshots"),
+        };
+        SnapshotStore { dir }
+    }
+
+    /// The snapshots, oldest first. A store that does not exist yet is empty.
+    pub fn list(&self) -> Result<Vec<Snapshot>, AppError> {
+        let index = self.dir.join(INDEX_FILE);
+        let text = match fs::read_to_string(&index) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(AppError::ManifestUnreadable(index, err)),
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                Some(Snapshot {
+                    name: fields.next()?.to_string(),
+                    created: fields.next()?.to_string(),
+                    files: fields.next()?.parse().ok()?,
+                    file: fields.next()?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// The result file of snapshot `name`.
+    pub fn find(&self, name: &str) -> Result<PathBuf, AppError> {
+        Ok(self.path(&self.get(name)?))
+    }
+
+    pub fn get(&self, name: &str) -> Result<Snapshot, AppError> {
+        self.list()?
+            .into_iter()
+            .find(|snapshot| snapshot.name == name)
+            .ok_or_else(|| AppError::UnknownSnapshot(name.to_string()))
+    }
+
+    /// Where to write a new snapshot `name`: `<created>-<name>.xxhash` in
+    /// the store, which is created if needed.
+    pub fn new_snapshot(&self, name: &str) -> Result<Snapshot, AppError> {
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_name {
+            return Err(AppError::InvalidSnapshotName(name.to_string()));
+        }
+        fs::create_dir_all(&self.dir)
+            .map_err(|err| AppError::OutputUnwritable(self.dir.clone(), err))?;
+        let created = format_timestamp(SystemTime::now());
+        Ok(Snapshot {
+            name: name.to_string(),
+            file: format!("{}-{}.xxhash", created, name),
+            created,
+            files: 0,
+        })
+    }
+
+    pub fn path(&self, snapshot: &Snapshot) -> PathBuf {
+        self.dir.join(&snapshot.file)
+    }
+
+    /// The archive of the text files kept with the snapshot (`snapshot save
+    /// --keep-text`): `<created>-<name>.content.zip`.
+    pub fn content_path(&self, snapshot: &Snapshot) -> PathBuf {
+        let stem = snapshot
+            .file
+            .strip_suffix(".xxhash")
+            .unwrap_or(&snapshot.file);
+        self.dir.join(format!("{}.content.zip", stem))
+    }
+
+    /// The text files kept with snapshot `name`.
+    pub fn kept_text(&self, name: &str) -> Result<SavedContent, AppError> {
+        let archive = self.content_path(&self.get(name)?);
+        SavedContent::open(&archive).map_err(|err| {
+            let err = if err.kind() == ErrorKind::NotFound {
+                IoError::new(
+                    ErrorKind::NotFound,
+                    "the snapshot was saved without `--keep-text`",
+                )
+            } else {
+                err
+            };
+            AppError::ManifestUnreadable(archive, err)
+        })
+    }
+
+    /// Add a written snapshot to the index. A snapshot of the same name is
+    /// replaced, and its result file deleted.
+    pub fn register(&self, snapshot: Snapshot) -> Result<(), AppError> {
+        let mut snapshots = self.list()?;
+        for old in snapshots.iter().filter(|old| old.name == snapshot.name) {
+            eprintln!(
+                "Replacing snapshot {} of {}.",
+                old.name,
+                display_created(&old.created)
+            );
+            if old.file != snapshot.file {
+                let _ = fs::remove_file(self.path(old));
+                let _ = fs::remove_file(self.content_path(old));
+            }
+        }
+        snapshots.retain(|old| old.name != snapshot.name);
+        snapshots.push(snapshot);
+        let index = self.dir.join(INDEX_FILE);
+        write_index(&index, &snapshots).map_err(|err| AppError::OutputUnwritable(index, err))
+    }
+}
+
+fn write_index(index: &Path, snapshots: &[Snapshot]) -> IoResult<()> {
+    let mut writer = BufWriter::new(File::create(index)?);
+    for snapshot in snapshots {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            snapshot.name, snapshot.created, snapshot.files, snapshot.file
+        )?;
+    }
+    writer.flush()
+}
+
+/// `20240115-093000` -> `2024-01-15 09:30:00 UTC`
+pub fn display_created(created: &str) -> String {
+    match (created.get(..8), created.get(9..15)) {
+        (Some(date), Some(time)) => format!(
+            "{}-{}-{} {}:{}:{} UTC",
+            &date[..4],
+            &date[4..6],
+            &date[6..],
+            &time[..2],
+            &time[2..4],
+            &time[4..]
+        ),
+        _ => created.to_string(),
+    }
+}
+
+/// Print the snapshots of the store, oldest first.
+pub fn print_list(store: &SnapshotStore) -> Result<(), AppError> {
+    let snapshots = store.list()?;
+    if snapshots.is_empty() {
+        eprintln!("No snapshot in {:?}.", store.dir);
+    }
+    for snapshot in &snapshots {
+        let kept_text = if store.content_path(snapshot).is_file() {
+            ", text kept"
+        } else {
+            ""
+        };
+        println!(
+            "{}\t{}\t{} files{}",
+            snapshot.name,
+            display_created(&snapshot.created),
+            snapshot.files,
+            kept_text
+        );
+    }
+    Ok(())
+}
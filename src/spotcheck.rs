@@ -0,0 +1,84 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `5%` or `5` -> 5.0, for `--spot-check`.
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("`{}` is not a percentage", value))?;
+    if percent > 0.0 && percent <= 100.0 {
+        Ok(percent)
+    } else {
+        Err(String::from(
+            "the percentage must be above 0 and at most 100",
+        ))
+    }
+}
+
+/// A seed for `--seed`, when none is given.
+pub fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// SplitMix64: small, and the same sample for the same seed everywhere.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// `percent` of `paths` (at least one), picked at random from `seed`. The
+/// paths are sorted first, so the sample does not depend on their order.
+pub fn sample(mut paths: Vec<&str>, percent: f64, seed: u64) -> Vec<&str> {
+    paths.sort_unstable();
+    let count = ((paths.len() as f64 * percent / 100.0).ceil() as usize).min(paths.len());
+    let mut rng = SplitMix64(seed);
+    // The first `count` steps of a Fisher-Yates shuffle.
+    for i in 0..count {
+        let j = i + (rng.next() % (paths.len() - i) as u64) as usize;
+        paths.swap(i, j);
+    }
+    paths.truncate(count);
+    paths
+}
+
+/// Print the pass rate of the sample and what it says about the whole
+/// reference: the expected number of bad files and its 95% upper bound
+/// (Wilson score interval).
+pub fn report(sampled: usize, failed: usize, total: usize, seed: u64) {
+    if sampled == 0 {
+        return;
+    }
+    let n = sampled as f64;
+    let rate = failed as f64 / n;
+    const Z: f64 = 1.96;
+    let upper =
+        (rate + Z * Z / (2.0 * n) + Z * (rate * (1.0 - rate) / n + Z * Z / (4.0 * n * n)).sqrt())
+            / (1.0 + Z * Z / n);
+    eprintln!(
+        "\nSpot check: {} of {} files (seed {}), {} passed ({:.1}%).",
+        sampled,
+        total,
+        seed,
+        sampled.saturating_sub(failed),
+        (1.0 - rate) * 100.0
+    );
+    eprintln!(
+        "Estimated bad files in the whole install: {:.0} (at most {:.0}, {:.2}% of the files, with 95% confidence).",
+        rate * total as f64,
+        (upper * total as f64).ceil(),
+        upper * 100.0
+    );
+    if failed > 0 {
+        eprintln!("Run a full `verify` to find all of them.");
+    }
+}
@@ -0,0 +1,170 @@
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Result as IoResult, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::budget::Coverage;
+use crate::hasher::Algorithm;
+use crate::locale;
+use crate::provenance::Provenance;
+use crate::FileRecord;
+
+/// How long `--submit-stats` waits for the endpoint.
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Throughput of a hashing run, for `--stats` and `--stats-file`.
+pub struct RunStats {
+    pub files: usize,
+    /// Size of all the files, read or not.
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Bytes of the files read by each hashing thread.
+    pub thread_bytes: Vec<u64>,
+    /// Files, and their bytes, whose hashes were reused without reading
+    /// them: from the cache, or from the command before (`--then`).
+    pub reused_files: usize,
+    pub reused_bytes: u64,
+    pub read_errors: usize,
+    /// Set when `--time-budget` cut the run short.
+    pub coverage: Option<Coverage>,
+}
+
+impl RunStats {
+    pub fn new(
+        results: &[FileRecord],
+        thread_bytes: Vec<u64>,
+        (reused_files, reused_bytes): (usize, u64),
+        read_errors: usize,
+        elapsed: Duration,
+    ) -> Self {
+        RunStats {
+            files: results.len(),
+            bytes: results.iter().map(|r| r.2).sum(),
+            elapsed,
+            thread_bytes,
+            reused_files,
+            reused_bytes,
+            read_errors,
+            coverage: None,
+        }
+    }
+
+    /// Bytes actually read from the disk.
+    pub fn bytes_read(&self) -> u64 {
+        self.thread_bytes.iter().sum()
+    }
+
+    /// Aggregate read throughput in MB/s (10^6 bytes, as disk benchmarks
+    /// do); the reused files do not count.
+    pub fn mb_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes_read() as f64 / 1e6 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Print the statistics to the stderr, one `key: value` per line.
+    pub fn print(&self) {
+        eprintln!("\nfiles: {}", locale::number(self.files as u64));
+        eprintln!("bytes: {}", locale::number(self.bytes));
+        eprintln!("bytes read: {}", locale::number(self.bytes_read()));
+        eprintln!(
+            "reused: {} files, {} bytes",
+            locale::number(self.reused_files as u64),
+            locale::number(self.reused_bytes)
+        );
+        eprintln!(
+            "elapsed: {} s",
+            locale::decimal(self.elapsed.as_secs_f64(), 3)
+        );
+        eprintln!("throughput: {} MB/s", locale::decimal(self.mb_per_sec(), 1));
+        for (thread, bytes) in self.thread_bytes.iter().enumerate() {
+            eprintln!("thread {}: {} bytes", thread, locale::number(*bytes));
+        }
+        eprintln!("read errors: {}", locale::number(self.read_errors as u64));
+        if let Some(ref coverage) = self.coverage {
+            eprintln!(
+                "coverage: {} of {} files, {} of {} bytes",
+                locale::number(coverage.files as u64),
+                locale::number(coverage.total_files as u64),
+                locale::number(coverage.bytes),
+                locale::number(coverage.total_bytes)
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut json = json!({
+            "files": self.files,
+            "bytes": self.bytes,
+            "bytes_read": self.bytes_read(),
+            "reused_files": self.reused_files,
+            "reused_bytes": self.reused_bytes,
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "mb_per_sec": self.mb_per_sec(),
+            "thread_bytes": self.thread_bytes,
+            "read_errors": self.read_errors,
+        });
+        if let Some(ref coverage) = self.coverage {
+            json["coverage"] = coverage.to_json();
+        }
+        json
+    }
+
+    /// The statistics sent by `--submit-stats`: counts, sizes and the sim
+    /// build only, never a path or a package name.
+    pub fn anonymized(
+        &self,
+        command: &str,
+        algorithm: Algorithm,
+        provenance: &Provenance,
+    ) -> Value {
+        let attempted = self.files + self.read_errors;
+        json!({
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "command": command,
+            "algorithm": algorithm.name(),
+            "sim_build": provenance.sim_build,
+            "store": provenance.store,
+            "packages": provenance.packages.len(),
+            "files": self.files,
+            "bytes": self.bytes,
+            "read_errors": self.read_errors,
+            "error_rate": if attempted > 0 {
+                self.read_errors as f64 / attempted as f64
+            } else {
+                0.0
+            },
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "threads": self.thread_bytes.len(),
+        })
+    }
+
+    pub fn write(&self, stats_file: &Path) -> IoResult<()> {
+        let mut writer = BufWriter::new(File::create(stats_file)?);
+        serde_json::to_writer_pretty(&mut writer, &self.to_json())?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+/// POST the anonymized statistics to `url` as JSON, printing exactly what is
+/// sent. A failure only warns: the run itself went fine.
+pub fn submit(url: &str, payload: &Value) {
+    eprintln!(
+        "\nSubmitting anonymized statistics to {}:\n{}",
+        url, payload
+    );
+    let agent = ureq::AgentBuilder::new().timeout(SUBMIT_TIMEOUT).build();
+    match agent
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&payload.to_string())
+    {
+        Ok(_) => eprintln!("Statistics submitted."),
+        Err(err) => eprintln!("Warning: the statistics could not be submitted: {}", err),
+    }
+}
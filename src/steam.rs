@@ -0,0 +1,148 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dirs::home_dir;
+
+/// Steam app ID of MSFS 2020.
+const MSFS_APP_ID: &str = "1250410";
+
+/// Steam library folders listed in `steamapps/libraryfolders.vdf` of the
+/// Steam installation at `steam_root`, starting with `steam_root` itself.
+pub fn library_folders(steam_root: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![steam_root.to_path_buf()];
+    let vdf = steam_root.join("steamapps").join("libraryfolders.vdf");
+    let text = fs::read_to_string(vdf).unwrap_or_default();
+    for line in text.lines() {
+        // `"path"		"D:\\SteamLibrary"`, or `"1"		"D:\\SteamLibrary"` in
+        // the format used before 2021.
+        let tokens = quoted_tokens(line);
+        if let [key, value] = tokens.as_slice() {
+            let is_old_format = key.parse::<u32>().is_ok() && value.contains(['\\', '/']);
+            if key.eq_ignore_ascii_case("path") || is_old_format {
+                let library = PathBuf::from(value);
+                if !libraries.contains(&library) {
+                    libraries.push(library);
+                }
+            }
+        }
+    }
+    libraries
+}
+
+fn quoted_tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            continue;
+        }
+        let mut token = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => token.extend(chars.next()),
+                '"' => break,
+                c => token.push(c),
+            }
+        }
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Installation folders of Steam, native and Flatpak on Linux.
+fn steam_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if cfg!(target_os = "windows") {
+        for var in ["ProgramFiles(x86)", "ProgramFiles"] {
+            if let Some(program_files) = env::var_os(var) {
+                roots.push(PathBuf::from(program_files).join("Steam"));
+            }
+        }
+    } else if let Some(home) = home_dir() {
+        roots.push(home.join(".steam").join("steam"));
+        roots.push(home.join(".local").join("share").join("Steam"));
+        roots.push(home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"));
+    }
+    roots.retain(|root| root.is_dir());
+    roots
+}
+
+/// Find `UserCfg.opt` through the Steam library MSFS is installed in: the
+/// library that has its app manifest.
+///
+/// The file is looked for in the install folder of the sim and, for Steam
+/// Proton on Linux, in the Wine prefix of the sim.
+pub fn find_steam_usercfg() -> Option<PathBuf> {
+    steam_roots()
+        .iter()
+        .find_map(|steam_root| usercfg_in_steam(steam_root))
+}
+
+/// `find_steam_usercfg` for the Steam installation at `steam_root`.
+pub fn usercfg_in_steam(steam_root: &Path) -> Option<PathBuf> {
+    for library in library_folders(steam_root) {
+        let steamapps = library.join("steamapps");
+        if !steamapps
+            .join(format!("appmanifest_{}.acf", MSFS_APP_ID))
+            .is_file()
+        {
+            continue;
+        }
+        let install_cfg = steamapps
+            .join("common")
+            .join("MicrosoftFlightSimulator")
+            .join("UserCfg.opt");
+        if install_cfg.is_file() {
+            return Some(install_cfg);
+        }
+        let users_dir = steamapps
+            .join("compatdata")
+            .join(MSFS_APP_ID)
+            .join("pfx")
+            .join("drive_c")
+            .join("users");
+        // Usually `steamuser`, but do not rely on it.
+        for user in fs::read_dir(users_dir).into_iter().flatten().flatten() {
+            let usercfg = user
+                .path()
+                .join("AppData")
+                .join("Roaming")
+                .join("Microsoft Flight Simulator")
+                .join("UserCfg.opt");
+            if usercfg.is_file() {
+                return Some(usercfg);
+            }
+        }
+    }
+    None
+}
+
+/// Translate a Windows path written by the sim (e.g. the
+/// `InstalledPackagesPath` of a `UserCfg.opt` inside a Proton prefix) into
+/// the path of the same file on the host.
+///
+/// Returns `None` when `usercfg` is not inside a Wine/Proton prefix or
+/// `windows_path` does not start with a drive letter.
+pub fn translate_prefix_path(usercfg: &Path, windows_path: &str) -> Option<PathBuf> {
+    let prefix = usercfg
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == "drive_c"))?
+        .parent()?;
+    let bytes = windows_path.as_bytes();
+    if bytes.len() < 2 || bytes[1] != b':' || !bytes[0].is_ascii_alphabetic() {
+        return None;
+    }
+    let drive = windows_path[..1].to_ascii_lowercase();
+    // `dosdevices/c:` links to `drive_c`, and so on for the mapped drives.
+    let mut host_path = prefix.join("dosdevices").join(format!("{}:", drive));
+    if !host_path.exists() && drive == "c" {
+        host_path = prefix.join("drive_c");
+    }
+    for component in windows_path[2..].split(['\\', '/']) {
+        if !component.is_empty() {
+            host_path.push(component);
+        }
+    }
+    Some(host_path)
+}
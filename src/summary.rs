@@ -0,0 +1,106 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use crate::config::CONFIG_PREFIX;
+use crate::hasher::{Algorithm, Digest};
+use crate::locale;
+use crate::FileRecord;
+
+/// Totals of one package, the first component of the relative paths (the
+/// first two under a `--root`, e.g. `@Community/my-livery`).
+pub struct PackageSummary {
+    pub files: u64,
+    pub bytes: u64,
+    /// Hash of the digests of the package files, in path order, so that two
+    /// runs with the same digest have the same content.
+    pub digest: Digest,
+}
+
+/// The package of a relative path: its first component, or its first two
+/// under a `--root`.
+pub fn package_of(path: &str) -> String {
+    let mut components = Path::new(path).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(root)), Some(Component::Normal(name)))
+            if root.to_string_lossy().starts_with('@') && root != CONFIG_PREFIX =>
+        {
+            Path::new(root).join(name).to_string_lossy().to_string()
+        }
+        (Some(Component::Normal(name)), _) => name.to_string_lossy().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Aggregate the (path-sorted) results by package.
+pub fn summarize(results: &[FileRecord], algorithm: Algorithm) -> BTreeMap<String, PackageSummary> {
+    let mut hashers = BTreeMap::new();
+    let mut totals: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for (path, hash, filesize, _, _) in results {
+        let package = package_of(&path.to_string());
+        hashers
+            .entry(package.clone())
+            .or_insert_with(|| algorithm.hasher())
+            .update(hash.as_bytes());
+        let total = totals.entry(package).or_default();
+        total.0 += 1;
+        total.1 += filesize;
+    }
+    totals
+        .into_iter()
+        .map(|(package, (files, bytes))| {
+            let digest = hashers[&package].digest();
+            (
+                package,
+                PackageSummary {
+                    files,
+                    bytes,
+                    digest,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Print the summary table to the stderr, after the hash values.
+pub fn print_summary(summary: &BTreeMap<String, PackageSummary>) {
+    eprintln!("\n{}", format_summary(summary));
+}
+
+/// The summary table, one line per package.
+pub fn format_summary(summary: &BTreeMap<String, PackageSummary>) -> String {
+    let width = summary
+        .keys()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    let mut table = format!(
+        "{:width$}  {:>8}  {:>14}  digest\n",
+        "package", "files", "bytes"
+    );
+    for (package, totals) in summary {
+        table.push_str(&format!(
+            "{:width$}  {:>8}  {:>14}  {}\n",
+            package,
+            locale::number(totals.files),
+            locale::number(totals.bytes),
+            totals.digest
+        ));
+    }
+    table
+}
+
+pub fn summary_json(summary: &BTreeMap<String, PackageSummary>) -> Value {
+    summary
+        .iter()
+        .map(|(package, totals)| {
+            json!({
+                "package": package,
+                "files": totals.files,
+                "bytes": totals.bytes,
+                "digest": totals.digest.to_string(),
+            })
+        })
+        .collect()
+}
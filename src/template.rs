@@ -0,0 +1,92 @@
+use std::fmt::Write;
+
+use crate::summary::package_of;
+use crate::FileRecord;
+
+/// The placeholders of `--template`, as listed in the error of an unknown
+/// one.
+const PLACEHOLDERS: &str = "{hash}, {size}, {path}, {mtime}, {package}";
+
+/// An output line of `--template`, e.g. `{hash}  {path}` for the layout of
+/// `sha256sum` and `xxhsum`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template(Vec<Piece>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Hash,
+    Size,
+    Path,
+    /// The modification time, in seconds since the Unix epoch.
+    Mtime,
+    Package,
+}
+
+/// Parse a `--template`: the placeholders in braces, `{{` and `}}` for the
+/// braces themselves and `\t` for a tab, hard to type in a shell.
+pub fn parse_template(value: &str) -> Result<Template, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("{{") {
+            text.push('{');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("}}") {
+            text.push('}');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\t") {
+            text.push('\t');
+            rest = after;
+        } else if c == '{' {
+            let (name, after) = rest[1..]
+                .split_once('}')
+                .ok_or_else(|| format!("`{}` has a `{{` that is not closed", value))?;
+            let piece = match name {
+                "hash" => Piece::Hash,
+                "size" => Piece::Size,
+                "path" => Piece::Path,
+                "mtime" => Piece::Mtime,
+                "package" => Piece::Package,
+                _ => {
+                    return Err(format!(
+                        "unknown placeholder `{{{}}}`, use {}",
+                        name, PLACEHOLDERS
+                    ))
+                }
+            };
+            if !text.is_empty() {
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+            }
+            pieces.push(piece);
+            rest = after;
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(Template(pieces))
+}
+
+impl Template {
+    /// The line of `record`, without its line ending.
+    pub fn render(&self, record: &FileRecord) -> String {
+        let (path, hash, filesize, mtime, _) = record;
+        let mut line = String::new();
+        for piece in &self.0 {
+            let _ = match piece {
+                Piece::Text(text) => write!(line, "{}", text),
+                Piece::Hash => write!(line, "{}", hash),
+                Piece::Size => write!(line, "{}", filesize),
+                Piece::Path => write!(line, "{}", path),
+                Piece::Mtime => write!(line, "{}", mtime / 1_000_000_000),
+                Piece::Package => write!(line, "{}", package_of(&path.to_string())),
+            };
+        }
+        line
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error as IoError, Read, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+
+use similar::TextDiff;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::longpath;
+
+/// Extensions of the files whose content `snapshot save --keep-text` keeps:
+/// the settings of the packages (`aircraft.cfg`, `panel.cfg`, ...), small
+/// enough to be read as text.
+const TEXT_EXTENSIONS: [&str; 5] = ["cfg", "xml", "json", "ini", "flt"];
+
+pub fn is_text(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            TEXT_EXTENSIONS
+                .iter()
+                .any(|text| extension.eq_ignore_ascii_case(text))
+        })
+}
+
+/// The name of `path` inside the content archive, with `/` separators.
+fn entry_name(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Write the text files among `files` (`(path, file on disk, size)`) of at
+/// most `max_size` bytes (which leaves out e.g. the generated
+/// `layout.json`) into the content archive `archive`, compressed. Returns
+/// the number of files kept.
+pub fn save_content(
+    archive: &Path,
+    files: impl Iterator<Item = (String, PathBuf, u64)>,
+    max_size: u64,
+) -> IoResult<usize> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(archive)?));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut kept = 0;
+    for (path, file, size) in files {
+        if !is_text(&path) || size > max_size {
+            continue;
+        }
+        let content = match fs::read(longpath::extended(&file)) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("Warning: unable to keep the content of {:?}: {}", file, err);
+                continue;
+            }
+        };
+        zip.start_file(entry_name(&path), options)
+            .map_err(IoError::from)?;
+        zip.write_all(&content)?;
+        kept += 1;
+    }
+    zip.finish().map_err(IoError::from)?.flush()?;
+    Ok(kept)
+}
+
+/// The text files kept by `snapshot save --keep-text`.
+pub struct SavedContent {
+    archive: ZipArchive<BufReader<File>>,
+}
+
+impl SavedContent {
+    pub fn open(archive: &Path) -> IoResult<Self> {
+        let archive =
+            ZipArchive::new(BufReader::new(File::open(archive)?)).map_err(IoError::from)?;
+        Ok(SavedContent { archive })
+    }
+
+    /// The kept content of `path`, if it was kept.
+    pub fn get(&mut self, path: &str) -> Option<String> {
+        let mut file = self.archive.by_name(&entry_name(path)).ok()?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).ok()?;
+        Some(String::from_utf8_lossy(&content).into_owned())
+    }
+}
+
+/// Print what changed in the text file `path` since `old` was kept, as a
+/// unified diff.
+pub fn print_diff(path: &str, old: &str, new: &str, snapshot: &str) {
+    let diff = TextDiff::from_lines(old, new);
+    print!(
+        "{}",
+        diff.unified_diff()
+            .context_radius(3)
+            .header(&format!("{} ({})", path, snapshot), path)
+    );
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::Path;
+
+/// The settings of a `UserCfg.opt` file.
+///
+/// The format is one `Key Value` per line, the value being a bare word or a
+/// quoted string, with `{Section` ... `}` blocks that may be nested:
+///
+/// ```text
+/// Version 62
+/// {Graphics
+///     Version 1.1
+/// }
+/// InstalledPackagesPath "D:\MSFS"
+/// ```
+pub struct UserCfg {
+    /// `(key, value)` in file order. The keys of the values inside sections
+    /// are prefixed with the section names, e.g. `Graphics.Version`.
+    entries: Vec<(String, String)>,
+}
+
+impl UserCfg {
+    /// Read and parse `UserCfg.opt`, whatever its encoding.
+    ///
+    /// The sim writes UTF-8, but the file has been seen in UTF-16-LE
+    /// (rewritten by some tools or editors) and with a byte order mark.
+    pub fn read(usercfg: &Path) -> IoResult<Self> {
+        Ok(Self::parse(&decode_text(&fs::read(usercfg)?)))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut sections: Vec<String> = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(section) = line.strip_prefix('{') {
+                sections.push(section.trim().to_string());
+                continue;
+            }
+            if line.starts_with('}') {
+                sections.pop();
+                continue;
+            }
+            let (key, value) = match line.split_once(char::is_whitespace) {
+                Some((key, value)) => (key, parse_value(value.trim())),
+                None if !line.is_empty() => (line, String::new()),
+                None => continue,
+            };
+            let key = sections
+                .iter()
+                .map(String::as_str)
+                .chain([key])
+                .collect::<Vec<_>>()
+                .join(".");
+            entries.push((key, value));
+        }
+        UserCfg { entries }
+    }
+
+    /// The value of `key` (`Section.Key` inside a section), the keys being
+    /// compared without regard to case.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Where the sim packages are installed.
+    pub fn installed_packages_path(&self) -> Option<&str> {
+        self.get("InstalledPackagesPath")
+            .filter(|path| !path.is_empty())
+    }
+}
+
+/// A quoted value goes from the first quote to the last one, so that a path
+/// containing quotes survives; `\"` inside it is an escaped quote. The other
+/// backslashes are kept as they are: they are the separators of Windows
+/// paths, a trailing one included (`"D:\MSFS\"`).
+fn parse_value(value: &str) -> String {
+    let inner = value
+        .strip_prefix('"')
+        .and_then(|rest| rest.rfind('"').map(|end| &rest[..end]));
+    match inner {
+        Some(inner) => inner.replace("\\\"", "\""),
+        None => value.to_string(),
+    }
+}
+
+/// Decode text by its byte order mark, UTF-8 without one. UTF-16-LE without
+/// a BOM is told by its zero high bytes: the file starts with ASCII.
+fn decode_text(data: &[u8]) -> String {
+    if let Some(utf8) = data.strip_prefix(b"\xEF\xBB\xBF") {
+        return String::from_utf8_lossy(utf8).to_string();
+    }
+    if let Some(utf16) = data.strip_prefix(b"\xFF\xFE") {
+        return decode_utf16(utf16, u16::from_le_bytes);
+    }
+    if let Some(utf16) = data.strip_prefix(b"\xFE\xFF") {
+        return decode_utf16(utf16, u16::from_be_bytes);
+    }
+    if data.len() >= 2 && data[0] != 0 && data[1] == 0 {
+        return decode_utf16(data, u16::from_le_bytes);
+    }
+    String::from_utf8_lossy(data).to_string()
+}
+
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
@@ -0,0 +1,547 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::hasher::Digest;
+use crate::intern::{self, RecordPath};
+use crate::locale;
+use crate::manifest::Manifest;
+use crate::FileRecord;
+
+/// Folders the packages are installed in, below `Official`, by store type.
+const STORE_DIRS: [&str; 2] = ["OneStore", "Steam"];
+
+type Entries = HashMap<RecordPath, (Digest, Option<u64>)>;
+
+/// `(path, STATUS)` of the files reported by `verify` and `diff`, sorted by
+/// path, for the report pages.
+pub type Findings = Vec<(String, &'static str)>;
+
+/// Make the results of another store type comparable with the local ones.
+///
+/// The store folder (`OneStore` / `Steam`) is dropped from every path, then
+/// the packages that only one side has are left out: they come with one
+/// store type only and would all be reported as missing or extra.
+pub fn cross_store(
+    results: &[FileRecord],
+    reference: &Entries,
+    baseline: Option<&Entries>,
+) -> (Vec<FileRecord>, Entries, Option<Entries>) {
+    let results: Vec<FileRecord> = results
+        .iter()
+        .map(|(path, hash, filesize, mtime, extra)| {
+            (
+                intern::path(strip_store(&path.to_string())),
+                hash.clone(),
+                *filesize,
+                *mtime,
+                extra.clone(),
+            )
+        })
+        .collect();
+    let strip_entries = |entries: &Entries| -> Entries {
+        entries
+            .iter()
+            .map(|(path, entry)| (intern::path(strip_store(&path.to_string())), entry.clone()))
+            .collect()
+    };
+    let reference = strip_entries(reference);
+    let baseline = baseline.map(strip_entries);
+
+    let local_packages: BTreeSet<&str> = results.iter().map(|r| r.0.first_component()).collect();
+    let reference_packages: BTreeSet<&str> =
+        reference.keys().map(RecordPath::first_component).collect();
+    let exclusive: BTreeSet<String> = local_packages
+        .symmetric_difference(&reference_packages)
+        .map(|package| package.to_string())
+        .collect();
+    if !exclusive.is_empty() {
+        eprintln!(
+            "Leaving out {} package(s) that only one store type has:",
+            exclusive.len()
+        );
+        for package in &exclusive {
+            eprintln!("    {}", package);
+        }
+        eprintln!();
+    }
+    let shared = |path: &RecordPath| !exclusive.contains(path.first_component());
+    let results = results.into_iter().filter(|r| shared(&r.0)).collect();
+    let reference = reference
+        .into_iter()
+        .filter(|(path, _)| shared(path))
+        .collect();
+    let baseline = baseline.map(|baseline| {
+        baseline
+            .into_iter()
+            .filter(|(path, _)| shared(path))
+            .collect()
+    });
+    (results, reference, baseline)
+}
+
+fn strip_store(path: &str) -> &str {
+    match path.split_once(['\\', '/']) {
+        Some((first, rest))
+            if STORE_DIRS
+                .iter()
+                .any(|store| first.eq_ignore_ascii_case(store)) =>
+        {
+            rest
+        }
+        _ => path,
+    }
+}
+
+/// For the files that differ from the reference, print the byte ranges whose
+/// chunk hashes differ from those of the reference as
+/// `RANGE<TAB>first-last<TAB>path` lines, adjacent chunks merged.
+pub fn report_chunks(
+    results: &[FileRecord],
+    reference: &Entries,
+    reference_chunks: &HashMap<String, BTreeMap<usize, Digest>>,
+    chunk_size: u64,
+) {
+    for (path, hash, filesize, _, extra) in results {
+        let (expected, expected_size) =
+            match (reference.get(path), reference_chunks.get(&path.to_string())) {
+                (Some((expected_hash, expected_size)), Some(expected)) if expected_hash != hash => {
+                    (expected, expected_size.unwrap_or(0))
+                }
+                _ => continue,
+            };
+        if extra.chunks.is_empty() {
+            continue;
+        }
+        // The ranges of the longer of the two versions.
+        let size = (*filesize).max(expected_size);
+        for (first, last) in differing_ranges(&extra.chunks, expected, size, chunk_size) {
+            println!("RANGE\t{}-{}\t{}", first, last, path);
+        }
+    }
+}
+
+/// The byte ranges, `(first, last)`, of a file of `size` bytes whose chunk
+/// hashes differ between `chunks` and `expected`, adjacent chunks merged.
+fn differing_ranges(
+    chunks: &[Digest],
+    expected: &BTreeMap<usize, Digest>,
+    size: u64,
+    chunk_size: u64,
+) -> Vec<(u64, u64)> {
+    // The chunk lines of the reference past the end of both versions name
+    // no bytes.
+    let count = chunks
+        .len()
+        .max(expected.keys().max().map_or(0, |last| last + 1))
+        .min(usize::try_from(size.div_ceil(chunk_size)).unwrap_or(usize::MAX));
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for index in 0..count {
+        if chunks.get(index) == expected.get(&index) {
+            continue;
+        }
+        let start = index as u64 * chunk_size;
+        let end = start.saturating_add(chunk_size).min(size) - 1;
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == start => *last = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    ranges
+}
+
+/// Report the directories whose number of files differs from the reference,
+/// before the long hashing starts. Returns the number of such directories.
+pub fn compare_dir_counts(
+    expected: &BTreeMap<String, usize>,
+    actual: &BTreeMap<String, usize>,
+) -> usize {
+    let mut changed = 0;
+    let dirs: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+    for dir in dirs {
+        let expected = expected.get(dir).copied().unwrap_or(0);
+        let actual = actual.get(dir).copied().unwrap_or(0);
+        if expected != actual {
+            if changed == 0 {
+                eprintln!("Directories whose file count differs from the reference:");
+            }
+            eprintln!("    {}: {} -> {} files", dir, expected, actual);
+            changed += 1;
+        }
+    }
+    if changed > 0 {
+        eprintln!();
+    }
+    changed
+}
+
+/// Compare the files on disk with a reference manifest and, optionally, the
+/// manifest of the previous sim version.
+///
+/// With a baseline, a file that differs from the reference is classified as
+/// `OUTDATED` when it still matches the baseline (the update did not reach it)
+/// and as `CORRUPTED` when it matches neither. Files that differ from the
+/// baseline but match the reference are reported as `UPDATED`.
+///
+/// A file of the reference missing on disk while an extra file has its
+/// content is reported once, as `MOVED` (see `find_moves`).
+///
+/// Prints one `STATUS<TAB>path` line per finding to the stdout and returns
+/// the number of problems (corrupted, outdated, missing and moved files),
+/// with the findings.
+pub fn verify(
+    results: &[FileRecord],
+    reference: &Entries,
+    baseline: Option<&Entries>,
+) -> (usize, Findings) {
+    let mut findings = BTreeMap::new();
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (path, hash, filesize, _, _) in results {
+        let matches = |expected: &(Digest, Option<u64>)| {
+            expected.0 == *hash && expected.1.is_none_or(|size| size == *filesize)
+        };
+        let in_baseline = baseline.map(|baseline| baseline.get(path));
+        let status = match (reference.get(path), in_baseline) {
+            (Some(expected), None) if matches(expected) => None,
+            (Some(expected), Some(old)) if matches(expected) => match old {
+                Some(old) if matches(old) => None,
+                _ => Some("UPDATED"),
+            },
+            (Some(_), None) => Some("MISMATCH"),
+            (Some(_), Some(Some(old))) if matches(old) => Some("OUTDATED"),
+            (Some(_), Some(_)) => Some("CORRUPTED"),
+            (None, Some(Some(_))) => Some("OBSOLETE"),
+            (None, _) => Some("EXTRA"),
+        };
+        if let Some(status) = status {
+            findings.insert(path, status);
+        }
+    }
+    let on_disk: HashMap<&RecordPath, (&Digest, Option<u64>)> = results
+        .iter()
+        .map(|(path, hash, filesize, _, _)| (path, (hash, Some(*filesize))))
+        .collect();
+    for path in reference.keys() {
+        if !on_disk.contains_key(path) {
+            findings.insert(path, "MISSING");
+        }
+    }
+    let findings = find_moves(
+        findings,
+        ("MISSING", "EXTRA"),
+        |path| reference.get(path).map(|(hash, size)| (hash, *size)),
+        |path| on_disk.get(path).copied(),
+    );
+
+    for (path, status) in &findings {
+        println!("{}\t{}", status, path);
+        *counts.entry(status).or_default() += 1;
+    }
+    let matched = results.len()
+        - findings
+            .iter()
+            .filter(|(_, status)| *status != "MISSING" && *status != "UPDATED")
+            .count();
+    eprintln!(
+        "\nVerified {} files, {} as expected.",
+        locale::number(results.len() as u64),
+        locale::number(matched as u64)
+    );
+    print_counts(&counts);
+    let problems = ["MISMATCH", "CORRUPTED", "OUTDATED", "MISSING", "MOVED"]
+        .iter()
+        .map(|status| counts.get(status).copied().unwrap_or(0))
+        .sum();
+    (problems, findings)
+}
+
+/// Replace the pairs of a `gone` finding (only in the old files) and an
+/// `appeared` one (only in the new files) that have the same hash and size
+/// with one `MOVED` finding, `old -> new`: a package restructured by an
+/// update, or a file put in the wrong folder, rather than two problems. A
+/// content that several such files share on a side is left alone, as which
+/// went where cannot be told.
+fn find_moves<'a>(
+    findings: BTreeMap<&RecordPath, &'static str>,
+    (gone, appeared): (&str, &str),
+    old_entry: impl Fn(&RecordPath) -> Option<(&'a Digest, Option<u64>)>,
+    new_entry: impl Fn(&RecordPath) -> Option<(&'a Digest, Option<u64>)>,
+) -> Findings {
+    // Hash -> the paths with that content, on each side.
+    let mut candidates: HashMap<&Digest, (Vec<&RecordPath>, Vec<&RecordPath>)> = HashMap::new();
+    for (&path, &status) in &findings {
+        if status == gone {
+            if let Some((hash, _)) = old_entry(path) {
+                candidates.entry(hash).or_default().0.push(path);
+            }
+        } else if status == appeared {
+            if let Some((hash, _)) = new_entry(path) {
+                candidates.entry(hash).or_default().1.push(path);
+            }
+        }
+    }
+    let mut moves: BTreeMap<&RecordPath, &RecordPath> = BTreeMap::new();
+    for (old_paths, new_paths) in candidates.values() {
+        if let ([old], [new]) = (old_paths.as_slice(), new_paths.as_slice()) {
+            let sizes = (
+                old_entry(old).and_then(|e| e.1),
+                new_entry(new).and_then(|e| e.1),
+            );
+            if let (Some(old_size), Some(new_size)) = sizes {
+                if old_size != new_size {
+                    continue;
+                }
+            }
+            moves.insert(old, new);
+        }
+    }
+    let moved: HashSet<&RecordPath> = moves.values().copied().collect();
+    findings
+        .into_iter()
+        .filter(|(path, _)| !moved.contains(path))
+        .map(|(path, status)| match moves.get(path) {
+            Some(new) => (format!("{} -> {}", path, new), "MOVED"),
+            None => (path.to_string(), status),
+        })
+        .collect()
+}
+
+/// Compare two result files, printing one `STATUS<TAB>path` line per file that
+/// differs (`CHANGED`, `ADDED`, `REMOVED` or `MOVED`), and return these
+/// files.
+pub fn diff(old: &Manifest, new: &Manifest) -> Findings {
+    let mut findings = BTreeMap::new();
+    for (path, (hash, filesize)) in &new.entries {
+        match old.entries.get(path) {
+            Some((old_hash, old_size)) if old_hash == hash && old_size == filesize => {}
+            Some(_) => {
+                findings.insert(path, "CHANGED");
+            }
+            None => {
+                findings.insert(path, "ADDED");
+            }
+        }
+    }
+    for path in old.entries.keys() {
+        if !new.entries.contains_key(path) {
+            findings.insert(path, "REMOVED");
+        }
+    }
+    let findings = find_moves(
+        findings,
+        ("REMOVED", "ADDED"),
+        |path| old.entries.get(path).map(|(hash, size)| (hash, *size)),
+        |path| new.entries.get(path).map(|(hash, size)| (hash, *size)),
+    );
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (path, status) in &findings {
+        println!("{}\t{}", status, path);
+        *counts.entry(status).or_default() += 1;
+    }
+    let unchanged = new.entries.len()
+        - counts.get("CHANGED").unwrap_or(&0)
+        - counts.get("ADDED").unwrap_or(&0)
+        - counts.get("MOVED").unwrap_or(&0);
+    eprintln!(
+        "\nCompared {} files with {} files, {} unchanged.",
+        locale::number(new.entries.len() as u64),
+        locale::number(old.entries.len() as u64),
+        locale::number(unchanged as u64)
+    );
+    print_counts(&counts);
+    findings
+}
+
+/// List the files of two trees (`compare`) that differ: `DIFFERENT` (same
+/// size, other content), `SIZE` (other size), `ONLY_A` and `ONLY_B`. Returns
+/// the number of such files.
+pub fn compare(a: &Manifest, b: &Manifest) -> usize {
+    let mut findings = BTreeMap::new();
+    for (path, (hash, filesize)) in &a.entries {
+        match b.entries.get(path) {
+            Some((_, b_size)) if b_size != filesize => {
+                findings.insert(path, "SIZE");
+            }
+            Some((b_hash, _)) if b_hash != hash => {
+                findings.insert(path, "DIFFERENT");
+            }
+            Some(_) => {}
+            None => {
+                findings.insert(path, "ONLY_A");
+            }
+        }
+    }
+    for path in b.entries.keys() {
+        if !a.entries.contains_key(path) {
+            findings.insert(path, "ONLY_B");
+        }
+    }
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (path, status) in &findings {
+        println!("{}\t{}", status, path);
+        *counts.entry(status).or_default() += 1;
+    }
+    let identical = a.entries.len()
+        - counts.get("DIFFERENT").unwrap_or(&0)
+        - counts.get("SIZE").unwrap_or(&0)
+        - counts.get("ONLY_A").unwrap_or(&0);
+    eprintln!(
+        "\nCompared {} files with {} files, {} identical.",
+        locale::number(a.entries.len() as u64),
+        locale::number(b.entries.len() as u64),
+        locale::number(identical as u64)
+    );
+    print_counts(&counts);
+    findings.len()
+}
+
+/// The number of files of each status, under the totals.
+fn print_counts(counts: &BTreeMap<&str, usize>) {
+    for (status, count) in counts {
+        eprintln!("{:>10}: {}", status, locale::number(*count as u64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::ExtraHashes;
+
+    fn digest(byte: u8) -> Digest {
+        Digest::from(u128::from(byte))
+    }
+
+    fn record(path: &str, byte: u8, size: u64) -> FileRecord {
+        (
+            intern::path(path),
+            digest(byte),
+            size,
+            0,
+            ExtraHashes::default(),
+        )
+    }
+
+    fn entries(list: &[(&str, u8, u64)]) -> Entries {
+        list.iter()
+            .map(|&(path, byte, size)| (intern::path(path), (digest(byte), Some(size))))
+            .collect()
+    }
+
+    fn status<'a>(findings: &'a Findings, path: &str) -> Option<&'a str> {
+        findings
+            .iter()
+            .find(|(finding, _)| finding == path)
+            .map(|(_, status)| *status)
+    }
+
+    #[test]
+    fn three_way() {
+        let results = [
+            record("same", 1, 1),
+            record("updated", 2, 1),
+            record("outdated", 1, 1),
+            record("corrupted", 9, 1),
+            record("added-by-update", 4, 1),
+        ];
+        let baseline = entries(&[
+            ("same", 1, 1),
+            ("updated", 1, 1),
+            ("outdated", 1, 1),
+            ("corrupted", 1, 1),
+        ]);
+        let reference = entries(&[
+            ("same", 1, 1),
+            ("updated", 2, 1),
+            ("outdated", 2, 1),
+            ("corrupted", 2, 1),
+            ("added-by-update", 4, 1),
+        ]);
+        let (problems, findings) = verify(&results, &reference, Some(&baseline));
+        assert_eq!(status(&findings, "same"), None);
+        assert_eq!(status(&findings, "updated"), Some("UPDATED"));
+        // Not in the baseline: new with the update, as the reference says.
+        assert_eq!(status(&findings, "added-by-update"), Some("UPDATED"));
+        assert_eq!(status(&findings, "outdated"), Some("OUTDATED"));
+        assert_eq!(status(&findings, "corrupted"), Some("CORRUPTED"));
+        assert_eq!(problems, 2);
+    }
+
+    #[test]
+    fn without_a_baseline() {
+        let results = [
+            record("same", 1, 1),
+            record("changed", 9, 1),
+            record("size", 1, 2),
+        ];
+        let reference = entries(&[("same", 1, 1), ("changed", 1, 1), ("size", 1, 1)]);
+        let (problems, findings) = verify(&results, &reference, None);
+        assert_eq!(status(&findings, "same"), None);
+        assert_eq!(status(&findings, "changed"), Some("MISMATCH"));
+        assert_eq!(status(&findings, "size"), Some("MISMATCH"));
+        assert_eq!(problems, 2);
+    }
+
+    #[test]
+    fn missing_extra_and_obsolete() {
+        let results = [record("extra", 5, 1), record("obsolete", 6, 1)];
+        let baseline = entries(&[("obsolete", 6, 1)]);
+        let reference = entries(&[("missing", 7, 1)]);
+        let (problems, findings) = verify(&results, &reference, Some(&baseline));
+        assert_eq!(status(&findings, "missing"), Some("MISSING"));
+        assert_eq!(status(&findings, "extra"), Some("EXTRA"));
+        assert_eq!(status(&findings, "obsolete"), Some("OBSOLETE"));
+        // Only the missing file is a problem.
+        assert_eq!(problems, 1);
+    }
+
+    #[test]
+    fn moved() {
+        let results = [
+            record("new/place", 3, 10),
+            record("twin-a", 4, 1),
+            record("twin-b", 4, 1),
+        ];
+        let reference = entries(&[("old/place", 3, 10), ("gone", 4, 1)]);
+        let (problems, findings) = verify(&results, &reference, None);
+        assert_eq!(status(&findings, "old/place -> new/place"), Some("MOVED"));
+        // Two files with the content of the missing one: which moved where
+        // cannot be told.
+        assert_eq!(status(&findings, "gone"), Some("MISSING"));
+        assert_eq!(status(&findings, "twin-a"), Some("EXTRA"));
+        assert_eq!(status(&findings, "twin-b"), Some("EXTRA"));
+        assert_eq!(problems, 2);
+    }
+
+    fn chunks(bytes: &[u8]) -> Vec<Digest> {
+        bytes.iter().map(|&byte| digest(byte)).collect()
+    }
+
+    #[test]
+    fn adjacent_differing_chunks_are_merged() {
+        let expected = chunks(&[1, 2, 3, 4, 5]).into_iter().enumerate().collect();
+        let ranges = differing_ranges(&chunks(&[1, 9, 9, 4, 9]), &expected, 48, 10);
+        assert_eq!(ranges, [(10, 29), (40, 47)]);
+    }
+
+    #[test]
+    fn chunks_of_one_side_only_differ() {
+        // The file grew by one chunk and a bit.
+        let expected = chunks(&[1, 2]).into_iter().enumerate().collect();
+        let ranges = differing_ranges(&chunks(&[1, 2, 3, 4]), &expected, 35, 10);
+        assert_eq!(ranges, [(20, 34)]);
+    }
+
+    #[test]
+    fn chunks_past_the_end_are_ignored() {
+        let expected = [(0, digest(1)), (usize::MAX - 1, digest(2))].into();
+        let ranges = differing_ranges(&chunks(&[1]), &expected, 10, 10);
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn not_moved_when_the_size_differs() {
+        let results = [record("new", 3, 10)];
+        let reference = entries(&[("old", 3, 11)]);
+        let (_, findings) = verify(&results, &reference, None);
+        assert_eq!(status(&findings, "old"), Some("MISSING"));
+        assert_eq!(status(&findings, "new"), Some("EXTRA"));
+    }
+}
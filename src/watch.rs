@@ -0,0 +1,178 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{Error as IoError, Result as IoResult};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+use crate::cache::HashCache;
+use crate::error::AppError;
+use crate::intern::{self, RecordPath};
+use crate::{get_buffer_size, hash_file, interrupt, with_retries};
+use crate::{FileRecord, HashOptions};
+
+/// An installer writes a file in many pieces: its files are hashed once
+/// nothing changed for this long.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+/// How often Ctrl+C is checked while waiting for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `--watch`: once the packages are hashed, hash again the files that change
+/// in `packages_dir`, added or removed, until Ctrl+C, and `write` the results
+/// after each batch of changes to `outpath`, whose own changes are ignored.
+/// `results` holds the real paths, as hashed.
+pub fn watch(
+    packages_dir: &Path,
+    outpath: &Path,
+    options: &HashOptions,
+    results: &[FileRecord],
+    write: impl Fn(&[FileRecord]) -> IoResult<()>,
+) -> Result<(), AppError> {
+    let unwatchable = |err: notify::Error| {
+        AppError::PackagesDirMissing(packages_dir.to_path_buf(), IoError::other(err))
+    };
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(unwatchable)?;
+    watcher
+        .watch(packages_dir, RecursiveMode::Recursive)
+        .map_err(unwatchable)?;
+    // The events may name the files under the canonical directory.
+    let canonical_dir = fs::canonicalize(packages_dir).ok();
+    let algorithms = options.algorithms();
+    let mut buffer = vec![0u8; get_buffer_size(1, options)?];
+    let mut records: BTreeMap<RecordPath, FileRecord> = results
+        .iter()
+        .map(|record| (record.0.clone(), record.clone()))
+        .collect();
+    let mut pending = HashSet::new();
+    let mut last_change = Instant::now();
+    let (mut changed, mut added, mut removed) = (0, 0, 0);
+    eprintln!(
+        "Watching {:?} for changes, press Ctrl+C to stop.",
+        packages_dir
+    );
+    while !interrupt::interrupted() {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if !matches!(event.kind, EventKind::Access(_)) => {
+                let paths: Vec<PathBuf> = event
+                    .paths
+                    .into_iter()
+                    .filter(|path| !same_file(path, outpath))
+                    .collect();
+                if !paths.is_empty() {
+                    pending.extend(paths);
+                    last_change = Instant::now();
+                }
+                continue;
+            }
+            Ok(Ok(_)) => continue,
+            Ok(Err(err)) => eprintln!("Warning: {}", err),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if pending.is_empty() || last_change.elapsed() < SETTLE_TIME {
+            continue;
+        }
+        let mut files = Vec::new();
+        for path in pending.drain() {
+            let r_path = match path.strip_prefix(packages_dir).or_else(|err| {
+                canonical_dir
+                    .as_deref()
+                    .map_or(Err(err), |dir| path.strip_prefix(dir))
+            }) {
+                Ok(r_path) if !r_path.as_os_str().is_empty() => r_path.to_path_buf(),
+                _ => continue,
+            };
+            if path.is_dir() {
+                // A directory moved or copied in brings its files.
+                files.extend(
+                    WalkDir::new(&path)
+                        .follow_links(options.follow_links)
+                        .into_iter()
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| entry.file_type().is_file())
+                        .filter_map(|entry| {
+                            let below = entry.path().strip_prefix(&path).ok()?;
+                            Some((r_path.join(below), entry.into_path()))
+                        }),
+                );
+            } else if path.is_file() {
+                files.push((r_path, path));
+            } else {
+                // Gone, with the files below it when it was a directory.
+                let path_string = r_path.to_string_lossy().to_string();
+                let below = format!("{}{}", path_string, MAIN_SEPARATOR);
+                let gone_path = intern::path(&path_string);
+                records.retain(|key, _| {
+                    let gone = *key == gone_path || key.starts_with(&below);
+                    if gone {
+                        eprintln!("REMOVED  {}", key);
+                        removed += 1;
+                    }
+                    !gone
+                });
+            }
+        }
+        files.sort_unstable();
+        files.dedup();
+        for (r_path, file) in files {
+            if !options.filter.is_match(&r_path) {
+                continue;
+            }
+            let path_string = r_path.to_string_lossy().to_string();
+            let hashed = with_retries(options.retries, || {
+                hash_file(
+                    options,
+                    &file,
+                    &path_string,
+                    &algorithms,
+                    &mut buffer[..],
+                    &HashCache::empty(),
+                )
+            });
+            match hashed {
+                Ok(Some((hash, filesize, mtime, extra, _))) => {
+                    let record = (intern::path(&path_string), hash, filesize, mtime, extra);
+                    match records.insert(record.0.clone(), record.clone()) {
+                        None => {
+                            eprintln!("ADDED    {}", path_string);
+                            added += 1;
+                        }
+                        Some(previous) if previous.1 != record.1 || previous.2 != record.2 => {
+                            eprintln!("CHANGED  {}", path_string);
+                            changed += 1;
+                        }
+                        // Touched, or written again as it was.
+                        Some(_) => {}
+                    }
+                }
+                Ok(None) => {}
+                // Hashed again with the next change.
+                Err(err) => eprintln!("Fail to read file {} {}", file.to_string_lossy(), err),
+            }
+        }
+        let results: Vec<FileRecord> = records.values().cloned().collect();
+        if let Err(err) = write(&results) {
+            eprintln!("Warning: unable to update the output file: {}", err);
+        }
+        // Only the directories of the next changes are interned again.
+        intern::release();
+    }
+    eprintln!(
+        "\nStopped watching: {} changed, {} added, {} removed.",
+        changed, added, removed
+    );
+    Ok(())
+}
+
+/// Whether `a` and `b` name the same file, which may not exist any more.
+fn same_file(a: &Path, b: &Path) -> bool {
+    let dir = |path: &Path| match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::canonicalize(dir).ok(),
+        _ => env::current_dir().ok(),
+    };
+    a.file_name() == b.file_name() && dir(a) == dir(b)
+}